@@ -0,0 +1,762 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use regex::Regex;
+use ropey::{Rope, RopeSlice};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    ops::Range,
+    path::Path,
+    rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+const MAX_EDITS: usize = 128;
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(1);
+fn next_revision() -> u64 {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
+}
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+    pub fn label(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+    pub fn toggled(self) -> Self {
+        match self {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextEdit {
+    pub base: u64,
+    pub start_byte: usize,
+    pub old_end_byte: usize,
+    pub new_end_byte: usize,
+    pub start: (usize, usize),
+    pub old_end: (usize, usize),
+    pub new_end: (usize, usize),
+}
+impl TextEdit {
+    pub fn lines(&self) -> (usize, usize, usize) {
+        let partial = usize::from(self.start.0 != 0 || self.old_end.0 != 0 || self.new_end.0 != 0);
+        (self.start.1, self.old_end.1 - self.start.1 + partial, self.new_end.1 - self.start.1 + partial)
+    }
+}
+#[derive(Clone)]
+pub struct TextBuffer {
+    rope: Rope,
+    revision: u64,
+    edits: Vec<TextEdit>,
+    text_cache: RefCell<Option<(u64, Rc<str>)>>,
+    pub encoding: &'static Encoding,
+    pub line_ending: LineEnding,
+    pub bom: bool,
+    pub final_newline: bool,
+    pub mixed_line_endings: bool,
+    pub decode_errors: bool,
+}
+impl TextBuffer {
+    pub fn new() -> Self {
+        Self::from_text("")
+    }
+    pub fn from_text(text: &str) -> Self {
+        Self {
+            rope: Rope::from_str(text),
+            revision: next_revision(),
+            edits: Vec::new(),
+            text_cache: RefCell::new(None),
+            encoding: UTF_8,
+            line_ending: LineEnding::Lf,
+            bom: false,
+            final_newline: true,
+            mixed_line_endings: false,
+            decode_errors: false,
+        }
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        match Encoding::for_bom(bytes) {
+            Some((encoding, bom_len)) => Self::decode(&bytes[bom_len..], encoding, true),
+            None => Self::decode(bytes, detect_encoding(bytes), false),
+        }
+    }
+    pub fn from_bytes_with_encoding(bytes: &[u8], encoding: &'static Encoding) -> Self {
+        match Encoding::for_bom(bytes) {
+            Some((bom_encoding, bom_len)) if bom_encoding == encoding => {
+                Self::decode(&bytes[bom_len..], encoding, true)
+            }
+            _ => Self::decode(bytes, encoding, false),
+        }
+    }
+    fn decode(bytes: &[u8], encoding: &'static Encoding, bom: bool) -> Self {
+        let (text, decode_errors) = encoding.decode_without_bom_handling(bytes);
+        let mut buffer = Self::from_file_text(&text);
+        buffer.encoding = encoding;
+        buffer.bom = bom;
+        buffer.decode_errors = decode_errors;
+        buffer
+    }
+    fn from_file_text(text: &str) -> Self {
+        let crlf_count = text.matches("\r\n").count();
+        let lf_count = text.matches('\n').count();
+        let line_ending = if crlf_count > 0 && crlf_count * 2 >= lf_count {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        };
+        let mixed_line_endings = crlf_count > 0 && crlf_count < lf_count;
+        let text = if crlf_count > 0 && !mixed_line_endings {
+            Cow::Owned(text.replace("\r\n", "\n"))
+        } else {
+            Cow::Borrowed(text)
+        };
+        let (text, final_newline) = match text.strip_suffix('\n') {
+            Some(text) => (text, true),
+            None => (&*text, false),
+        };
+        Self {
+            rope: Rope::from_str(text),
+            revision: next_revision(),
+            edits: Vec::new(),
+            text_cache: RefCell::new(None),
+            encoding: UTF_8,
+            line_ending,
+            bom: false,
+            final_newline,
+            mixed_line_endings,
+            decode_errors: false,
+        }
+    }
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        if encoding == UTF_16LE || encoding == UTF_16BE {
+            self.bom = true;
+        } else if encoding != UTF_8 {
+            self.bom = false;
+        }
+        self.encoding = encoding;
+    }
+    pub fn line_endings(&self) -> (LineEnding, bool) {
+        (self.line_ending, self.mixed_line_endings)
+    }
+    pub fn set_line_endings(&mut self, (line_ending, mixed): (LineEnding, bool)) {
+        self.line_ending = line_ending;
+        self.mixed_line_endings = mixed;
+    }
+    pub fn unify_line_endings(&mut self) {
+        if self.mixed_line_endings {
+            let mut text = self.text().replace("\r\n", "\n");
+            if self.final_newline && text.ends_with('\r') {
+                text.pop();
+            }
+            self.set_text(&text);
+            self.mixed_line_endings = false;
+        }
+    }
+    pub fn can_encode(&self, encoding: &'static Encoding) -> bool {
+        encoding.output_encoding() == UTF_8 || encode_text(&self.text(), encoding, false).is_ok()
+    }
+    pub fn set_text(&mut self, text: &str) {
+        self.rope = Rope::from_str(text);
+        self.revision = next_revision();
+        self.edits.clear();
+    }
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+    pub fn edits_since(&self, revision: u64) -> Option<&[TextEdit]> {
+        if revision == self.revision {
+            return Some(&[]);
+        }
+        let index = self.edits.iter().position(|edit| edit.base == revision)?;
+        Some(&self.edits[index..])
+    }
+    pub fn changes_since(&self, base: &TextBuffer) -> Option<(usize, Vec<String>, Vec<String>)> {
+        let Some(edits) = self.edits_since(base.revision) else {
+            return base.changed_lines(self);
+        };
+        let mut range: Option<(usize, usize, usize)> = None;
+        for edit in edits {
+            let (start, removed, inserted) = edit.lines();
+            let (edit_old_end, edit_new_end) = (start + removed, start + inserted);
+            range = Some(match range {
+                None => (start, edit_old_end, edit_new_end),
+                Some((range_start, old_end, new_end)) => {
+                    let end = new_end.max(edit_old_end);
+                    (range_start.min(start), end - new_end + old_end, end - edit_old_end + edit_new_end)
+                }
+            });
+        }
+        let (start, old_end, new_end) = range?;
+        let old_lines: Vec<String> = (start..old_end).map(|line_idx| base.rope.line(line_idx).to_string()).collect();
+        let new_lines: Vec<String> = (start..new_end).map(|line_idx| self.rope.line(line_idx).to_string()).collect();
+        if old_lines == new_lines {
+            return None;
+        }
+        Some((start, old_lines, new_lines))
+    }
+    fn edit_point(&self, char_idx: usize) -> (usize, (usize, usize)) {
+        let byte = self.rope.char_to_byte(char_idx);
+        let line_idx = self.rope.byte_to_line(byte);
+        (byte, (byte - self.rope.line_to_byte(line_idx), line_idx))
+    }
+    fn replace(&mut self, start: usize, end: usize, text: &str) {
+        if start == end && text.is_empty() {
+            return;
+        }
+        let (start_byte, start_point) = self.edit_point(start);
+        let (old_end_byte, old_end) = self.edit_point(end);
+        self.rope.remove(start..end);
+        self.rope.insert(start, text);
+        let new_end = match text.rfind('\n') {
+            Some(newline) => (text.len() - newline - 1, start_point.1 + text.matches('\n').count()),
+            None => (start_point.0 + text.len(), start_point.1),
+        };
+        self.edits.push(TextEdit {
+            base: self.revision,
+            start_byte,
+            old_end_byte,
+            new_end_byte: start_byte + text.len(),
+            start: start_point,
+            old_end,
+            new_end,
+        });
+        if self.edits.len() > MAX_EDITS {
+            self.edits.drain(..self.edits.len() - MAX_EDITS);
+        }
+        self.revision = next_revision();
+    }
+    pub fn format_label(&self) -> String {
+        let mut label = self.encoding.name().to_string();
+        if self.bom {
+            label.push_str(" BOM");
+        }
+        label.push(' ');
+        label.push_str(if self.mixed_line_endings { "Mixed" } else { self.line_ending.label() });
+        if !self.final_newline {
+            label.push_str(" noEOL");
+        }
+        label
+    }
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+    pub fn line_to_byte(&self, line_idx: usize) -> usize {
+        self.rope.line_to_byte(line_idx.min(self.len_lines()))
+    }
+    pub fn chunk_at_byte(&self, byte_idx: usize) -> &str {
+        if byte_idx >= self.rope.len_bytes() {
+            return "";
+        }
+        let (chunk, chunk_start, _, _) = self.rope.chunk_at_byte(byte_idx);
+        &chunk[byte_idx - chunk_start..]
+    }
+    pub fn byte_chunks(&self, range: Range<usize>) -> impl Iterator<Item = &str> {
+        let end = range.end.min(self.rope.len_bytes());
+        self.rope.byte_slice(range.start.min(end)..end).chunks()
+    }
+    fn line_slice(&self, line_idx: usize) -> RopeSlice<'_> {
+        let line = self.rope.line(line_idx.min(self.len_lines() - 1));
+        let mut len = line.len_chars();
+        if len > 0 && line.char(len - 1) == '\n' {
+            len -= 1;
+        }
+        if self.mixed_line_endings && len > 0 && line.char(len - 1) == '\r' {
+            len -= 1;
+        }
+        line.slice(..len)
+    }
+    pub fn line(&self, line_idx: usize) -> Cow<'_, str> {
+        self.line_slice(line_idx).into()
+    }
+    pub fn line_len(&self, line_idx: usize) -> usize {
+        self.line_slice(line_idx).len_chars()
+    }
+    pub fn display_segment(&self, line_idx: usize, start: usize, width: usize, tab_width: usize) -> String {
+        self.display_segment_styled(line_idx, start, width, tab_width, |_| ())
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect()
+    }
+    pub fn display_segment_styled<S: Copy + PartialEq>(
+        &self,
+        line_idx: usize,
+        start: usize,
+        width: usize,
+        tab_width: usize,
+        style_at: impl Fn(usize) -> S,
+    ) -> Vec<(S, String)> {
+        let line = self.line(line_idx);
+        let end = start + width;
+        let mut segments: Vec<(S, String)> = Vec::new();
+        let mut display = 0;
+        for (col, grapheme) in grapheme_cols(&line) {
+            if display >= end {
+                break;
+            }
+            let grapheme_end = display + grapheme_width(grapheme, display, tab_width);
+            if grapheme_end > start {
+                let style = style_at(col);
+                if segments.last().is_none_or(|(last, _)| *last != style) {
+                    segments.push((style, String::new()));
+                }
+                let segment = &mut segments.last_mut().expect("segment was just pushed").1;
+                if display < start || grapheme_end > end || grapheme == "\t" {
+                    let visible = grapheme_end.min(end) - display.max(start);
+                    segment.extend(std::iter::repeat_n(' ', visible));
+                } else {
+                    segment.push_str(grapheme);
+                }
+            }
+            display = grapheme_end;
+        }
+        segments
+    }
+    pub fn display_col(&self, pos: (usize, usize), tab_width: usize) -> usize {
+        let line = self.line(pos.1);
+        let mut display = 0;
+        for (col, grapheme) in grapheme_cols(&line) {
+            if col >= pos.0 {
+                break;
+            }
+            display += grapheme_width(grapheme, display, tab_width);
+        }
+        display
+    }
+    pub fn col_for_display(&self, line_idx: usize, target: usize, tab_width: usize) -> usize {
+        let line = self.line(line_idx);
+        let mut display = 0;
+        for (col, grapheme) in grapheme_cols(&line) {
+            display += grapheme_width(grapheme, display, tab_width);
+            if display > target {
+                return col;
+            }
+        }
+        line.chars().count()
+    }
+    pub fn col_at_or_after_display(&self, line_idx: usize, target: usize, tab_width: usize) -> usize {
+        let line = self.line(line_idx);
+        let mut display = 0;
+        for (col, grapheme) in grapheme_cols(&line) {
+            if display >= target {
+                return col;
+            }
+            display += grapheme_width(grapheme, display, tab_width);
+        }
+        line.chars().count()
+    }
+    pub fn prev_grapheme(&self, pos: (usize, usize)) -> usize {
+        grapheme_cols(&self.line(pos.1))
+            .map(|(col, _)| col)
+            .take_while(|&col| col < pos.0)
+            .last()
+            .unwrap_or(0)
+    }
+    pub fn next_grapheme(&self, pos: (usize, usize)) -> usize {
+        grapheme_cols(&self.line(pos.1))
+            .map(|(col, grapheme)| col + grapheme.chars().count())
+            .find(|&end| end > pos.0)
+            .unwrap_or_else(|| self.line_len(pos.1))
+    }
+    pub fn lines(&self) -> impl Iterator<Item = Cow<'_, str>> + '_ {
+        (0..self.len_lines()).map(move |idx| self.line(idx))
+    }
+    pub fn char_index(&self, pos: (usize, usize)) -> usize {
+        let line_idx = pos.1.min(self.len_lines() - 1);
+        self.rope.line_to_char(line_idx) + pos.0.min(self.line_len(line_idx))
+    }
+    pub fn position(&self, char_idx: usize) -> (usize, usize) {
+        let char_idx = char_idx.min(self.len_chars());
+        let line_idx = self.rope.char_to_line(char_idx);
+        (char_idx - self.rope.line_to_char(line_idx), line_idx)
+    }
+    pub fn byte_index(&self, pos: (usize, usize)) -> usize {
+        self.rope.char_to_byte(self.char_index(pos))
+    }
+    pub fn byte_position(&self, byte_idx: usize) -> (usize, usize) {
+        self.position(self.rope.byte_to_char(byte_idx.min(self.rope.len_bytes())))
+    }
+    pub fn clamp(&self, pos: (usize, usize)) -> (usize, usize) {
+        let line_idx = pos.1.min(self.len_lines() - 1);
+        (pos.0.min(self.line_len(line_idx)), line_idx)
+    }
+    pub fn insert(&mut self, pos: (usize, usize), text: &str) -> (usize, usize) {
+        let idx = self.char_index(pos);
+        self.replace(idx, idx, text);
+        self.position(idx + text.chars().count())
+    }
+    pub fn insert_char(&mut self, pos: (usize, usize), c: char) -> (usize, usize) {
+        let idx = self.char_index(pos);
+        self.replace(idx, idx, c.encode_utf8(&mut [0; 4]));
+        self.position(idx + 1)
+    }
+    pub fn text_range(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let (start, end) = self.ordered_range(start, end);
+        self.rope.slice(start..end).to_string()
+    }
+    pub fn remove(&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        let (start, end) = self.ordered_range(start, end);
+        let removed = self.rope.slice(start..end).to_string();
+        self.replace(start, end, "");
+        removed
+    }
+    fn ordered_range(&self, a: (usize, usize), b: (usize, usize)) -> (usize, usize) {
+        let (a, b) = (self.char_index(a), self.char_index(b));
+        (a.min(b), a.max(b))
+    }
+    pub fn remove_line(&mut self, line_idx: usize) -> String {
+        let line_idx = line_idx.min(self.len_lines() - 1);
+        let text = self.line(line_idx).into_owned();
+        let mut start = self.rope.line_to_char(line_idx);
+        let end = if line_idx + 1 < self.len_lines() {
+            self.rope.line_to_char(line_idx + 1)
+        } else {
+            start = start.saturating_sub(1);
+            self.len_chars()
+        };
+        self.replace(start, end, "");
+        text
+    }
+    pub fn changed_lines(&self, other: &TextBuffer) -> Option<(usize, Vec<String>, Vec<String>)> {
+        let (old, new) = (&self.rope, &other.rope);
+        let (old_len, new_len) = (old.len_lines(), new.len_lines());
+        let shared = old_len.min(new_len);
+        let mut prefix = 0;
+        while prefix < shared && old.line(prefix) == new.line(prefix) {
+            prefix += 1;
+        }
+        let mut suffix = 0;
+        while suffix < shared - prefix && old.line(old_len - 1 - suffix) == new.line(new_len - 1 - suffix) {
+            suffix += 1;
+        }
+        let collect = |rope: &Rope, end: usize| -> Vec<String> {
+            (prefix..end).map(|line_idx| rope.line(line_idx).to_string()).collect()
+        };
+        let old_lines = collect(old, old_len - suffix);
+        let new_lines = collect(new, new_len - suffix);
+        if old_lines.is_empty() && new_lines.is_empty() {
+            return None;
+        }
+        Some((prefix, old_lines, new_lines))
+    }
+    pub fn splice_lines(&mut self, start_line: usize, removed: &[String], inserted: &[String]) {
+        let start = self.rope.line_to_char(start_line);
+        let len: usize = removed.iter().map(|line| line.chars().count()).sum();
+        self.replace(start, start + len, &inserted.concat());
+    }
+    pub fn find_all(&self, query: &str) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        if query.is_empty() {
+            return matches;
+        }
+        for (line_idx, line) in self.lines().enumerate() {
+            let mut start = 0;
+            while let Some(pos) = line[start..].find(query) {
+                let abs_pos = start + pos;
+                matches.push((line_idx, line[..abs_pos].chars().count()));
+                start = abs_pos + line[abs_pos..].chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        matches
+    }
+    pub fn find_regex(&self, regex: &Regex) -> Vec<((usize, usize), (usize, usize))> {
+        let text = self.cached_text();
+        regex.find_iter(&text)
+            .filter(|found| !found.is_empty())
+            .map(|found| (self.byte_position(found.start()), self.byte_position(found.end())))
+            .collect()
+    }
+    pub fn text(&self) -> String {
+        self.rope.to_string()
+    }
+    pub fn cached_text(&self) -> Rc<str> {
+        let mut cache = self.text_cache.borrow_mut();
+        match &*cache {
+            Some((revision, text)) if *revision == self.revision => text.clone(),
+            _ => {
+                let text: Rc<str> = self.text().into();
+                *cache = Some((self.revision, text.clone()));
+                text
+            }
+        }
+    }
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        let encoded = if self.encoding == UTF_8 {
+            None
+        } else {
+            let mut text = String::with_capacity(self.len_chars());
+            self.write_text(|part| {
+                text.push_str(part);
+                Ok(())
+            })?;
+            Some(encode_text(&text, self.encoding, self.bom)?)
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        match encoded {
+            Some(bytes) => writer.write_all(&bytes)?,
+            None => {
+                if self.bom {
+                    writer.write_all("\u{feff}".as_bytes())?;
+                }
+                self.write_text(|text| writer.write_all(text.as_bytes()))?;
+            }
+        }
+        writer.flush()
+    }
+    pub fn write_file_atomic(&self, path: &Path) -> io::Result<()> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temp = path.with_file_name(format!(".{}.red-tmp", name));
+        let result = self.write_file(&temp)
+            .and_then(|_| match fs::metadata(&path) {
+                Ok(metadata) => fs::set_permissions(&temp, metadata.permissions()),
+                Err(_) => Ok(()),
+            })
+            .and_then(|_| fs::rename(&temp, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+    fn write_text<F: FnMut(&str) -> io::Result<()>>(&self, mut write: F) -> io::Result<()> {
+        let newline = if self.mixed_line_endings { "\n" } else { self.line_ending.as_str() };
+        for chunk in self.rope.chunks() {
+            match self.line_ending {
+                _ if self.mixed_line_endings => write(chunk)?,
+                LineEnding::Lf => write(chunk)?,
+                LineEnding::CrLf => {
+                    for (i, part) in chunk.split('\n').enumerate() {
+                        if i > 0 {
+                            write(newline)?;
+                        }
+                        write(part)?;
+                    }
+                }
+            }
+        }
+        if self.final_newline {
+            write(newline)?;
+        }
+        Ok(())
+    }
+}
+impl PartialEq for TextBuffer {
+    fn eq(&self, other: &Self) -> bool {
+        self.rope == other.rope
+            && self.encoding == other.encoding
+            && self.line_ending == other.line_ending
+            && self.bom == other.bom
+            && self.final_newline == other.final_newline
+            && self.mixed_line_endings == other.mixed_line_endings
+    }
+}
+impl Default for TextBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+    if bytes.len() >= 2 && bytes.len().is_multiple_of(2) {
+        let sample = &bytes[..bytes.len().min(4096)];
+        let even_zeros = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+        let odd_zeros = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+        let threshold = sample.len() / 4;
+        if odd_zeros > threshold && even_zeros == 0 {
+            return UTF_16LE;
+        }
+        if even_zeros > threshold && odd_zeros == 0 {
+            return UTF_16BE;
+        }
+    }
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+fn encode_text(text: &str, encoding: &'static Encoding, bom: bool) -> io::Result<Vec<u8>> {
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        let mut bytes = Vec::with_capacity(text.len() * 2 + 2);
+        let units = bom.then_some(0xfeff).into_iter().chain(text.encode_utf16());
+        for unit in units {
+            if encoding == UTF_16LE {
+                bytes.extend_from_slice(&unit.to_le_bytes());
+            } else {
+                bytes.extend_from_slice(&unit.to_be_bytes());
+            }
+        }
+        return Ok(bytes);
+    }
+    let (bytes, _, had_errors) = encoding.encode(text);
+    if had_errors {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Text contains characters that cannot be encoded as {}", encoding.name())
+        ));
+    }
+    Ok(bytes.into_owned())
+}
+fn grapheme_cols(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut col = 0;
+    line.graphemes(true).map(move |grapheme| {
+        let start = col;
+        col += grapheme.chars().count();
+        (start, grapheme)
+    })
+}
+pub fn grapheme_width(grapheme: &str, display_col: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        tab_width - display_col % tab_width
+    } else {
+        grapheme.width()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("red-buffer-{}-{}", std::process::id(), name))
+    }
+    fn written(buffer: &TextBuffer, name: &str) -> Vec<u8> {
+        let path = temp_path(name);
+        buffer.write_file(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        bytes
+    }
+    fn assert_round_trip(bytes: &[u8], name: &str) -> TextBuffer {
+        let buffer = TextBuffer::from_bytes(bytes);
+        assert_eq!(written(&buffer, name), bytes);
+        buffer
+    }
+
+    #[test]
+    fn crlf_round_trips() {
+        let buffer = assert_round_trip(b"one\r\ntwo\r\n", "crlf");
+        assert!(buffer.line_ending == LineEnding::CrLf);
+        assert!(!buffer.mixed_line_endings);
+        assert_eq!(buffer.text(), "one\ntwo");
+        assert_eq!(buffer.format_label(), "UTF-8 CRLF");
+    }
+    #[test]
+    fn bom_round_trips() {
+        let buffer = assert_round_trip(b"\xef\xbb\xbfhello\n", "bom");
+        assert!(buffer.bom);
+        assert_eq!(buffer.text(), "hello");
+    }
+    #[test]
+    fn missing_final_newline_round_trips() {
+        let buffer = assert_round_trip(b"one\ntwo", "noeol");
+        assert!(!buffer.final_newline);
+        assert_eq!(buffer.format_label(), "UTF-8 LF noEOL");
+    }
+    #[test]
+    fn utf16_round_trips() {
+        let mut bytes = vec![0xff, 0xfe];
+        for unit in "h\u{e9}llo\r\nw\u{f6}rld\r\n".encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+        let buffer = assert_round_trip(&bytes, "utf16");
+        assert!(buffer.encoding == UTF_16LE);
+        assert_eq!(buffer.line(1), "w\u{f6}rld");
+    }
+    #[test]
+    fn latin1_round_trips() {
+        let bytes = b"caf\xe9\nna\xefve\n";
+        let buffer = TextBuffer::from_bytes_with_encoding(bytes, encoding_rs::WINDOWS_1252);
+        assert!(!buffer.decode_errors);
+        assert_eq!(buffer.line(0), "caf\u{e9}");
+        assert_eq!(written(&buffer, "latin1"), bytes);
+    }
+    #[test]
+    fn cached_text_follows_edits() {
+        let mut buffer = TextBuffer::from_text("one two");
+        let regex = Regex::new("o").unwrap();
+        assert!(Rc::ptr_eq(&buffer.cached_text(), &buffer.cached_text()));
+        assert_eq!(buffer.find_regex(&regex), vec![((0, 0), (1, 0)), ((6, 0), (7, 0))]);
+        buffer.insert((3, 0), "\nfoo");
+        assert_eq!(&*buffer.cached_text(), "one\nfoo two");
+        assert_eq!(buffer.find_regex(&Regex::new("e\nf").unwrap()), vec![((2, 0), (1, 1))]);
+    }
+    #[test]
+    fn invalid_bytes_are_recorded() {
+        let buffer = TextBuffer::from_bytes_with_encoding(b"caf\xe9\n", UTF_8);
+        assert!(buffer.decode_errors);
+        assert_eq!(buffer.line(0), "caf\u{fffd}");
+        assert!(!TextBuffer::from_bytes(b"cafe\n").decode_errors);
+    }
+    #[test]
+    fn mixed_line_endings_are_kept() {
+        let mut buffer = assert_round_trip(b"one\r\ntwo\nthree\r\n", "mixed");
+        assert!(buffer.mixed_line_endings);
+        assert_eq!(buffer.format_label(), "UTF-8 Mixed");
+        assert_eq!(buffer.line(0), "one");
+        assert_eq!(buffer.line_len(2), 5);
+        buffer.insert((3, 1), "!");
+        assert_eq!(written(&buffer, "mixed-edit"), b"one\r\ntwo!\nthree\r\n");
+        buffer.unify_line_endings();
+        assert!(!buffer.mixed_line_endings);
+        assert_eq!(written(&buffer, "mixed-unified"), b"one\r\ntwo!\r\nthree\r\n");
+    }
+    #[test]
+    fn changes_since_matches_edits() {
+        let base = TextBuffer::from_text("one\ntwo\nthree\nfour");
+        let mut content = base.clone();
+        content.insert((3, 1), "!");
+        content.insert((0, 3), "new\n");
+        content.remove((2, 0), (0, 1));
+        content.remove_line(4);
+        let (start, old_lines, new_lines) = content.changes_since(&base).unwrap();
+        let mut undone = content.clone();
+        undone.splice_lines(start, &new_lines, &old_lines);
+        assert_eq!(undone.text(), base.text());
+        let mut redone = base.clone();
+        redone.splice_lines(start, &old_lines, &new_lines);
+        assert_eq!(redone.text(), content.text());
+    }
+    #[test]
+    fn changes_since_ignores_reverted_edits() {
+        let base = TextBuffer::from_text("one\ntwo");
+        let mut content = base.clone();
+        content.insert_char((1, 0), 'x');
+        content.remove((1, 0), (2, 0));
+        assert_eq!(content.changes_since(&base), None);
+    }
+    #[test]
+    fn edits_since_requires_journal() {
+        let base = TextBuffer::from_text("one");
+        let mut content = base.clone();
+        assert_eq!(content.edits_since(base.revision()).map(<[TextEdit]>::len), Some(0));
+        content.insert((3, 0), "\ntwo");
+        let edits = content.edits_since(base.revision()).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!((edits[0].start, edits[0].new_end), ((3, 0), (3, 1)));
+        assert_eq!(edits[0].lines(), (0, 1, 2));
+        content.set_text("other");
+        assert!(content.edits_since(base.revision()).is_none());
+        assert_eq!(TextBuffer::from_text("one").edits_since(base.revision()).map(<[TextEdit]>::len), None);
+    }
+}
@@ -0,0 +1,222 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    Global,
+    Editor,
+    Tree,
+    Search,
+    ProjectSearch,
+}
+impl Context {
+    pub const ALL: [Context; 5] = [Context::Global, Context::Editor, Context::Tree, Context::Search, Context::ProjectSearch];
+    pub fn name(self) -> &'static str {
+        match self {
+            Context::Global => "global",
+            Context::Editor => "editor",
+            Context::Tree => "tree",
+            Context::Search => "search",
+            Context::ProjectSearch => "project_search",
+        }
+    }
+    pub fn overlaps(self, other: Context) -> bool {
+        self == other || matches!(
+            (self, other),
+            (Context::Global, Context::Editor | Context::Tree)
+                | (Context::Editor | Context::Tree, Context::Global)
+                | (Context::Search, Context::ProjectSearch)
+                | (Context::ProjectSearch, Context::Search)
+        )
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    GoToLine,
+    GoToSymbol,
+    GoToProjectSymbol,
+    NextSibling,
+    PreviousSibling,
+    ParentNode,
+    Fold,
+    Unfold,
+    ToggleAllFolds,
+    Save,
+    Open,
+    FindFile,
+    CloseTab,
+    NextTab,
+    PreviousTab,
+    MoveTabRight,
+    MoveTabLeft,
+    Quit,
+    ToggleTree,
+    ToggleOutline,
+    ToggleLineNumbers,
+    SplitHorizontal,
+    SplitVertical,
+    FocusLeft,
+    FocusRight,
+    FocusUp,
+    FocusDown,
+    GrowPane,
+    ShrinkPane,
+    ClosePane,
+    Cut,
+    Copy,
+    Paste,
+    Indent,
+    Outdent,
+    Undo,
+    Redo,
+    UndoTree,
+    OlderState,
+    NewerState,
+    SelectAll,
+    SelectLine,
+    SelectWord,
+    ExpandSelection,
+    ShrinkSelection,
+    VisualMode,
+    BlockMode,
+    NextOccurrence,
+    CursorAbove,
+    CursorBelow,
+    CursorPerMatch,
+    Find,
+    Replace,
+    FindNext,
+    ProjectSearch,
+    ProjectReplace,
+    ToggleRegex,
+    ToggleIgnoreCase,
+    ToggleWholeWord,
+    FocusExplorer,
+    NewFile,
+    NewDirectory,
+    DeleteComments,
+    RemoveEmptyLines,
+    TransliterateToAscii,
+    ConvertLineEndings,
+    ReopenWithEncoding,
+    SaveWithEncoding,
+    ClearCache,
+    RunFile,
+    ToolMenu,
+    Settings,
+    Palette,
+    Help,
+}
+pub struct CommandInfo {
+    pub command: Command,
+    pub context: Context,
+    pub category: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub keys: &'static [&'static str],
+}
+impl CommandInfo {
+    pub fn id(&self) -> String {
+        self.name.to_lowercase().replace(' ', "_")
+    }
+}
+const fn info(
+    command: Command,
+    context: Context,
+    category: &'static str,
+    name: &'static str,
+    description: &'static str,
+    keys: &'static [&'static str],
+) -> CommandInfo {
+    CommandInfo { command, context, category, name, description, keys }
+}
+pub const COMMANDS: &[CommandInfo] = &[
+    info(Command::WordLeft, Context::Editor, "Navigation", "Word left", "Move to the previous word", &["Ctrl+Left"]),
+    info(Command::WordRight, Context::Editor, "Navigation", "Word right", "Move to the next word", &["Ctrl+Right"]),
+    info(Command::LineStart, Context::Editor, "Navigation", "Line start", "Jump to start of line", &["Home", "Shift+Home"]),
+    info(Command::LineEnd, Context::Editor, "Navigation", "Line end", "Jump to end of line", &["End", "Shift+End"]),
+    info(Command::PageUp, Context::Editor, "Navigation", "Page up", "Move up by a page", &["PgUp", "Shift+PgUp"]),
+    info(Command::PageDown, Context::Editor, "Navigation", "Page down", "Move down by a page", &["PgDn", "Shift+PgDn"]),
+    info(Command::GoToLine, Context::Editor, "Navigation", "Go to line", "Jump to a line number", &["Ctrl+g"]),
+    info(Command::GoToSymbol, Context::Editor, "Navigation", "Go to symbol", "Fuzzy-find a symbol in the current file", &["Ctrl+k s"]),
+    info(Command::GoToProjectSymbol, Context::Global, "Navigation", "Go to symbol in project", "Fuzzy-find a function or type anywhere in the project", &["Ctrl+k t"]),
+    info(Command::NextSibling, Context::Editor, "Navigation", "Next sibling", "Jump to the next syntax node at the same level", &["Alt+Shift+Down"]),
+    info(Command::PreviousSibling, Context::Editor, "Navigation", "Previous sibling", "Jump to the previous syntax node at the same level", &["Alt+Shift+Up"]),
+    info(Command::ParentNode, Context::Editor, "Navigation", "Parent node", "Jump to the start of the enclosing syntax node", &["Ctrl+k p"]),
+    info(Command::Fold, Context::Editor, "Folding", "Fold", "Fold the innermost block around the cursor", &["Ctrl+k ["]),
+    info(Command::Unfold, Context::Editor, "Folding", "Unfold", "Unfold the folded block at the cursor line", &["Ctrl+k ]"]),
+    info(Command::ToggleAllFolds, Context::Editor, "Folding", "Toggle all folds", "Fold every block, or unfold all if any is folded", &["Ctrl+k 0"]),
+    info(Command::Save, Context::Editor, "File", "Save", "Save current file", &["Ctrl+s"]),
+    info(Command::Open, Context::Editor, "File", "Open", "Open file by path", &["Alt+o"]),
+    info(Command::FindFile, Context::Global, "File", "Find file", "Fuzzy-find a file in the project", &["Ctrl+p"]),
+    info(Command::CloseTab, Context::Editor, "File", "Close tab", "Close current tab", &["Alt+w"]),
+    info(Command::NextTab, Context::Global, "File", "Next tab", "Go to next tab", &["Alt+."]),
+    info(Command::PreviousTab, Context::Global, "File", "Previous tab", "Go to previous tab", &["Alt+,"]),
+    info(Command::MoveTabRight, Context::Editor, "File", "Move tab right", "Move current tab right", &["Alt+>"]),
+    info(Command::MoveTabLeft, Context::Editor, "File", "Move tab left", "Move current tab left", &["Alt+<"]),
+    info(Command::Quit, Context::Global, "File", "Quit", "Exit editor", &["Alt+q"]),
+    info(Command::ToggleTree, Context::Global, "Layout", "Tree view", "Toggle file explorer sidebar", &["Alt+b"]),
+    info(Command::ToggleOutline, Context::Global, "Layout", "Outline", "Switch the sidebar between files and the symbol outline", &["Ctrl+k o"]),
+    info(Command::ToggleLineNumbers, Context::Editor, "Layout", "Line numbers", "Toggle line number gutter", &["Alt+l"]),
+    info(Command::SplitHorizontal, Context::Editor, "Layout", "Split horizontally", "Split pane side by side", &["Alt+s"]),
+    info(Command::SplitVertical, Context::Editor, "Layout", "Split vertically", "Split pane top and bottom", &["Alt+v"]),
+    info(Command::FocusLeft, Context::Editor, "Layout", "Focus left", "Move focus to the pane on the left", &["Ctrl+Alt+Left"]),
+    info(Command::FocusRight, Context::Editor, "Layout", "Focus right", "Move focus to the pane on the right", &["Ctrl+Alt+Right"]),
+    info(Command::FocusUp, Context::Editor, "Layout", "Focus up", "Move focus to the pane above", &["Ctrl+Alt+Up"]),
+    info(Command::FocusDown, Context::Editor, "Layout", "Focus down", "Move focus to the pane below", &["Ctrl+Alt+Down"]),
+    info(Command::GrowPane, Context::Editor, "Layout", "Grow pane", "Grow focused pane", &["Alt+="]),
+    info(Command::ShrinkPane, Context::Editor, "Layout", "Shrink pane", "Shrink focused pane", &["Alt+-"]),
+    info(Command::ClosePane, Context::Editor, "Layout", "Close pane", "Close focused pane, keep buffer", &["Alt+x"]),
+    info(Command::Cut, Context::Editor, "Editing", "Cut", "Cut selection or current line", &["Ctrl+x"]),
+    info(Command::Copy, Context::Editor, "Editing", "Copy", "Copy selection or current line", &["Ctrl+c"]),
+    info(Command::Paste, Context::Editor, "Editing", "Paste", "Paste from clipboard", &["Ctrl+v"]),
+    info(Command::Indent, Context::Editor, "Editing", "Indent", "Indent selected lines or insert indentation", &["Tab"]),
+    info(Command::Outdent, Context::Editor, "Editing", "Outdent", "Outdent selected lines", &["Shift+Tab"]),
+    info(Command::Undo, Context::Editor, "Editing", "Undo", "Undo last action", &["Ctrl+z"]),
+    info(Command::Redo, Context::Editor, "Editing", "Redo", "Redo last action", &["Ctrl+y"]),
+    info(Command::UndoTree, Context::Editor, "Editing", "Undo tree", "Browse and restore undo branches", &["Alt+u"]),
+    info(Command::OlderState, Context::Editor, "Editing", "Older state", "Go to the previous state in time", &["Alt+{"]),
+    info(Command::NewerState, Context::Editor, "Editing", "Newer state", "Go to the next state in time", &["Alt+}"]),
+    info(Command::SelectAll, Context::Editor, "Selection", "Select all", "Select entire file", &["Alt+a"]),
+    info(Command::SelectLine, Context::Editor, "Selection", "Select line", "Select current line", &["Alt+L"]),
+    info(Command::SelectWord, Context::Editor, "Selection", "Select word", "Select current word", &["Alt+W"]),
+    info(Command::ExpandSelection, Context::Editor, "Selection", "Expand selection", "Grow the selection to the enclosing syntax node", &["Alt+Shift+Right"]),
+    info(Command::ShrinkSelection, Context::Editor, "Selection", "Shrink selection", "Return to the selection before the last expansion", &["Alt+Shift+Left"]),
+    info(Command::VisualMode, Context::Editor, "Selection", "Visual mode", "Toggle visual selection mode", &["Alt+V"]),
+    info(Command::BlockMode, Context::Editor, "Selection", "Block mode", "Toggle rectangular selection", &["Alt+B"]),
+    info(Command::NextOccurrence, Context::Editor, "Multiple Cursors", "Next occurrence", "Add cursor at next occurrence of word", &["Ctrl+d"]),
+    info(Command::CursorAbove, Context::Editor, "Multiple Cursors", "Cursor above", "Add cursor on the line above", &["Alt+Up"]),
+    info(Command::CursorBelow, Context::Editor, "Multiple Cursors", "Cursor below", "Add cursor on the line below", &["Alt+Down"]),
+    info(Command::CursorPerMatch, Context::Editor, "Multiple Cursors", "Cursor per match", "Add cursor at every search match", &["Alt+m"]),
+    info(Command::Find, Context::Editor, "Search", "Find", "Search in file", &["Ctrl+f"]),
+    info(Command::Replace, Context::Editor, "Search", "Replace", "Replace, confirming each match (y/n/a/q); $1 / ${name} insert groups", &["Ctrl+r"]),
+    info(Command::FindNext, Context::Editor, "Search", "Find next", "Go to next match", &["Alt+n"]),
+    info(Command::ProjectSearch, Context::Global, "Search", "Project search", "Search all files in the project", &["Alt+f"]),
+    info(Command::ProjectReplace, Context::ProjectSearch, "Search", "Project replace", "Replace checked hits of the project search", &["Ctrl+r"]),
+    info(Command::ToggleRegex, Context::Search, "Search", "Toggle regex", "Treat the search query as a regex", &["Alt+r"]),
+    info(Command::ToggleIgnoreCase, Context::Search, "Search", "Toggle ignore case", "Match regardless of case", &["Alt+c"]),
+    info(Command::ToggleWholeWord, Context::Search, "Search", "Toggle whole word", "Only match whole words", &["Alt+w"]),
+    info(Command::FocusExplorer, Context::Global, "File Tree", "Switch to explorer", "Switch focus to the file explorer", &["Alt+e"]),
+    info(Command::NewFile, Context::Tree, "File Tree", "New file", "Create new file", &["Alt+n"]),
+    info(Command::NewDirectory, Context::Tree, "File Tree", "New directory", "Create new directory", &["Alt+d"]),
+    info(Command::DeleteComments, Context::Editor, "Tools", "Delete Comments", "Remove all comments from the file", &[]),
+    info(Command::RemoveEmptyLines, Context::Editor, "Tools", "Remove Empty Lines", "Remove all empty lines from the file", &[]),
+    info(Command::TransliterateToAscii, Context::Editor, "Tools", "Transliterate to ASCII", "Replace non-ASCII characters with ASCII lookalikes", &[]),
+    info(Command::ConvertLineEndings, Context::Editor, "Tools", "Convert Line Endings", "Switch the file between LF and CRLF", &[]),
+    info(Command::ReopenWithEncoding, Context::Editor, "Tools", "Reopen with Encoding", "Reload the file using a chosen encoding", &[]),
+    info(Command::SaveWithEncoding, Context::Editor, "Tools", "Save with Encoding", "Save the file using a chosen encoding", &[]),
+    info(Command::ClearCache, Context::Editor, "Tools", "Clear Cache", "Clear editor's cache", &[]),
+    info(Command::RunFile, Context::Editor, "Extra", "Run file", "Run the current file", &["Alt+r"]),
+    info(Command::ToolMenu, Context::Global, "Extra", "Tool menu", "Open tool menu", &["Alt+t"]),
+    info(Command::Settings, Context::Global, "Extra", "Settings", "Open settings", &["Alt+p"]),
+    info(Command::Palette, Context::Global, "Extra", "Command palette", "Search and run any command", &["F1"]),
+    info(Command::Help, Context::Global, "Extra", "Help", "Show this help", &["Alt+h"]),
+];
+pub fn info_for(command: Command) -> &'static CommandInfo {
+    COMMANDS.iter()
+        .find(|info| info.command == command)
+        .expect("every command is registered")
+}
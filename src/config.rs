@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io, path::Path};
+use toml_edit::{DocumentMut, TableLike, Value};
+
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+const MAX_TAB_WIDTH: usize = 16;
+
+#[derive(Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub editor: EditorConfig,
+    pub ui: UiConfig,
+    pub languages: BTreeMap<String, LanguageConfig>,
+}
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EditorConfig {
+    pub tab_width: usize,
+    pub insert_spaces: bool,
+}
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    pub show_tree: bool,
+    pub show_numbers: bool,
+    pub show_status: bool,
+    pub theme: String,
+}
+#[derive(Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanguageConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tab_width: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insert_spaces: Option<bool>,
+}
+impl Default for EditorConfig {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            insert_spaces: true,
+        }
+    }
+}
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            show_tree: true,
+            show_numbers: true,
+            show_status: true,
+            theme: DEFAULT_THEME.to_string(),
+        }
+    }
+}
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let config: Self = toml::from_str(&text).map_err(|e| e.message().to_string())?;
+        let widths = std::iter::once(("editor".to_string(), Some(config.editor.tab_width)))
+            .chain(config.languages.iter().map(|(name, language)| (format!("languages.{}", name), language.tab_width)));
+        for (section, width) in widths {
+            if width.is_some_and(|width| !(1..=MAX_TAB_WIDTH).contains(&width)) {
+                return Err(format!("{}.tab_width must be between 1 and {}", section, MAX_TAB_WIDTH));
+            }
+        }
+        Ok(config)
+    }
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let text = toml::to_string_pretty(self).map_err(io::Error::other)?;
+        let text = match fs::read_to_string(path) {
+            Ok(existing) => {
+                let mut document = existing.parse::<DocumentMut>().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "config.toml has syntax errors, not overwriting it")
+                })?;
+                let updated = text.parse::<DocumentMut>().map_err(io::Error::other)?;
+                merge(document.as_table_mut(), updated.as_table());
+                document.to_string()
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => text,
+            Err(e) => return Err(e),
+        };
+        fs::write(path, text)
+    }
+    fn language(&self, path: Option<&Path>) -> Option<&LanguageConfig> {
+        let path = path?;
+        path.extension()
+            .and_then(|extension| self.languages.get(extension.to_str()?))
+            .or_else(|| self.languages.get(path.file_name()?.to_str()?))
+    }
+    pub fn tab_width(&self, path: Option<&Path>) -> usize {
+        self.language(path)
+            .and_then(|language| language.tab_width)
+            .unwrap_or(self.editor.tab_width)
+            .clamp(1, MAX_TAB_WIDTH)
+    }
+    pub fn indent_unit(&self, path: Option<&Path>) -> String {
+        let insert_spaces = self.language(path)
+            .and_then(|language| language.insert_spaces)
+            .unwrap_or(self.editor.insert_spaces);
+        if insert_spaces {
+            " ".repeat(self.tab_width(path))
+        } else {
+            "\t".to_string()
+        }
+    }
+    pub fn cycle_tab_width(&mut self, forward: bool) {
+        let width = self.editor.tab_width.clamp(1, MAX_TAB_WIDTH);
+        self.editor.tab_width = if forward {
+            width % MAX_TAB_WIDTH + 1
+        } else {
+            (width + MAX_TAB_WIDTH - 2) % MAX_TAB_WIDTH + 1
+        };
+    }
+}
+fn merge(target: &mut dyn TableLike, source: &dyn TableLike) {
+    for (key, item) in source.iter() {
+        match target.get_mut(key) {
+            Some(existing) if existing.is_table_like() && item.is_table_like() => {
+                if let (Some(existing), Some(item)) = (existing.as_table_like_mut(), item.as_table_like()) {
+                    merge(existing, item);
+                }
+            }
+            Some(existing) => match (existing.as_value_mut(), item.as_value()) {
+                (Some(old), Some(new)) => {
+                    if bare(old) != bare(new) {
+                        let decor = old.decor().clone();
+                        *old = new.clone();
+                        *old.decor_mut() = decor;
+                    }
+                }
+                _ => *existing = item.clone(),
+            },
+            None => {
+                target.insert(key, item.clone());
+            }
+        }
+    }
+}
+fn bare(value: &Value) -> String {
+    let mut value = value.clone();
+    value.decor_mut().clear();
+    value.to_string()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, text: &str) -> Result<Config, String> {
+        let path = std::env::temp_dir().join(format!("red-config-{}-{}.toml", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        let config = Config::load(&path);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn missing_file_uses_defaults() {
+        let path = std::env::temp_dir().join(format!("red-config-{}-missing.toml", std::process::id()));
+        assert!(Config::load(&path).ok() == Some(Config::default()));
+    }
+    #[test]
+    fn partial_file_keeps_other_defaults() {
+        let config = load("partial", "[editor]\ntab_width = 2\n\n[ui]\nshow_numbers = false\n").unwrap();
+        assert_eq!(config.editor.tab_width, 2);
+        assert!(config.editor.insert_spaces);
+        assert!(!config.ui.show_numbers);
+        assert!(config.ui.show_status);
+        assert_eq!(config.ui.theme, DEFAULT_THEME);
+    }
+    #[test]
+    fn tab_width_is_validated() {
+        assert_eq!(load("zero", "[editor]\ntab_width = 0\n").err().unwrap(), "editor.tab_width must be between 1 and 16");
+        assert_eq!(
+            load("language", "[languages.go]\ntab_width = 17\n").err().unwrap(),
+            "languages.go.tab_width must be between 1 and 16"
+        );
+        assert!(load("max", "[editor]\ntab_width = 16\n").is_ok());
+    }
+    #[test]
+    fn invalid_toml_is_rejected() {
+        assert!(load("syntax", "[editor\n").is_err());
+        assert!(load("type", "[editor]\ntab_width = \"wide\"\n").is_err());
+    }
+    #[test]
+    fn language_settings_override_editor() {
+        let config = load("languages", "[languages.go]\ntab_width = 8\ninsert_spaces = false\n\n[languages.Makefile]\ninsert_spaces = false\n").unwrap();
+        assert_eq!(config.tab_width(Some(Path::new("main.go"))), 8);
+        assert_eq!(config.indent_unit(Some(Path::new("main.go"))), "\t");
+        assert_eq!(config.indent_unit(Some(Path::new("Makefile"))), "\t");
+        assert_eq!(config.tab_width(Some(Path::new("main.rs"))), 4);
+        assert_eq!(config.indent_unit(None), "    ");
+    }
+    #[test]
+    fn save_round_trips() {
+        let mut config = Config::default();
+        config.editor.tab_width = 3;
+        config.languages.insert("py".to_string(), LanguageConfig { tab_width: Some(4), insert_spaces: None });
+        let path = std::env::temp_dir().join(format!("red-config-{}-saved.toml", std::process::id()));
+        config.save(&path).unwrap();
+        let loaded = Config::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.ok() == Some(config));
+    }
+    #[test]
+    fn save_keeps_comments_and_layout() {
+        let path = std::env::temp_dir().join(format!("red-config-{}-edited.toml", std::process::id()));
+        let text = "# my settings\n[ui]\ntheme = \"InspiredGitHub\" # light\n\n[editor]\ntab_width = 2   # narrow\n";
+        fs::write(&path, text).unwrap();
+        let mut config = Config::load(&path).unwrap();
+        config.editor.tab_width = 8;
+        config.save(&path).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        let loaded = Config::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(saved.starts_with("# my settings\n[ui]\ntheme = \"InspiredGitHub\" # light\n"));
+        assert!(saved.contains("tab_width = 8   # narrow\n"));
+        assert!(loaded.ok() == Some(config));
+    }
+    #[test]
+    fn save_refuses_to_overwrite_invalid_file() {
+        let path = std::env::temp_dir().join(format!("red-config-{}-broken.toml", std::process::id()));
+        fs::write(&path, "[editor\n").unwrap();
+        let result = Config::default().save(&path);
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+        assert_eq!(text, "[editor\n");
+    }
+    #[test]
+    fn cycle_tab_width_wraps() {
+        let mut config = Config::default();
+        config.editor.tab_width = 16;
+        config.cycle_tab_width(true);
+        assert_eq!(config.editor.tab_width, 1);
+        config.cycle_tab_width(false);
+        assert_eq!(config.editor.tab_width, 16);
+    }
+}
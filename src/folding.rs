@@ -0,0 +1,296 @@
+use crate::{buffer::TextBuffer, syntax::Highlighter};
+use std::time::{Duration, Instant};
+
+const RECOMPUTE_DELAY: Duration = Duration::from_millis(300);
+const MAX_INDENT_LINES: usize = 100_000;
+
+#[derive(Clone, Default)]
+pub struct Folds {
+    ranges: Vec<(usize, usize)>,
+    closed: Vec<(usize, usize)>,
+    revision: Option<u64>,
+    last_edit: Option<Instant>,
+}
+impl Folds {
+    pub fn update(&mut self, content: &TextBuffer, highlighter: Option<&Highlighter>, tab_width: usize) {
+        if self.revision != Some(content.revision()) {
+            let edits = self.revision.and_then(|revision| content.edits_since(revision));
+            self.revision = Some(content.revision());
+            let Some(edits) = edits else {
+                self.recompute(content, highlighter, tab_width);
+                return;
+            };
+            for edit in edits {
+                let (start, removed, inserted) = edit.lines();
+                self.shift_closed(start, removed, inserted);
+                self.shift_ranges(start, removed, inserted);
+            }
+            self.last_edit = Some(Instant::now());
+        }
+        if self.last_edit.is_some_and(|since| since.elapsed() >= RECOMPUTE_DELAY) {
+            self.recompute(content, highlighter, tab_width);
+        }
+    }
+    pub fn settle(&mut self, content: &TextBuffer, highlighter: Option<&Highlighter>, tab_width: usize) {
+        self.update(content, highlighter, tab_width);
+        if self.last_edit.is_some() {
+            self.recompute(content, highlighter, tab_width);
+        }
+    }
+    fn recompute(&mut self, content: &TextBuffer, highlighter: Option<&Highlighter>, tab_width: usize) {
+        self.ranges = highlighter
+            .and_then(Highlighter::fold_ranges)
+            .unwrap_or_else(|| {
+                if content.len_lines() > MAX_INDENT_LINES {
+                    Vec::new()
+                } else {
+                    indent_ranges(content, tab_width)
+                }
+            });
+        let ranges = &self.ranges;
+        self.closed = self.closed.iter()
+            .filter_map(|&(start, _)| ranges.iter().find(|range| range.0 == start).copied())
+            .collect();
+        self.last_edit = None;
+    }
+    fn shift_ranges(&mut self, start: usize, removed: usize, inserted: usize) {
+        let delta = inserted as isize - removed as isize;
+        let shift = |line: usize| (line as isize + delta).max(0) as usize;
+        let end = start + removed;
+        self.ranges.retain_mut(|range| {
+            let before = if removed == 0 { start <= range.0 } else { end <= range.0 };
+            if before {
+                *range = (shift(range.0), shift(range.1));
+            } else if start > range.1 {
+                return true;
+            } else if start >= range.0 && end <= range.1 + 1 {
+                range.1 = shift(range.1);
+            } else {
+                return false;
+            }
+            range.0 < range.1
+        });
+    }
+    fn shift_closed(&mut self, start: usize, removed: usize, inserted: usize) {
+        let delta = inserted as isize - removed as isize;
+        let shift = |line: usize| (line as isize + delta).max(0) as usize;
+        self.closed.retain_mut(|fold| {
+            if removed == 0 {
+                if start <= fold.0 {
+                    *fold = (shift(fold.0), shift(fold.1));
+                } else if start <= fold.1 {
+                    return false;
+                }
+                return true;
+            }
+            let last = start + removed - 1;
+            if last < fold.0 {
+                *fold = (shift(fold.0), shift(fold.1));
+            } else if start == fold.0 && last == fold.0 {
+                fold.1 = shift(fold.1);
+            } else if start <= fold.1 {
+                return false;
+            }
+            true
+        });
+    }
+    fn hiding(&self, line: usize) -> Option<(usize, usize)> {
+        self.closed.iter()
+            .filter(|&&(start, end)| start < line && line <= end)
+            .min_by_key(|&&(start, _)| start)
+            .copied()
+    }
+    pub fn is_hidden(&self, line: usize) -> bool {
+        self.hiding(line).is_some()
+    }
+    pub fn visible_line(&self, line: usize) -> usize {
+        self.hiding(line).map_or(line, |(start, _)| start)
+    }
+    pub fn next_visible(&self, line: usize, len_lines: usize) -> Option<usize> {
+        let end = self.closed.iter()
+            .filter(|&&(start, end)| start <= line && line < end)
+            .map(|&(_, end)| end)
+            .max()
+            .unwrap_or(line);
+        let next = end + 1;
+        if next >= len_lines {
+            None
+        } else if self.is_hidden(next) {
+            self.next_visible(next, len_lines)
+        } else {
+            Some(next)
+        }
+    }
+    pub fn prev_visible(&self, line: usize) -> Option<usize> {
+        line.checked_sub(1).map(|line| self.visible_line(line))
+    }
+    pub fn step(&self, line: usize, count: isize, len_lines: usize) -> usize {
+        let mut line = self.visible_line(line);
+        for _ in 0..count.unsigned_abs() {
+            let next = if count < 0 { self.prev_visible(line) } else { self.next_visible(line, len_lines) };
+            match next {
+                Some(next) => line = next,
+                None => break,
+            }
+        }
+        line
+    }
+    pub fn rows_between(&self, from: usize, to: usize, len_lines: usize) -> usize {
+        let mut rows = 0;
+        let mut line = self.visible_line(from);
+        while line < to {
+            rows += 1;
+            match self.next_visible(line, len_lines) {
+                Some(next) => line = next,
+                None => break,
+            }
+        }
+        rows
+    }
+    pub fn marker(&self, line: usize) -> Option<bool> {
+        if self.closed.iter().any(|&(start, _)| start == line) {
+            Some(true)
+        } else {
+            self.ranges.iter().any(|&(start, _)| start == line).then_some(false)
+        }
+    }
+    pub fn fold(&mut self, line: usize) -> Option<(usize, usize)> {
+        let range = self.ranges.iter()
+            .filter(|&&(start, end)| start <= line && line <= end && !self.closed.contains(&(start, end)))
+            .max_by_key(|&&(start, _)| start)
+            .copied()?;
+        let index = self.closed.partition_point(|&fold| fold < range);
+        self.closed.insert(index, range);
+        Some(range)
+    }
+    pub fn unfold(&mut self, line: usize) -> bool {
+        let len = self.closed.len();
+        self.closed.retain(|&(start, _)| start != line);
+        self.closed.len() != len
+    }
+    pub fn reveal(&mut self, line: usize) {
+        self.closed.retain(|&(start, end)| !(start < line && line <= end));
+    }
+    pub fn toggle_all(&mut self) -> bool {
+        if self.closed.is_empty() {
+            self.closed = self.ranges.clone();
+        } else {
+            self.closed.clear();
+        }
+        !self.closed.is_empty()
+    }
+}
+fn indent_ranges(content: &TextBuffer, tab_width: usize) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    let mut last_line = 0;
+    for (line_idx, line) in content.lines().enumerate() {
+        let Some(text_col) = line.chars().position(|c| !c.is_whitespace()) else {
+            continue;
+        };
+        let indent = content.display_col((text_col, line_idx), tab_width);
+        while let Some(&(open_indent, start)) = open.last() {
+            if open_indent < indent {
+                break;
+            }
+            open.pop();
+            if last_line > start {
+                ranges.push((start, last_line));
+            }
+        }
+        open.push((indent, line_idx));
+        last_line = line_idx;
+    }
+    for (_, start) in open {
+        if last_line > start {
+            ranges.push((start, last_line));
+        }
+    }
+    ranges.sort();
+    ranges
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn closed(folds: &[(usize, usize)]) -> Folds {
+        Folds { closed: folds.to_vec(), ..Folds::default() }
+    }
+    fn indented() -> TextBuffer {
+        TextBuffer::from_text("fn a\n    one\n    two\nfn b\n    three")
+    }
+
+    #[test]
+    fn shift_closed_moves_folds_below_edit() {
+        let mut folds = closed(&[(2, 4), (6, 8)]);
+        folds.shift_closed(0, 0, 2);
+        assert_eq!(folds.closed, vec![(4, 6), (8, 10)]);
+        folds.shift_closed(0, 3, 1);
+        assert_eq!(folds.closed, vec![(2, 4), (6, 8)]);
+    }
+    #[test]
+    fn shift_closed_keeps_fold_edited_on_its_first_line() {
+        let mut folds = closed(&[(2, 4)]);
+        folds.shift_closed(2, 1, 1);
+        assert_eq!(folds.closed, vec![(2, 4)]);
+        folds.shift_closed(2, 1, 3);
+        assert_eq!(folds.closed, vec![(2, 6)]);
+    }
+    #[test]
+    fn shift_closed_drops_folds_touched_inside() {
+        let mut folds = closed(&[(2, 4), (6, 8)]);
+        folds.shift_closed(3, 1, 1);
+        assert_eq!(folds.closed, vec![(6, 8)]);
+        folds.shift_closed(5, 2, 1);
+        assert!(folds.closed.is_empty());
+    }
+    #[test]
+    fn shift_closed_ignores_edits_below() {
+        let mut folds = closed(&[(2, 4)]);
+        folds.shift_closed(5, 1, 3);
+        folds.shift_closed(5, 0, 2);
+        assert_eq!(folds.closed, vec![(2, 4)]);
+    }
+    #[test]
+    fn shift_ranges_grows_enclosing_range() {
+        let mut folds = Folds { ranges: vec![(0, 5), (2, 3), (7, 9)], ..Folds::default() };
+        folds.shift_ranges(3, 1, 2);
+        assert_eq!(folds.ranges, vec![(0, 6), (2, 4), (8, 10)]);
+    }
+    #[test]
+    fn closed_folds_follow_edits_before_recompute() {
+        let mut content = indented();
+        let mut folds = Folds::default();
+        folds.update(&content, None, 4);
+        assert_eq!(folds.ranges, vec![(0, 2), (3, 4)]);
+        assert_eq!(folds.fold(1), Some((0, 2)));
+        assert!(folds.is_hidden(2));
+        content.insert((0, 0), "use x;\n");
+        folds.update(&content, None, 4);
+        assert_eq!(folds.closed, vec![(1, 3)]);
+        assert_eq!(folds.marker(1), Some(true));
+        assert_eq!(folds.visible_line(3), 1);
+    }
+    #[test]
+    fn settle_recomputes_ranges() {
+        let mut content = indented();
+        let mut folds = Folds::default();
+        folds.update(&content, None, 4);
+        content.insert((9, 1), "\n    four");
+        folds.update(&content, None, 4);
+        assert!(folds.last_edit.is_some());
+        folds.settle(&content, None, 4);
+        assert!(folds.last_edit.is_none());
+        assert_eq!(folds.ranges, vec![(0, 3), (4, 5)]);
+    }
+    #[test]
+    fn toggle_all_folds_every_range() {
+        let content = indented();
+        let mut folds = Folds::default();
+        folds.update(&content, None, 4);
+        assert!(folds.toggle_all());
+        assert_eq!(folds.step(0, 1, content.len_lines()), 3);
+        assert!(!folds.toggle_all());
+        assert_eq!(folds.step(0, 1, content.len_lines()), 1);
+    }
+}
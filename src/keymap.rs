@@ -0,0 +1,357 @@
+use crate::commands::{info_for, Command, Context, COMMANDS};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{fs, path::Path};
+
+pub type KeyChord = (KeyCode, KeyModifiers);
+
+pub struct Binding {
+    pub context: Context,
+    pub keys: Vec<KeyChord>,
+    pub command: Option<Command>,
+}
+pub enum KeyMatch {
+    Command(Command),
+    Pending,
+    Unbound,
+}
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = COMMANDS.iter()
+            .flat_map(|info| info.keys.iter().map(move |text| (info, text)))
+            .filter_map(|(info, text)| {
+                Some(Binding {
+                    context: info.context,
+                    keys: parse_sequence(text)?,
+                    command: Some(info.command),
+                })
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+impl Keymap {
+    pub fn load(path: &Path) -> (Self, Vec<String>) {
+        let mut keymap = Self::default();
+        let mut problems = Vec::new();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return (keymap, problems),
+            Err(e) => {
+                problems.push(format!("{}: {}", path.display(), e));
+                return (keymap, problems);
+            }
+        };
+        let table = match text.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(e) => {
+                problems.push(format!("{}: {}", path.display(), e.message()));
+                return (keymap, problems);
+            }
+        };
+        let mut user_bindings: Vec<Binding> = Vec::new();
+        for (section, entries) in &table {
+            let Some(context) = Context::ALL.into_iter().find(|context| context.name() == section) else {
+                problems.push(format!("unknown context [{}]", section));
+                continue;
+            };
+            let Some(entries) = entries.as_table() else {
+                problems.push(format!("[{}] must be a table of \"keys\" = \"action\"", section));
+                continue;
+            };
+            for (keys_text, action) in entries {
+                let Some(keys) = parse_sequence(keys_text) else {
+                    problems.push(format!("[{}] \"{}\": invalid key", section, keys_text));
+                    continue;
+                };
+                if keys.len() > 1 && matches!(context, Context::Search | Context::ProjectSearch) {
+                    problems.push(format!("[{}] \"{}\": key sequences are not supported in prompts", section, keys_text));
+                    continue;
+                }
+                let command = match action.as_str() {
+                    Some("none") => None,
+                    Some(name) => match COMMANDS.iter().find(|info| info.id() == name) {
+                        Some(info) => Some(info.command),
+                        None => {
+                            problems.push(format!("[{}] \"{}\": unknown action \"{}\"", section, keys_text, name));
+                            continue;
+                        }
+                    },
+                    None => {
+                        problems.push(format!("[{}] \"{}\": action must be a string", section, keys_text));
+                        continue;
+                    }
+                };
+                if let Some(existing) = user_bindings.iter().find(|binding| binding.context == context && binding.keys == keys) {
+                    if existing.command != command {
+                        problems.push(format!("[{}] \"{}\" is bound more than once", section, format_sequence(&keys)));
+                    }
+                    continue;
+                }
+                user_bindings.push(Binding { context, keys, command });
+            }
+        }
+        keymap.bindings.retain(|binding| {
+            !user_bindings.iter().any(|user| user.context == binding.context && user.keys == binding.keys)
+        });
+        keymap.bindings.extend(user_bindings);
+        problems.extend(keymap.conflicts());
+        (keymap, problems)
+    }
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        for (i, first) in self.bindings.iter().enumerate() {
+            for second in &self.bindings[i + 1..] {
+                if first.command.is_none() || second.command.is_none() || first.command == second.command {
+                    continue;
+                }
+                if !first.context.overlaps(second.context) {
+                    continue;
+                }
+                let shorter = first.keys.len().min(second.keys.len());
+                if first.keys[..shorter] != second.keys[..shorter] {
+                    continue;
+                }
+                let describe = |binding: &Binding| format!(
+                    "\"{}\" ({}: {})",
+                    format_sequence(&binding.keys),
+                    binding.context.name(),
+                    binding.command.map_or_else(String::new, |command| info_for(command).id())
+                );
+                conflicts.push(format!("{} conflicts with {}", describe(first), describe(second)));
+            }
+        }
+        conflicts
+    }
+    pub fn resolve(&self, keys: &[KeyEvent], contexts: &[Context]) -> KeyMatch {
+        for &context in contexts {
+            let exact = self.bindings.iter().find(|binding| {
+                binding.context == context
+                    && binding.keys.len() == keys.len()
+                    && binding.keys.iter().zip(keys).all(|(chord, key)| chord_matches(chord, key))
+            });
+            if let Some(binding) = exact {
+                return binding.command.map_or(KeyMatch::Unbound, KeyMatch::Command);
+            }
+        }
+        let pending = self.bindings.iter().any(|binding| {
+            contexts.contains(&binding.context)
+                && binding.command.is_some()
+                && binding.keys.len() > keys.len()
+                && binding.keys.iter().zip(keys).all(|(chord, key)| chord_matches(chord, key))
+        });
+        if pending {
+            KeyMatch::Pending
+        } else {
+            KeyMatch::Unbound
+        }
+    }
+    pub fn lookup(&self, key: &KeyEvent, contexts: &[Context]) -> Option<Command> {
+        match self.resolve(std::slice::from_ref(key), contexts) {
+            KeyMatch::Command(command) => Some(command),
+            _ => None,
+        }
+    }
+    pub fn keys_for(&self, command: Command) -> Vec<String> {
+        self.bindings.iter()
+            .filter(|binding| binding.command == Some(command))
+            .map(|binding| format_sequence(&binding.keys))
+            .collect()
+    }
+}
+pub fn parse_key(text: &str) -> Option<KeyChord> {
+    let (modifiers_text, key) = match text.strip_suffix("++") {
+        Some(rest) => (rest, "+"),
+        None => text.rsplit_once('+').unwrap_or(("", text)),
+    };
+    let mut modifiers = KeyModifiers::NONE;
+    for modifier in modifiers_text.split('+').filter(|part| !part.is_empty()) {
+        modifiers |= match modifier.to_ascii_lowercase().as_str() {
+            "ctrl" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+    }
+    let code = match key.to_ascii_lowercase().as_str() {
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pgup" | "pageup" => KeyCode::PageUp,
+        "pgdn" | "pagedown" => KeyCode::PageDown,
+        "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+        "tab" => KeyCode::Tab,
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "space" => KeyCode::Char(' '),
+        lower if lower.len() > 1 && lower.starts_with('f') => KeyCode::F(lower[1..].parse().ok()?),
+        _ => {
+            let mut chars = key.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            KeyCode::Char(c)
+        }
+    };
+    Some(normalize(code, modifiers))
+}
+pub fn parse_sequence(text: &str) -> Option<Vec<KeyChord>> {
+    let keys: Vec<KeyChord> = text.split_whitespace().map(parse_key).collect::<Option<_>>()?;
+    (!keys.is_empty()).then_some(keys)
+}
+pub fn format_key((code, modifiers): &KeyChord) -> String {
+    let mut text = String::new();
+    for (modifier, label) in [(KeyModifiers::CONTROL, "Ctrl+"), (KeyModifiers::ALT, "Alt+"), (KeyModifiers::SHIFT, "Shift+")] {
+        if modifiers.contains(modifier) {
+            text.push_str(label);
+        }
+    }
+    match code {
+        KeyCode::Char(' ') => text.push_str("Space"),
+        KeyCode::Char(c) => text.push(*c),
+        KeyCode::F(n) => text.push_str(&format!("F{}", n)),
+        KeyCode::BackTab => text.push_str("Shift+Tab"),
+        KeyCode::PageUp => text.push_str("PgUp"),
+        KeyCode::PageDown => text.push_str("PgDn"),
+        other => text.push_str(&format!("{:?}", other)),
+    }
+    text
+}
+pub fn chord_of(key: &KeyEvent) -> KeyChord {
+    normalize(key.code, key.modifiers)
+}
+fn normalize(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+    match code {
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+            (KeyCode::Char(c.to_ascii_uppercase()), modifiers - KeyModifiers::SHIFT)
+        }
+        KeyCode::BackTab => (code, modifiers - KeyModifiers::SHIFT),
+        _ => (code, modifiers),
+    }
+}
+pub fn format_sequence(keys: &[KeyChord]) -> String {
+    keys.iter().map(format_key).collect::<Vec<_>>().join(" ")
+}
+fn chord_matches(chord: &KeyChord, key: &KeyEvent) -> bool {
+    *chord == chord_of(key)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+    fn load(text: &str) -> (Keymap, Vec<String>) {
+        let path = std::env::temp_dir().join(format!("red-keymap-{}-{:x}.toml", std::process::id(), md5::compute(text)));
+        fs::write(&path, text).unwrap();
+        let loaded = Keymap::load(&path);
+        fs::remove_file(&path).unwrap();
+        loaded
+    }
+    fn editor_lookup(keymap: &Keymap, code: KeyCode, modifiers: KeyModifiers) -> Option<Command> {
+        keymap.lookup(&key(code, modifiers), &[Context::Editor, Context::Global])
+    }
+
+    #[test]
+    fn parse_key_reads_modifiers_and_names() {
+        assert_eq!(parse_key("Ctrl+Alt+Left"), Some((KeyCode::Left, KeyModifiers::CONTROL | KeyModifiers::ALT)));
+        assert_eq!(parse_key("ctrl+PageDown"), Some((KeyCode::PageDown, KeyModifiers::CONTROL)));
+        assert_eq!(parse_key("Ctrl++"), Some((KeyCode::Char('+'), KeyModifiers::CONTROL)));
+        assert_eq!(parse_key("F12"), Some((KeyCode::F(12), KeyModifiers::NONE)));
+        assert_eq!(parse_key("Shift+Tab"), Some((KeyCode::BackTab, KeyModifiers::NONE)));
+        assert_eq!(parse_key("Space"), Some((KeyCode::Char(' '), KeyModifiers::NONE)));
+        assert_eq!(parse_key("Hyper+x"), None);
+        assert_eq!(parse_key("Ctrl+ab"), None);
+        assert_eq!(parse_key("Fx"), None);
+    }
+    #[test]
+    fn shifted_letters_normalize_to_uppercase() {
+        assert_eq!(parse_key("Alt+Shift+l"), parse_key("Alt+L"));
+        assert_ne!(parse_key("Alt+l"), parse_key("Alt+L"));
+        assert_eq!(chord_of(&key(KeyCode::Char('l'), KeyModifiers::ALT | KeyModifiers::SHIFT)), (KeyCode::Char('L'), KeyModifiers::ALT));
+    }
+    #[test]
+    fn parse_sequence_splits_on_whitespace() {
+        let keys = parse_sequence("Ctrl+k  s").unwrap();
+        assert_eq!(keys, vec![(KeyCode::Char('k'), KeyModifiers::CONTROL), (KeyCode::Char('s'), KeyModifiers::NONE)]);
+        assert_eq!(format_sequence(&keys), "Ctrl+k s");
+        assert_eq!(parse_sequence("  "), None);
+        assert_eq!(parse_sequence("Ctrl+k bogus"), None);
+    }
+    #[test]
+    fn modifiers_must_match_exactly() {
+        let keymap = Keymap::default();
+        assert_eq!(editor_lookup(&keymap, KeyCode::Left, KeyModifiers::CONTROL), Some(Command::WordLeft));
+        assert_eq!(editor_lookup(&keymap, KeyCode::Left, KeyModifiers::CONTROL | KeyModifiers::SHIFT), None);
+        assert_eq!(editor_lookup(&keymap, KeyCode::Down, KeyModifiers::ALT), Some(Command::CursorBelow));
+        assert_eq!(editor_lookup(&keymap, KeyCode::Down, KeyModifiers::ALT | KeyModifiers::SHIFT), Some(Command::NextSibling));
+        assert_eq!(editor_lookup(&keymap, KeyCode::Home, KeyModifiers::SHIFT), Some(Command::LineStart));
+        assert_eq!(editor_lookup(&keymap, KeyCode::Char('l'), KeyModifiers::ALT), Some(Command::ToggleLineNumbers));
+        assert_eq!(editor_lookup(&keymap, KeyCode::Char('L'), KeyModifiers::ALT | KeyModifiers::SHIFT), Some(Command::SelectLine));
+        assert_eq!(editor_lookup(&keymap, KeyCode::BackTab, KeyModifiers::SHIFT), Some(Command::Outdent));
+    }
+    #[test]
+    fn sequences_resolve_after_prefix() {
+        let keymap = Keymap::default();
+        let contexts = [Context::Editor, Context::Global];
+        let prefix = key(KeyCode::Char('k'), KeyModifiers::CONTROL);
+        assert!(matches!(keymap.resolve(&[prefix], &contexts), KeyMatch::Pending));
+        let symbol = [prefix, key(KeyCode::Char('s'), KeyModifiers::NONE)];
+        assert!(matches!(keymap.resolve(&symbol, &contexts), KeyMatch::Command(Command::GoToSymbol)));
+        let unbound = [prefix, key(KeyCode::Char('q'), KeyModifiers::NONE)];
+        assert!(matches!(keymap.resolve(&unbound, &contexts), KeyMatch::Unbound));
+    }
+    #[test]
+    fn default_keymap_has_no_conflicts() {
+        assert_eq!(Keymap::default().conflicts(), Vec::<String>::new());
+    }
+    #[test]
+    fn user_bindings_override_defaults() {
+        let (keymap, problems) = load("[editor]\n\"Ctrl+z\" = \"redo\"\n\"Ctrl+y\" = \"none\"\n\"Ctrl+k u\" = \"undo\"\n");
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(editor_lookup(&keymap, KeyCode::Char('z'), KeyModifiers::CONTROL), Some(Command::Redo));
+        assert_eq!(editor_lookup(&keymap, KeyCode::Char('y'), KeyModifiers::CONTROL), None);
+        assert_eq!(keymap.keys_for(Command::Undo), vec!["Ctrl+k u"]);
+    }
+    #[test]
+    fn invalid_entries_are_reported() {
+        let (keymap, problems) = load(concat!(
+            "[editor]\n",
+            "\"Ctrl+Bogus\" = \"undo\"\n",
+            "\"Ctrl+u\" = \"no_such_action\"\n",
+            "\"Ctrl+j\" = 3\n",
+            "[search]\n",
+            "\"Ctrl+k r\" = \"toggle_regex\"\n",
+            "[nowhere]\n",
+            "\"Ctrl+u\" = \"undo\"\n",
+        ));
+        assert_eq!(problems, vec![
+            "[editor] \"Ctrl+Bogus\": invalid key",
+            "[editor] \"Ctrl+j\": action must be a string",
+            "[editor] \"Ctrl+u\": unknown action \"no_such_action\"",
+            "unknown context [nowhere]",
+            "[search] \"Ctrl+k r\": key sequences are not supported in prompts",
+        ]);
+        assert_eq!(editor_lookup(&keymap, KeyCode::Char('z'), KeyModifiers::CONTROL), Some(Command::Undo));
+    }
+    #[test]
+    fn same_chord_bound_twice_is_reported() {
+        let (_, problems) = load("[editor]\n\"Alt+Shift+j\" = \"undo\"\n\"Alt+J\" = \"redo\"\n");
+        assert_eq!(problems, vec!["[editor] \"Alt+J\" is bound more than once"]);
+    }
+    #[test]
+    fn conflicts_report_shadowed_bindings() {
+        let (_, problems) = load("[global]\n\"Ctrl+z\" = \"quit\"\n[editor]\n\"Ctrl+k\" = \"save\"\n");
+        assert!(problems.iter().any(|problem| problem == "\"Ctrl+z\" (editor: undo) conflicts with \"Ctrl+z\" (global: quit)"));
+        assert!(problems.iter().any(|problem| problem == "\"Ctrl+k s\" (editor: go_to_symbol) conflicts with \"Ctrl+k\" (editor: save)"));
+    }
+}
@@ -1,3 +1,12 @@
+mod buffer;
+mod commands;
+mod config;
+mod folding;
+mod keymap;
+mod search;
+mod symbols;
+mod syntax;
+mod undo;
 use crossterm::{
     event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     terminal,
@@ -15,10 +24,24 @@ use std::{
     collections::{HashSet, HashMap},
     fs::File,
     io::Write,
+    sync::mpsc::{Receiver, TryRecvError},
 };
-use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use thiserror::Error;
 use chrono::Local;
+use serde::{Deserialize, Serialize};
+use regex::{Regex, RegexBuilder};
+use buffer::TextBuffer;
+use commands::{Command, Context, COMMANDS};
+use config::Config;
+use folding::Folds;
+use keymap::{KeyMatch, Keymap};
+use undo::{MultiLineDelta, UndoTree};
+use search::SearchHit;
+use symbols::SymbolIndex;
+use syntax::{Highlighter, Symbol};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 #[derive(Debug, Error)]
 pub enum EditorError {
     #[error("IO error: {0}")]
@@ -39,31 +62,50 @@ enum PopupType {
     Help,
     SaveConfirm(SaveAction),
     OverwriteConfirm(String),
+    LossySaveConfirm,
     Find,
     Open,
     InitialMenu,
     ToolMenu,
     RecentFiles,
     JumpToLine,
+    Settings,
     Replace,
     FileChanged,
     ReplaceQuery,
     ReplaceWithQuery,
     NewFile,
     NewDirectory,
+    EncodingSelect(EncodingAction),
+    UndoTree,
+    QueryReplace,
+    ProjectSearch,
+    ProjectReplace,
+    ProjectReplaceReport,
+    FileFinder,
+    GoToSymbol,
+    CommandPalette,
 }
 #[derive(Debug, PartialEq)]
 enum EditorMode {
     Normal,
+    #[allow(dead_code)]
     Insert,
+    #[allow(dead_code)]
     Command,
     Visual,
+    VisualBlock,
     Replace,
 }
 #[derive(Debug, PartialEq, Clone)]
 enum SaveAction {
     Exit,
-    OpenFile,
+    CloseTab,
+}
+#[derive(Debug, PartialEq, Clone)]
+enum EncodingAction {
+    Reopen,
+    Save,
 }
 #[derive(PartialEq, Clone)]
 struct RecentFile {
@@ -76,47 +118,76 @@ struct FileEntry {
     name: String,
     path: PathBuf,
     is_dir: bool,
-    is_selected: bool,
     depth: usize,
 }
-#[derive(Clone)]
+#[derive(Clone, Default)]
 struct EditorTab {
-    content: Vec<String>,
+    content: TextBuffer,
     cursor_position: (usize, usize),
     filename: Option<PathBuf>,
     modified: bool,
-    scroll_offset: u16,
+    scroll_offset: usize,
+    undo_tree: UndoTree,
+    last_save_state: Option<TextBuffer>,
+    current_syntax: Option<String>,
+    highlighter: Option<Highlighter>,
+    folds: Folds,
+    last_modified: Option<SystemTime>,
+    last_save_time: Option<SystemTime>,
+}
+type TextRange = ((usize, usize), (usize, usize));
+#[derive(Clone, Copy, Default)]
+struct SearchOptions {
+    regex: bool,
+    ignore_case: bool,
+    whole_word: bool,
+}
+struct QueryReplaceMatch {
+    start: usize,
+    original: String,
+    replacement: String,
+    pending: bool,
 }
 #[derive(Clone)]
 struct EditorSplit {
     tab_index: usize,
     size: u16,
     is_horizontal: bool,
+    cursor_position: (usize, usize),
+    scroll_offset: usize,
 }
-#[derive(Clone)]
-struct TextDelta {
-    line_index: usize,
-    old_line: String,
-    new_line: String,
-    cursor_before: (usize, usize),
-    cursor_after: (usize, usize),
-    timestamp: Instant,
+struct PaneView<'a> {
+    content: &'a TextBuffer,
+    filename: Option<&'a Path>,
+    syntax: Option<&'a str>,
+    highlighter: Option<&'a Highlighter>,
+    folds: &'a Folds,
+    cursor_position: (usize, usize),
+    scroll_offset: usize,
+    matches: &'a [TextRange],
+    selection: Option<((usize, usize), (usize, usize))>,
+    preview: Option<TextRange>,
+    block: Option<(usize, usize, usize, usize)>,
+    cursors: &'a [(usize, usize)],
+    focused: bool,
+    tab_width: usize,
 }
 struct Editor {
-    content: Vec<String>,
+    content: TextBuffer,
     cursor_position: (usize, usize),
     filename: Option<PathBuf>,
-    undo_stack: Vec<(Vec<String>, (usize, usize))>,
-    redo_stack: Vec<(Vec<String>, (usize, usize))>,
+    undo_tree: UndoTree,
     terminal: Terminal<CrosstermBackend<std::io::Stdout>>,
     popup_state: PopupType,
     temp_filename: String,
     status_message: Option<(String, std::time::Instant)>,
-    scroll_offset: u16,
+    scroll_offset: usize,
     modified: bool,
     search_query: String,
     search_index: Option<usize>,
-    highlighted_matches: Vec<(usize, usize)>,
+    highlighted_matches: Vec<TextRange>,
+    search_options: SearchOptions,
+    search_error: Option<String>,
     recent_files: Vec<RecentFile>,
     initial_menu_selection: usize,
     show_initial_menu: bool,
@@ -128,40 +199,123 @@ struct Editor {
     syntax_set: SyntaxSet,
     theme_set: ThemeSet,
     current_syntax: Option<String>,
+    highlighter: Option<Highlighter>,
+    folds: Folds,
+    #[allow(dead_code)]
     suggestion_matcher: SkimMatcherV2,
     suggestions: Vec<String>,
     showing_suggestions: bool,
     suggestion_index: usize,
     word_database: HashMap<String, f64>,
     language_keywords: HashSet<String>,
+    #[allow(dead_code)]
     last_search: String,
     mode: EditorMode,
+    selection_anchor: Option<(usize, usize)>,
+    syntax_selections: Vec<(TextRange, TextRange)>,
+    extra_cursors: Vec<(usize, usize)>,
+    block_clipboard: Option<Vec<String>>,
     show_tree: bool,
     tree_focused: bool,
+    show_outline: bool,
+    outline_symbols: Vec<Symbol>,
+    outline_revision: Option<u64>,
+    outline_edit: Option<(u64, Instant)>,
+    outline_query: String,
+    outline_results: Vec<(usize, Vec<usize>)>,
+    outline_selection: usize,
+    symbol_index: Option<SymbolIndex>,
+    symbol_index_receiver: Option<Receiver<(SymbolIndex, usize)>>,
+    symbol_project: bool,
+    symbol_query: String,
+    symbol_entries: Vec<(Option<PathBuf>, String, Symbol)>,
+    symbol_results: Vec<(usize, Vec<usize>)>,
+    symbol_selection: usize,
+    #[allow(dead_code)]
     show_minimap: bool,
     show_status: bool,
     show_numbers: bool,
+    #[allow(dead_code)]
     is_fullscreen: bool,
     active_tab: usize,
     tabs: Vec<EditorTab>,
     splits: Vec<EditorSplit>,
+    focused_split: usize,
+    pane_areas: Vec<Rect>,
     last_file_check: Instant,
     last_modified: Option<SystemTime>,
     last_save_time: Option<SystemTime>,
     tool_menu_selection: usize,
+    encoding_selection: usize,
     tools: Vec<(&'static str, &'static str, &'static str)>,
     replace_text: String,
     current_match_index: usize,
+    query_replace_matches: Vec<QueryReplaceMatch>,
+    query_replace_count: usize,
+    project_search_query: String,
+    project_search_hits: Vec<SearchHit>,
+    project_search_selection: usize,
+    project_search_receiver: Option<Receiver<SearchHit>>,
+    project_search_dirty: bool,
+    project_replace_text: String,
+    project_replace_checked: Vec<bool>,
+    project_replace_report: Vec<(PathBuf, String)>,
+    file_finder_query: String,
+    file_finder_files: Vec<(PathBuf, String, i64)>,
+    file_index: Option<(PathBuf, Vec<PathBuf>)>,
+    file_index_receiver: Option<Receiver<(PathBuf, Vec<PathBuf>)>>,
+    file_finder_results: Vec<(usize, Vec<usize>)>,
+    file_finder_selection: usize,
+    palette_query: String,
+    palette_results: Vec<(usize, Vec<usize>)>,
+    palette_selection: usize,
+    keymap: Keymap,
+    pending_keys: Vec<KeyEvent>,
+    config: Config,
+    config_modified: Option<SystemTime>,
+    settings_selection: usize,
     file_tree_scroll_offset: u16,
-    last_save_state: Option<Vec<String>>,
+    last_save_state: Option<TextBuffer>,
+    last_save_cursor: (usize, usize),
+    undo_tree_selection: usize,
+    undo_tree_origin: usize,
+    undo_time_input: String,
+    typing_group: Option<(char, (usize, usize))>,
     last_edit_time: Instant,
+    #[allow(dead_code)]
     current_file_path: Option<PathBuf>,
 }
 use syntect::{
-    easy::HighlightLines,
-    highlighting::ThemeSet,
-    parsing::SyntaxSet,
+    easy::{HighlightLines, ScopeRangeIterator},
+    highlighting::{Theme, ThemeSet},
+    parsing::{ParseState, Scope, ScopeStack, SyntaxSet},
 };
+const MAX_FILE_SIZE: u64 = 100 * 1024 * 1024;
+const WORD_DATABASE_RADIUS: usize = 1000;
+const UNDO_HISTORY_FILE_LIMIT: u64 = 1024 * 1024;
+const UNDO_HISTORY_DIR_LIMIT: u64 = 32 * 1024 * 1024;
+const FILE_FINDER_LIMIT: usize = 500;
+const SYMBOL_FINDER_LIMIT: usize = 500;
+const OUTLINE_DELAY: Duration = Duration::from_millis(300);
+const SYNTECT_OUTLINE_LINE_LIMIT: usize = 20_000;
+const ENCODINGS: [&str; 13] = [
+    "UTF-8",
+    "UTF-16LE",
+    "UTF-16BE",
+    "windows-1252",
+    "ISO-8859-2",
+    "ISO-8859-15",
+    "windows-1251",
+    "KOI8-R",
+    "Shift_JIS",
+    "EUC-JP",
+    "GBK",
+    "Big5",
+    "EUC-KR",
+];
+fn encoding_for_label(label: &str) -> &'static encoding_rs::Encoding {
+    encoding_rs::Encoding::for_label(label.as_bytes()).unwrap_or(encoding_rs::UTF_8)
+}
 const RED_LOGO: &str = r#"
    ██▀███  ▓█████ ▓████▄
   ▓██ ▒ ██▒▓█   ▀ ▒██▀ ██▌
@@ -181,50 +335,6 @@ const ICONS: &[(&str, &str)] = &[
     ("󰋖", "Help"),
     ("󰗼", "Exit"),
 ];
-const STATUS_ICONS: &[(&str, &str)] = &[
-    ("󰆍", "Modified"),
-    ("󰆍", "Saved"),
-    ("󰆍", "Read Only"),
-    ("󰆍", "Error"),
-];
-const HELP_TEXT: &[(&str, &str, &str)] = &[
-    ("Navigation", "", ""),
-    ("←↑↓→", "Move cursor", "Basic movement"),
-    ("Ctrl+←/→", "Word jump", "Move by words"),
-    ("Home/End", "Line edges", "Jump to start/end of line"),
-    ("PgUp/PgDn", "Page scroll", "Move by pages"),
-    ("File", "", ""),
-    ("Ctrl+s", "Save", "Save current file"),
-    ("Alt+o", "Open", "Open file"),
-    ("Alt+w", "Close", "Close current file"),
-    ("Alt+q", "Quit", "Exit editor"),
-    ("Layout", "", ""),
-    ("Alt+b", "Tree View", "Toggle file explorer sidebar"),
-    ("Alt+l", "Line Numbers", "Toggle line number gutter"),
-    ("Editing", "", ""),
-    ("Ctrl+x", "Cut line", "Cut current line"),
-    ("Ctrl+c", "Copy line", "Copy current line"),
-    ("Ctrl+v", "Paste line", "Paste from clipboard"),
-    ("Ctrl+z", "Undo", "Undo last action"),
-    ("Ctrl+y", "Redo", "Redo last action"),
-    ("Selection", "", ""),
-    ("Alt+a", "Select all", "Select entire file"),
-    ("Alt+L", "Select line", "Select current line"),
-    ("Alt+W", "Select word", "Select current word"),
-    ("Search", "", ""),
-    ("Ctrl+f", "Find", "Search in file"),
-    ("Ctrl+r", "Replace", "Search and replace"),
-    ("Alt+n", "Next match", "Go to next match"),
-    ("File Tree", "", ""),
-    ("Alt+e", "Switch to explorer", "Switch to file explorer window"),
-    ("Alt+n", "New file", "Create new file"),
-    ("Alt+d", "New directory", "Create new directory"),
-    ("Alt+r", "Rename", "Rename selected item"),
-    ("Extra", "", ""),
-    ("Alt+t", "Tool Menu", "Open tool menu"),
-    ("Alt+p", "Settings", "Open settings"),
-    ("Alt+h", "Help", "Show this help")
-];
 const FOLDER_ICONS: &[(&str, &str)] = &[
     ("node_modules", "󰉋"),
     ("src", "󰉋"),
@@ -278,16 +388,12 @@ const FILE_ICONS: &[(&str, &str)] = &[
     ("lock", ""),
     ("", ""),
 ];
-struct MultiLineDelta {
-    start_line: usize,
-    old_lines: Vec<String>,
-    new_lines: Vec<String>,
-    cursor_before: (usize, usize),
-    cursor_after: (usize, usize),
-    timestamp: Instant,
-    file_id: Option<PathBuf>,
+#[derive(Serialize, Deserialize)]
+struct UndoHistory {
+    path: PathBuf,
+    content_hash: [u8; 16],
+    tree: UndoTree,
 }
-use crossterm::execute;
 use crossterm::terminal::{ClearType};
 use crossterm::cursor;
 impl Editor {
@@ -303,11 +409,8 @@ impl Editor {
             }
             if path.exists() {
                 let metadata = fs::metadata(path)?;
-                if metadata.len() > 100 * 1024 * 1024 {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        "File is too large (>100MB)"
-                    ));
+                if metadata.len() > MAX_FILE_SIZE {
+                    return Err(std::io::Error::other("File is too large (>100MB)"));
                 }
             }
             if path.exists() {
@@ -322,38 +425,38 @@ impl Editor {
                         ));
                     }
                 }
-            } else {
-                if let Some(parent) = path.parent() {
-                    if parent.exists() {
-                        if let Err(e) = std::fs::OpenOptions::new()
-                            .write(true)
-                            .create(true)
-                            .open(parent.join(".red_test_file"))
-                        {
-                            if e.kind() == std::io::ErrorKind::PermissionDenied {
-                                return Err(std::io::Error::new(
-                                    std::io::ErrorKind::PermissionDenied,
-                                    "Permission denied"
-                                ));
-                            }
-                        } else {
-                            let _ = std::fs::remove_file(parent.join(".red_test_file"));
+            } else if let Some(parent) = path.parent() {
+                if parent.exists() {
+                    if let Err(e) = std::fs::OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(false)
+                        .open(parent.join(".red_test_file"))
+                    {
+                        if e.kind() == std::io::ErrorKind::PermissionDenied {
+                            return Err(std::io::Error::new(
+                                std::io::ErrorKind::PermissionDenied,
+                                "Permission denied"
+                            ));
                         }
+                    } else {
+                        let _ = std::fs::remove_file(parent.join(".red_test_file"));
                     }
                 }
             }
-            match fs::read_to_string(path) {
-                Ok(content) => {
-                    let lines: Vec<String> = content.lines().map(String::from).collect();
-                    (if lines.is_empty() { vec![String::new()] } else { lines }, None, false)
-                },
+            match fs::read(path) {
+                Ok(bytes) => {
+                    let content = TextBuffer::from_bytes(&bytes);
+                    let warning = Self::load_warning(&content);
+                    (content, warning, false)
+                }
                 Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                    (vec![String::new()], Some(format!("New file: {}", Self::format_path(path))), false)
+                    (TextBuffer::new(), Some(format!("New file: {}", Self::format_path(path))), false)
                 },
                 Err(e) => return Err(e)
             }
         } else {
-            (vec![String::new()], None, true)
+            (TextBuffer::new(), None, true)
         };
         terminal::enable_raw_mode()?;
         let mut stdout = stdout();
@@ -364,6 +467,9 @@ impl Editor {
         let file_entries = Self::read_directory(&current_dir)?;
         let syntax_set = SyntaxSet::load_defaults_newlines();
         let theme_set = ThemeSet::load_defaults();
+        let (keymap, keymap_problems) = Self::keymap_path()
+            .map(|path| Keymap::load(&path))
+            .unwrap_or_default();
         let current_syntax = if let Some(path) = &filename {
             Self::detect_syntax(&syntax_set, path)
         } else {
@@ -389,6 +495,8 @@ impl Editor {
             search_query: String::new(),
             search_index: None,
             highlighted_matches: Vec::new(),
+            search_options: SearchOptions::default(),
+            search_error: None,
             recent_files,
             initial_menu_selection: 0,
             show_initial_menu: show_menu,
@@ -400,6 +508,8 @@ impl Editor {
             syntax_set,
             theme_set,
             current_syntax,
+            highlighter: None,
+            folds: Folds::default(),
             suggestion_matcher: SkimMatcherV2::default(),
             suggestions: Vec::new(),
             showing_suggestions: false,
@@ -408,62 +518,189 @@ impl Editor {
             language_keywords: HashSet::new(),
             last_search: String::new(),
             mode: EditorMode::Normal,
+            selection_anchor: None,
+            syntax_selections: Vec::new(),
+            extra_cursors: Vec::new(),
+            block_clipboard: None,
             show_tree: true,
             tree_focused: false,
+            show_outline: false,
+            outline_symbols: Vec::new(),
+            outline_revision: None,
+            outline_edit: None,
+            outline_query: String::new(),
+            outline_results: Vec::new(),
+            outline_selection: 0,
+            symbol_index: None,
+            symbol_index_receiver: None,
+            symbol_project: false,
+            symbol_query: String::new(),
+            symbol_entries: Vec::new(),
+            symbol_results: Vec::new(),
+            symbol_selection: 0,
             show_minimap: true,
             show_status: true,
             show_numbers: true,
             is_fullscreen: false,
             active_tab: 0,
-            tabs: Vec::new(),
-            splits: Vec::new(),
+            tabs: vec![EditorTab::default()],
+            splits: vec![EditorSplit {
+                tab_index: 0,
+                size: 50,
+                is_horizontal: false,
+                cursor_position: (0, 0),
+                scroll_offset: 0,
+            }],
+            focused_split: 0,
+            pane_areas: Vec::new(),
             last_file_check: Instant::now(),
             last_modified: None,
             last_save_time: None,
             tool_menu_selection: 0,
-            tools: vec![
-                ("  ", "Delete Comments", "Remove all comments"),
-                ("  ", "Remove Empty lines", "Remove all empty lines"),
-                ("  ", "Clear Cache", "Clear editor's cache"),
-            ],
+            encoding_selection: 0,
+            tools: COMMANDS.iter()
+                .filter(|info| info.category == "Tools")
+                .map(|info| ("󰄾", info.name, info.description))
+                .collect(),
             replace_text: String::new(),
             current_match_index: 0,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            query_replace_matches: Vec::new(),
+            query_replace_count: 0,
+            project_search_query: String::new(),
+            project_search_hits: Vec::new(),
+            project_search_selection: 0,
+            project_search_receiver: None,
+            project_search_dirty: false,
+            project_replace_text: String::new(),
+            project_replace_checked: Vec::new(),
+            project_replace_report: Vec::new(),
+            file_finder_query: String::new(),
+            file_finder_files: Vec::new(),
+            file_index: None,
+            file_index_receiver: None,
+            file_finder_results: Vec::new(),
+            file_finder_selection: 0,
+            palette_query: String::new(),
+            palette_results: Vec::new(),
+            palette_selection: 0,
+            keymap,
+            pending_keys: Vec::new(),
+            config: Config::default(),
+            config_modified: None,
+            settings_selection: 0,
+            undo_tree: UndoTree::default(),
             file_tree_scroll_offset: 0,
             last_save_state: None,
+            last_save_cursor: (0, 0),
+            undo_tree_selection: 0,
+            undo_tree_origin: 0,
+            undo_time_input: String::new(),
+            typing_group: None,
             last_edit_time: Instant::now(),
             current_file_path: None,
         };
         editor.last_save_state = Some(editor.content.clone());
+        if let Some(undo_tree) = editor.filename.as_deref()
+            .and_then(|path| Self::load_undo_history(path, &editor.content))
+        {
+            editor.undo_tree = undo_tree;
+        }
         if let Some(syntax) = editor.current_syntax.clone() {
             editor.update_word_database_for_syntax(&syntax);
         }
+        editor.load_config();
+        if let Some(problem) = keymap_problems.first() {
+            editor.log_error(&format!("Keymap problems:\n{}", keymap_problems.join("\n")));
+            let more = match keymap_problems.len() {
+                1 => String::new(),
+                n => format!(" (+{} more, see logs)", n - 1),
+            };
+            editor.set_status_message(format!("Keymap: {}{}", problem, more));
+        }
         editor.draw()?;
         Ok(editor)
     }
     fn undo(&mut self) {
-        if let Some((previous_state, previous_cursor)) = self.undo_stack.pop() {
-            self.redo_stack.push((self.content.clone(), self.cursor_position));
-            self.content = previous_state;
-            self.cursor_position = previous_cursor;
+        self.save_state();
+        if let Some(cursor) = self.undo_tree.undo(&mut self.content) {
+            self.cursor_position = self.content.clamp(cursor);
+            self.reset_undo_base();
             self.set_status_message("Undid last action.");
-            self.modified = true;
+            self.modified = !self.undo_tree.is_saved();
         } else {
             self.set_status_message("No more actions to undo.");
         }
     }
     fn redo(&mut self) {
-        if let Some((next_state, next_cursor)) = self.redo_stack.pop() {
-            self.undo_stack.push((self.content.clone(), self.cursor_position));
-            self.content = next_state;
-            self.cursor_position = next_cursor;
+        self.save_state();
+        if let Some(cursor) = self.undo_tree.redo(&mut self.content) {
+            self.cursor_position = self.content.clamp(cursor);
+            self.reset_undo_base();
             self.set_status_message("Redid last action.");
-            self.modified = true;
+            self.modified = !self.undo_tree.is_saved();
         } else {
             self.set_status_message("No more actions to redo.");
         }
     }
+    fn jump_to_undo_state(&mut self, node: usize) -> bool {
+        self.save_state();
+        match self.undo_tree.jump_to(node, &mut self.content) {
+            Some(cursor) => {
+                self.cursor_position = self.content.clamp(cursor);
+                self.reset_undo_base();
+                self.modified = !self.undo_tree.is_saved();
+                true
+            }
+            None => false,
+        }
+    }
+    fn undo_chronologically(&mut self, older: bool) {
+        self.save_state();
+        let current = self.undo_tree.current;
+        let target = if older {
+            current.checked_sub(1)
+        } else {
+            Some(current + 1).filter(|&node| node < self.undo_tree.nodes.len())
+        };
+        match target {
+            Some(node) if self.jump_to_undo_state(node) => {
+                self.set_status_message(format!("Moved to state #{}", node));
+            }
+            _ => self.set_status_message(if older { "Already at oldest state." } else { "Already at newest state." }),
+        }
+    }
+    fn undo_by_time(&mut self, duration: Duration, older: bool) {
+        self.save_state();
+        let reference = self.undo_tree.nodes[self.undo_tree.current].timestamp;
+        let time = if older {
+            reference.checked_sub(duration).unwrap_or(SystemTime::UNIX_EPOCH)
+        } else {
+            reference + duration
+        };
+        let node = self.undo_tree.node_at_time(time, older);
+        if self.jump_to_undo_state(node) {
+            self.set_status_message(format!("Moved to state #{} ({})", node, Self::format_age(self.undo_tree.nodes[node].timestamp)));
+        } else {
+            self.set_status_message("No state at that time.");
+        }
+    }
+    fn format_age(time: SystemTime) -> String {
+        let seconds = time.elapsed().map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+        match seconds {
+            0..=59 => format!("{}s ago", seconds),
+            60..=3599 => format!("{}m ago", seconds / 60),
+            3600..=86399 => format!("{}h ago", seconds / 3600),
+            _ => format!("{}d ago", seconds / 86400),
+        }
+    }
+    fn open_undo_tree(&mut self) {
+        self.save_state();
+        let current = self.undo_tree.current;
+        self.undo_tree_origin = current;
+        self.undo_tree_selection = self.undo_tree.rows().iter().position(|&(node, _)| node == current).unwrap_or(0);
+        self.undo_time_input.clear();
+        self.popup_state = PopupType::UndoTree;
+    }
     fn format_path(path: &Path) -> String {
         let home = env::var("HOME").ok().map(PathBuf::from);
         if let Some(home_path) = home {
@@ -483,8 +720,22 @@ impl Editor {
         } else {
             None
         };
+        let cursor_display = self.content.display_col(self.cursor_position, self.tab_width());
+        let selection = self.selection_range();
+        let block = self.block_range();
+        let search_options = self.search_options_label();
+        let preview = self.query_replace_preview();
+        let project_search_status = self.project_search_status();
+        self.update_syntax();
+        self.update_outline();
+        let settings_rows = self.settings_rows();
+        let config_path = Self::config_path();
+        let project_regex = (self.popup_state == PopupType::ProjectReplace)
+            .then(|| Self::build_regex(&self.project_search_query, self.search_options).ok())
+            .flatten();
         self.terminal.draw(|frame| {
-            let area = frame.size();
+            let area = frame.area();
+            let mut cursor_row = 0;
             let max_scroll = self.file_entries.len().saturating_sub(1) as u16;
             self.file_tree_scroll_offset = self.file_tree_scroll_offset.min(max_scroll);
             let chunks = Layout::default()
@@ -508,7 +759,67 @@ impl Editor {
                     .constraints([Constraint::Min(1)])
                     .split(chunks[0])
             };
-            if self.show_tree {
+            if self.show_tree && self.show_outline {
+                let outline_block = Block::default()
+                    .title(if self.tree_focused { "[ Outline ]" } else { " Outline " })
+                    .title_alignment(Alignment::Center)
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(if self.tree_focused { Color::Green } else { Color::Cyan }));
+                let outline_inner = outline_block.inner(main_chunks[0]);
+                frame.render_widget(outline_block, main_chunks[0]);
+                let filter_height = if self.tree_focused || !self.outline_query.is_empty() { 2 } else { 0 };
+                if filter_height > 0 {
+                    frame.render_widget(
+                        Paragraph::new(format!("> {}", self.outline_query)).style(Style::default().fg(Color::White)),
+                        Rect::new(outline_inner.x, outline_inner.y, outline_inner.width, 1),
+                    );
+                }
+                let list_height = outline_inner.height.saturating_sub(filter_height) as usize;
+                let first = (self.outline_selection + 1).saturating_sub(list_height);
+                let items: Vec<Line> = self.outline_results.iter().enumerate()
+                    .skip(first)
+                    .take(list_height)
+                    .map(|(i, (index, indices))| {
+                        let symbol = &self.outline_symbols[*index];
+                        let style = if i == self.outline_selection {
+                            Style::default()
+                                .fg(if self.tree_focused { Color::Green } else { Color::White })
+                                .add_modifier(Modifier::BOLD)
+                        } else {
+                            Style::default().fg(Color::White)
+                        };
+                        let (icon, color) = Self::symbol_icon(&symbol.kind);
+                        let name = Self::truncate_to_width(
+                            &symbol.name,
+                            outline_inner.width.saturating_sub(symbol.depth as u16 * 2 + 4)
+                        );
+                        let mut spans = vec![
+                            Span::raw("  ".repeat(symbol.depth)),
+                            Span::styled(format!("{} ", if i == self.outline_selection { "▶" } else { " " }), style),
+                            Span::styled(format!("{} ", icon), style.fg(color)),
+                        ];
+                        spans.extend(name.chars().enumerate().map(|(j, c)| {
+                            if indices.contains(&j) {
+                                Span::styled(c.to_string(), style.fg(Color::Yellow).add_modifier(Modifier::BOLD))
+                            } else {
+                                Span::styled(c.to_string(), style)
+                            }
+                        }));
+                        Line::from(spans)
+                    })
+                    .collect();
+                let text = if !items.is_empty() {
+                    items
+                } else if self.outline_symbols.is_empty() {
+                    vec![Line::from(Span::styled("No symbols", Style::default().fg(Color::Gray)))]
+                } else {
+                    vec![Line::from(Span::styled("No matches", Style::default().fg(Color::Gray)))]
+                };
+                frame.render_widget(
+                    Paragraph::new(text),
+                    Rect::new(outline_inner.x, outline_inner.y + filter_height, outline_inner.width, list_height as u16),
+                );
+            } else if self.show_tree {
                 let tree_block = Block::default()
                     .title(if self.tree_focused { "[ Files ]" } else { " Files " })
                     .title_alignment(Alignment::Center)
@@ -548,7 +859,10 @@ impl Editor {
                             Self::get_file_icon(&entry.path)
                         };
                         let indent = "  ".repeat(entry.depth);
-                        let name = &entry.name;
+                        let name = Self::truncate_to_width(
+                            &entry.name,
+                            tree_inner.width.saturating_sub(entry.depth as u16 * 2 + 4)
+                        );
                         Line::from(vec![
                             Span::raw(indent),
                             Span::styled(
@@ -583,286 +897,130 @@ impl Editor {
                 frame.render_widget(paragraph, tree_inner);
             }
             let editor_area = if self.show_tree { main_chunks[1] } else { main_chunks[0] };
-            let title = if let Some(path) = &self.filename {
-                format!("─[{}]", Self::format_path(path))
-            } else {
-                "─[New File]".to_string()
-            };
-            let block = Block::default()
-                .title(title)
-                .borders(Borders::ALL);
-            let inner = block.inner(editor_area);
-            frame.render_widget(block, editor_area);
-            if (self.cursor_position.1 as u16) >= inner.height + self.scroll_offset {
-                self.scroll_offset = (self.cursor_position.1 as u16).saturating_sub(inner.height) + 1;
-            } else if (self.cursor_position.1 as u16) < self.scroll_offset {
-                self.scroll_offset = self.cursor_position.1 as u16;
-            }
-            let text = {
-                let terminal_height = inner.height as usize;
-                let start_line = self.scroll_offset as usize;
-                let end_line = (start_line + terminal_height).min(self.content.len());
-                let visible_width = inner.width as usize - if self.show_numbers { 5 } else { 1 };
-                if let Some(syntax_name) = &self.current_syntax {
-                    if let Some(syntax) = self.syntax_set.find_syntax_by_name(syntax_name) {
-                        let mut highlighter = HighlightLines::new(
-                            syntax,
-                            &self.theme_set.themes["base16-ocean.dark"]
-                        );
-                        let highlighted: Vec<Line> = self.content[start_line..end_line]
-                            .iter()
-                            .enumerate()
-                            .map(|(idx, line)| {
-                                let line_idx = idx + start_line;
-                                let mut spans = Vec::new();
-                                if self.show_numbers {
-                                    spans.push(Span::styled(
-                                        format!("{:4} ", line_idx + 1),
-                                        Style::default().fg(Color::DarkGray)
-                                    ));
-                                } else {
-                                    spans.push(Span::raw(" "));
-                                }
-                                let matches: Vec<_> = self.highlighted_matches.iter()
-                                    .filter(|&(l, _)| *l == line_idx)
-                                    .map(|(_, c)| *c)
-                                    .collect();
-                                let visible_start = if line_idx == self.cursor_position.1 {
-                                    (self.cursor_position.0 / visible_width) * visible_width
-                                } else {
-                                    0
-                                };
-                                let visible_text = if line.len() > visible_start {
-                                    let end = (visible_start + visible_width).min(line.len());
-                                    &line[visible_start..end]
-                                } else {
-                                    ""
-                                };
-                                if let Ok(ranges) = highlighter.highlight_line(visible_text, &self.syntax_set) {
-                                    let mut last_end = 0;
-                                    for (style, text) in ranges {
-                                        if text.is_empty() {
-                                            continue;
-                                        }
-                                        let start = last_end;
-                                        let end = start + text.len();
-                                        let matching_positions: Vec<_> = matches.iter()
-                                            .filter(|&&pos| pos >= start + visible_start && pos < end + visible_start)
-                                            .map(|&pos| pos - visible_start)
-                                            .collect();
-                                        if !matching_positions.is_empty() {
-                                            for match_pos in matching_positions {
-                                                if match_pos > start {
-                                                    let prefix = text.get(..match_pos.saturating_sub(start))
-                                                        .unwrap_or_default();
-                                                    if !prefix.is_empty() {
-                                                        spans.push(Span::styled(
-                                                            prefix,
-                                                            Style::default().fg(Color::Rgb(
-                                                                style.foreground.r,
-                                                                style.foreground.g,
-                                                                style.foreground.b,
-                                                            ))
-                                                        ));
-                                                    }
-                                                }
-                                                let match_text = text.get(
-                                                    match_pos.saturating_sub(start)..
-                                                        (match_pos.saturating_sub(start) + self.search_query.len())
-                                                            .min(text.len())
-                                                ).unwrap_or_default();
-                                                if !match_text.is_empty() {
-                                                    spans.push(Span::styled(
-                                                        match_text,
-                                                        Style::default()
-                                                            .bg(Color::DarkGray)
-                                                            .fg(Color::White)
-                                                    ));
-                                                }
-                                            }
-                                        } else {
-                                            spans.push(Span::styled(
-                                                text,
-                                                Style::default().fg(Color::Rgb(
-                                                    style.foreground.r,
-                                                    style.foreground.g,
-                                                    style.foreground.b,
-                                                ))
-                                            ));
-                                        }
-                                        last_end = end;
-                                    }
-                                } else {
-                                    spans.push(Span::raw(visible_text));
-                                }
-                                if line.len() > visible_start + visible_width {
-                                }
-                                if visible_start > 0 {
-                                    spans.insert(if self.show_numbers { 1 } else { 1 },
-                                        Span::styled("", Style::default().fg(Color::DarkGray)));
-                                }
-                                Line::from(spans)
-                            })
-                            .collect();
-                        Text::from(highlighted)
+            let editor_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(1)])
+                .split(editor_area);
+            let editor_area = editor_chunks[1];
+            let labels: Vec<(String, bool)> = (0..self.tabs.len())
+                .map(|i| {
+                    let (filename, modified) = if i == self.active_tab {
+                        (&self.filename, self.modified)
                     } else {
-                        Text::from(
-                            self.content[start_line..end_line]
-                                .iter()
-                                .enumerate()
-                                .map(|(idx, line)| {
-                                    let line_idx = idx + start_line;
-                                    let visible_start = if line_idx == self.cursor_position.1 {
-                                        (self.cursor_position.0 / visible_width) * visible_width
-                                    } else {
-                                        0
-                                    };
-                                    let mut spans = Vec::new();
-                                    if self.show_numbers {
-                                        spans.push(Span::styled(
-                                            format!("{:4} ", line_idx + start_line + 1),
-                                            Style::default().fg(Color::DarkGray)
-                                        ));
-                                    } else {
-                                        spans.push(Span::raw(" "));
-                                    }
-                                    if visible_start > 0 {
-                                    }
-                                    let visible_text = if line.len() > visible_start {
-                                        let end = (visible_start + visible_width).min(line.len());
-                                        &line[visible_start..end]
-                                    } else {
-                                        ""
-                                    };
-                                    spans.push(Span::raw(visible_text));
-                                    if line.len() > visible_start + visible_width {
-                                    }
-                                    Line::from(spans)
-                                })
-                                .collect::<Vec<_>>()
-                        )
+                        (&self.tabs[i].filename, self.tabs[i].modified)
+                    };
+                    let name = filename.as_ref()
+                        .and_then(|path| path.file_name())
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| "New File".to_string());
+                    (format!(" {}{} ", name, if modified { " ●" } else { "" }), i == self.active_tab)
+                })
+                .collect();
+            let mut first_tab = 0;
+            while first_tab < self.active_tab
+                && labels[first_tab..=self.active_tab].iter().map(|(label, _)| label.width()).sum::<usize>()
+                    > editor_chunks[0].width as usize
+            {
+                first_tab += 1;
+            }
+            let tab_spans: Vec<Span> = labels[first_tab..].iter()
+                .map(|(label, active)| Span::styled(
+                    label.clone(),
+                    if *active {
+                        Style::default().fg(Color::Black).bg(Color::Cyan).add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default().fg(Color::Gray)
                     }
+                ))
+                .collect();
+            frame.render_widget(Paragraph::new(Line::from(tab_spans)), editor_chunks[0]);
+            let theme = self.theme_set.themes.get(&self.config.ui.theme)
+                .unwrap_or(&self.theme_set.themes[config::DEFAULT_THEME]);
+            let highlight_focus = self.splits.len() > 1;
+            self.pane_areas = Self::split_areas(editor_area, &self.splits);
+            let mut inner = editor_area;
+            for (i, &pane_area) in self.pane_areas.iter().enumerate() {
+                if i == self.focused_split {
+                    let pane = PaneView {
+                        content: &self.content,
+                        filename: self.filename.as_deref(),
+                        syntax: self.current_syntax.as_deref(),
+                        highlighter: self.highlighter.as_ref(),
+                        folds: &self.folds,
+                        cursor_position: self.cursor_position,
+                        scroll_offset: self.scroll_offset,
+                        matches: &self.highlighted_matches,
+                        selection,
+                        preview,
+                        block,
+                        cursors: &self.extra_cursors,
+                        focused: highlight_focus,
+                        tab_width: self.config.tab_width(self.filename.as_deref()),
+                    };
+                    let (pane_inner, scroll_offset) = Self::draw_pane(frame, pane_area, pane, &self.syntax_set, theme, self.show_numbers);
+                    inner = pane_inner;
+                    self.scroll_offset = scroll_offset;
+                    cursor_row = self.folds.rows_between(scroll_offset, self.cursor_position.1, self.content.len_lines());
                 } else {
-                    Text::from(
-                        self.content[start_line..end_line]
-                            .iter()
-                            .enumerate()
-                            .map(|(idx, line)| {
-                                let line_idx = idx + start_line;
-                                let visible_start = if line_idx == self.cursor_position.1 {
-                                    (self.cursor_position.0 / visible_width) * visible_width
-                                } else {
-                                    0
-                                };
-                                let mut spans = Vec::new();
-                                if self.show_numbers {
-                                    spans.push(Span::styled(
-                                        format!("{:4} ", line_idx + start_line + 1),
-                                        Style::default().fg(Color::DarkGray)
-                                    ));
-                                } else {
-                                    spans.push(Span::raw(" "));
-                                }
-                                if visible_start > 0 {
-                                }
-                                let visible_text = if line.len() > visible_start {
-                                    let end = (visible_start + visible_width).min(line.len());
-                                    &line[visible_start..end]
-                                } else {
-                                    ""
-                                };
-                                spans.push(Span::raw(visible_text));
-                                if line.len() > visible_start + visible_width {
-                                }
-                                Line::from(spans)
-                            })
-                            .collect::<Vec<_>>()
-                    )
+                    let split = &self.splits[i];
+                    let (content, filename, syntax, highlighter, folds) = if split.tab_index == self.active_tab {
+                        (&self.content, self.filename.as_deref(), self.current_syntax.as_deref(), self.highlighter.as_ref(), &self.folds)
+                    } else {
+                        let tab = &self.tabs[split.tab_index];
+                        (&tab.content, tab.filename.as_deref(), tab.current_syntax.as_deref(), tab.highlighter.as_ref(), &tab.folds)
+                    };
+                    let pane = PaneView {
+                        content,
+                        filename,
+                        syntax,
+                        highlighter,
+                        folds,
+                        cursor_position: content.clamp(split.cursor_position),
+                        scroll_offset: split.scroll_offset,
+                        matches: &[],
+                        selection: None,
+                        preview: None,
+                        block: None,
+                        cursors: &[],
+                        focused: false,
+                        tab_width: self.config.tab_width(filename),
+                    };
+                    let (_, scroll_offset) = Self::draw_pane(frame, pane_area, pane, &self.syntax_set, theme, self.show_numbers);
+                    self.splits[i].scroll_offset = scroll_offset;
                 }
-            };
-            let paragraph = Paragraph::new(text);
-            frame.render_widget(paragraph, inner);
+            }
             if let Some((msg, instant)) = &self.status_message {
                 if instant.elapsed() < std::time::Duration::from_secs(2) {
                     let status_area = chunks[1];
                     frame.render_widget(Clear, status_area);
-                    let status_icon = if msg.contains("Error") {
-                        ""
-                    } else if msg.contains("Saved") {
-                        ""
-                    } else {
-                        ""
-                    };
-                    let status = Paragraph::new(format!(" {} {}", status_icon, msg))
+                    let status = Paragraph::new(format!("  {}", msg))
                         .style(Style::default().fg(Color::Gray));
                     frame.render_widget(status, status_area);
                 } else {
                     self.status_message = None;
                 }
             }
+            if self.show_status {
+                let format = match self.mode {
+                    EditorMode::Visual => format!(" VISUAL  {} ", self.content.format_label()),
+                    EditorMode::VisualBlock => format!(" VISUAL BLOCK  {} ", self.content.format_label()),
+                    _ => format!(" {} ", self.content.format_label()),
+                };
+                let width = (format.width() as u16).min(chunks[1].width);
+                let format_area = Rect::new(
+                    chunks[1].x + chunks[1].width - width,
+                    chunks[1].y,
+                    width,
+                    1,
+                );
+                frame.render_widget(
+                    Paragraph::new(format).style(Style::default().fg(Color::DarkGray)),
+                    format_area
+                );
+            }
             match &self.popup_state {
                 PopupType::Help => {
-                    let area = frame.size();
-                    let width = area.width.saturating_sub(4).min(100);
-                    let height = area.height.saturating_sub(4);
-                    let help_area = Rect::new(
-                        (area.width.saturating_sub(width)) / 2,
-                        (area.height.saturating_sub(height)) / 2,
-                        width,
-                        height
-                    );
-                    frame.render_widget(Clear, help_area);
-                    let help_block = Block::default()
-                        .title(" Keyboard Shortcuts (Use ↑↓ to scroll) ")
-                        .title_alignment(Alignment::Center)
-                        .borders(Borders::ALL)
-                        .border_style(Style::default().fg(Color::Cyan));
-                    let inner = help_block.inner(help_area);
-                    frame.render_widget(help_block, help_area);
-                    let mut text = Vec::new();
-                    let max_key_width = 12;
-                    let max_action_width = 15;
-                    let desc_width = inner.width.saturating_sub(max_key_width as u16 + max_action_width as u16 + 6);
-                    for (key, action, desc) in HELP_TEXT {
-                        if action.is_empty() {
-                            if !text.is_empty() {
-                                text.push(Line::from(""));
-                            }
-                            text.push(Line::from(vec![
-                                Span::styled(
-                                    format!("─── {} ", key),
-                                    Style::default()
-                                        .fg(Color::Yellow)
-                                        .add_modifier(Modifier::BOLD)
-                                ),
-                                Span::styled(
-                                    "─".repeat((desc_width as usize).saturating_sub(key.len() + 4)),
-                                    Style::default().fg(Color::DarkGray)
-                                ),
-                            ]));
-                            continue;
-                        }
-                        text.push(Line::from(vec![
-                            Span::styled(
-                                format!("{:width$}", key, width = max_key_width),
-                                Style::default().fg(Color::Green)
-                            ),
-                            Span::raw(" "),
-                            Span::styled(
-                                format!("{:width$}", action, width = max_action_width),
-                                Style::default().fg(Color::White)
-                            ),
-                            Span::raw(" "),
-                            Span::styled(
-                                desc.to_string(),
-                                Style::default().fg(Color::Gray)
-                            )
-                        ]));
-                    }
-                    let help_text = Paragraph::new(text)
-                        .alignment(Alignment::Left)
-                        .scroll((self.file_tree_scroll_offset, 0));
-                    frame.render_widget(help_text, inner);
+                    Self::draw_help(frame, &self.keymap, self.file_tree_scroll_offset);
                 }
                 PopupType::Save => {
                     let area = Rect::new(
@@ -882,10 +1040,10 @@ impl Editor {
                     let input = Paragraph::new(format!("Filename: {}", self.temp_filename))
                         .style(Style::default().fg(Color::White));
                     frame.render_widget(input, inner_area);
-                    frame.set_cursor(
+                    frame.set_cursor_position((
                         area.x + 11 + self.temp_filename.len() as u16,
                         area.y + 1
-                    );
+                    ));
                 },
                 PopupType::SaveConfirm(action) => {
                     let area = Rect::new(
@@ -902,10 +1060,9 @@ impl Editor {
                         .border_style(Style::default().fg(Color::Yellow));
                     let inner_area = popup_block.inner(area);
                     frame.render_widget(popup_block, area);
-                    let msg = if *action == SaveAction::Exit {
-                        "Save before exit? (y/n/c)"
-                    } else {
-                        "Save unsaved changes? (y/n/c)"
+                    let msg = match action {
+                        SaveAction::Exit => "Save before exit? (y/n/c)",
+                        SaveAction::CloseTab => "Save before closing tab? (y/n/c)",
                     };
                     let text = Paragraph::new(msg)
                         .style(Style::default().fg(Color::White))
@@ -932,7 +1089,7 @@ impl Editor {
                         .alignment(Alignment::Center);
                     frame.render_widget(text, inner_area);
                 },
-                PopupType::Find => {
+                PopupType::LossySaveConfirm => {
                     let area = Rect::new(
                         area.width / 4,
                         area.height / 2 - 2,
@@ -941,19 +1098,43 @@ impl Editor {
                     );
                     frame.render_widget(Clear, area);
                     let popup_block = Block::default()
+                        .title(format!("Decoded with errors as {}", self.content.encoding.name()))
+                        .title_alignment(Alignment::Center)
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Red));
+                    let inner_area = popup_block.inner(area);
+                    frame.render_widget(popup_block, area);
+                    let text = Paragraph::new("Invalid bytes will be lost. Save anyway? (y/n)")
+                        .style(Style::default().fg(Color::White))
+                        .alignment(Alignment::Center);
+                    frame.render_widget(text, inner_area);
+                },
+                PopupType::Find => {
+                    let area = Rect::new(
+                        area.width / 4,
+                        area.height / 2 - 2,
+                        area.width / 2,
+                        3
+                    );
+                    frame.render_widget(Clear, area);
+                    let mut popup_block = Block::default()
                         .title("Find")
                         .title_alignment(Alignment::Center)
+                        .title(Line::from(search_options.as_str()).right_aligned())
                         .borders(Borders::ALL)
                         .border_style(Style::default().fg(Color::White));
+                    if let Some(error) = &self.search_error {
+                        popup_block = popup_block.title_bottom(Line::styled(format!(" {} ", error), Style::default().fg(Color::Red)));
+                    }
                     let inner_area = popup_block.inner(area);
                     frame.render_widget(popup_block, area);
                     let input = Paragraph::new(format!("Search: {}", self.search_query))
                         .style(Style::default().fg(Color::White));
                     frame.render_widget(input, inner_area);
-                    frame.set_cursor(
+                    frame.set_cursor_position((
                         area.x + 9 + self.search_query.len() as u16,
                         area.y + 1
-                    );
+                    ));
                 },
                 PopupType::Open => {
                     let area = Rect::new(
@@ -973,10 +1154,10 @@ impl Editor {
                     let input = Paragraph::new(format!("Path: {}", self.temp_filename))
                         .style(Style::default().fg(Color::White));
                     frame.render_widget(input, inner_area);
-                    frame.set_cursor(
+                    frame.set_cursor_position((
                         area.x + 7 + self.temp_filename.len() as u16,
                         area.y + 1
-                    );
+                    ));
                 },
                 PopupType::InitialMenu => {
                     let menu_block = Block::default()
@@ -1046,13 +1227,9 @@ impl Editor {
                     }
                 },
                 PopupType::ToolMenu => {
-                    let tools = vec![
-                        ("󰄾", "Delete Comments", "Remove all comments from the file"),
-                        ("󰄾", "Remove Empty Lines", "Remove all empty lines from the file"),
-                        ("󰄾", "Clear Cache", "Clear editor's cache"),
-                    ];
+                    let tools = &self.tools;
                     let max_tool_width = tools.iter()
-                        .map(|(_, name, desc)| name.len() + desc.len() + 4)
+                        .map(|(_, name, desc)| name.width() + desc.width() + 8)
                         .max()
                         .unwrap_or(0) as u16;
                     let tool_height = tools.len() as u16 + 2;
@@ -1090,114 +1267,615 @@ impl Editor {
                         .alignment(Alignment::Left);
                     frame.render_widget(paragraph, inner_area);
                 },
-                PopupType::RecentFiles => {
+                PopupType::ProjectSearch | PopupType::ProjectReplace => {
+                    let replacing = self.popup_state == PopupType::ProjectReplace;
+                    let width = (area.width * 4 / 5).max(20).min(area.width);
+                    let height = (area.height * 3 / 4).max(6).min(area.height);
                     let area = Rect::new(
-                        area.width / 2 - (area.width / 4) / 2,
-                        area.height / 3,
-                        area.width / 4,
-                        area.height / 3,
+                        (area.width - width) / 2,
+                        (area.height - height) / 2,
+                        width,
+                        height,
                     );
                     frame.render_widget(Clear, area);
+                    let status = match &self.search_error {
+                        Some(error) => Line::styled(format!(" {} ", error), Style::default().fg(Color::Red)),
+                        None if replacing => Line::styled(
+                            " Tab: toggle hit  Shift+Tab: toggle file  Alt+a: toggle all  Enter: apply ",
+                            Style::default().fg(Color::DarkGray)
+                        ),
+                        None => Line::styled(project_search_status.as_str(), Style::default().fg(Color::DarkGray)),
+                    };
                     let popup_block = Block::default()
-                        .title(" Recent Files ")
+                        .title(if replacing { " Replace in Project " } else { " Search in Project " })
                         .title_alignment(Alignment::Center)
+                        .title(Line::from(search_options.as_str()).right_aligned())
+                        .title_bottom(status)
                         .borders(Borders::ALL)
                         .border_style(Style::default().fg(Color::Cyan));
                     let inner_area = popup_block.inner(area);
                     frame.render_widget(popup_block, area);
-                    let recent_files_text: Vec<Line> = self.recent_files
-                        .iter()
-                        .enumerate()
-                        .map(|(i, rf)| {
-                            let status = if rf.exists { "  " } else { "  " };
-                            let path = Self::format_path(&rf.path);
-                            let style = if i == self.recent_files_selection {
+                    let (label, input) = if replacing {
+                        ("Replace with: ", &self.project_replace_text)
+                    } else {
+                        ("Search: ", &self.project_search_query)
+                    };
+                    frame.render_widget(
+                        Paragraph::new(format!("{}{}", label, input)).style(Style::default().fg(Color::White)),
+                        Rect::new(inner_area.x, inner_area.y, inner_area.width, 1),
+                    );
+                    frame.set_cursor_position((
+                        inner_area.x + (label.width() + input.width()) as u16,
+                        inner_area.y
+                    ));
+                    let list_height = inner_area.height.saturating_sub(2) as usize;
+                    let hits = &self.project_search_hits;
+                    let checked = |i: usize| self.project_replace_checked.get(i).copied().unwrap_or(false);
+                    let checkbox = |on: bool| if on { "[x] " } else { "[ ] " };
+                    let selected = self.project_search_selection.min(hits.len().saturating_sub(1));
+                    let selected_row = selected + 1 + hits[..selected.min(hits.len())].windows(2)
+                        .filter(|pair| pair[0].path != pair[1].path)
+                        .count();
+                    let first_row = (selected_row + 1).saturating_sub(list_height);
+                    let mut lines = Vec::new();
+                    let mut row = 0;
+                    for (i, hit) in hits.iter().enumerate() {
+                        if row >= first_row + list_height {
+                            break;
+                        }
+                        if i == 0 || hits[i - 1].path != hit.path {
+                            if row >= first_row {
+                                let path = hit.path.strip_prefix(&self.current_dir).unwrap_or(&hit.path);
+                                let count = hits[i..].iter().take_while(|other| other.path == hit.path).count();
+                                let mut spans = Vec::new();
+                                if replacing {
+                                    spans.push(Span::raw(checkbox((i..i + count).all(checked))));
+                                }
+                                spans.push(Span::styled(path.display().to_string(), Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)));
+                                spans.push(Span::styled(format!(" ({})", count), Style::default().fg(Color::DarkGray)));
+                                lines.push(Line::from(spans));
+                            }
+                            row += 1;
+                        }
+                        if row >= first_row && row < first_row + list_height {
+                            let style = if i == selected {
+                                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+                            } else {
+                                Style::default().fg(Color::White)
+                            };
+                            let preview: Vec<char> = hit.preview.chars().collect();
+                            let start = hit.preview_col.min(preview.len());
+                            let end = (hit.preview_col + hit.len).min(preview.len());
+                            let mut spans = vec![
+                                Span::styled(format!("{} {:>5}: ", if i == selected { "󰄾" } else { " " }, hit.line + 1), Style::default().fg(Color::DarkGray)),
+                            ];
+                            if replacing {
+                                spans.insert(0, Span::raw(checkbox(checked(i))));
+                            }
+                            spans.push(Span::styled(preview[..start].iter().collect::<String>(), style));
+                            let matched: String = preview[start..end].iter().collect();
+                            let replacement = project_regex.as_ref().and_then(|regex| {
+                                let line: String = preview.iter().collect();
+                                let byte = line.char_indices().nth(start).map_or(line.len(), |(byte, _)| byte);
+                                search::expand_replacement(regex, &line, byte, &self.project_replace_text, self.search_options.regex)
+                            });
+                            match replacement {
+                                Some((_, replacement)) if replacing => {
+                                    spans.push(Span::styled(matched, style.fg(Color::Red).add_modifier(Modifier::CROSSED_OUT)));
+                                    spans.push(Span::styled(replacement, style.fg(Color::Black).bg(Color::Green)));
+                                }
+                                _ => spans.push(Span::styled(matched, style.bg(Color::DarkGray))),
+                            }
+                            spans.push(Span::styled(preview[end..].iter().collect::<String>(), style));
+                            lines.push(Line::from(spans));
+                        }
+                        row += 1;
+                    }
+                    frame.render_widget(
+                        Paragraph::new(lines),
+                        Rect::new(inner_area.x, inner_area.y + 2, inner_area.width, list_height as u16),
+                    );
+                },
+                PopupType::Settings => {
+                    let width = 56.min(area.width);
+                    let height = (settings_rows.len() as u16 + 4).min(area.height);
+                    let area = Rect::new(
+                        (area.width - width) / 2,
+                        (area.height - height) / 2,
+                        width,
+                        height,
+                    );
+                    frame.render_widget(Clear, area);
+                    let popup_block = Block::default()
+                        .title(" Settings ")
+                        .title_alignment(Alignment::Center)
+                        .title_bottom(Line::styled(" ←/→: change  o: open config.toml  Esc: close ", Style::default().fg(Color::DarkGray)))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Cyan));
+                    let inner_area = popup_block.inner(area);
+                    frame.render_widget(popup_block, area);
+                    let mut text: Vec<Line> = settings_rows.iter().enumerate()
+                        .map(|(i, (label, value))| {
+                            let style = if i == self.settings_selection {
                                 Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
                             } else {
                                 Style::default().fg(Color::White)
                             };
                             Line::from(vec![
-                                Span::styled(format!(" {} ", if i == self.recent_files_selection { "󰄾" } else { " " }), style),
-                                Span::styled(status, if rf.exists { style } else { style.fg(Color::Red) }),
-                                Span::styled(path, style),
+                                Span::styled(format!(" {} ", if i == self.settings_selection { "󰄾" } else { " " }), style),
+                                Span::styled(format!("{:<22}", label), style),
+                                Span::styled(value.clone(), style.fg(Color::Cyan)),
                             ])
                         })
                         .collect();
-                    let text = if recent_files_text.is_empty() {
-                        vec![Line::from(Span::styled("No recent files", Style::default().fg(Color::Gray)))]
-                    } else {
-                        let mut text = recent_files_text;
+                    if let Some(path) = &config_path {
                         text.push(Line::from(""));
-                        text.push(Line::from(Span::styled("         Enter to select, Esc to cancel", Style::default().fg(Color::DarkGray))));
-                        text
-                    };
-                    let paragraph = Paragraph::new(text)
-                        .alignment(Alignment::Left);
-                    frame.render_widget(paragraph, inner_area);
-                },
-                PopupType::None => {
-                    let visible_width = inner.width.saturating_sub(if self.show_numbers { 5 } else { 1 }) as usize;
-                    let cursor_x = self.cursor_position.0 % visible_width;
-                    let base_offset = if self.show_numbers { 5 } else { 1 };
-                    let wrap_offset = if cursor_x == 0 && self.cursor_position.0 > 0 {
-                        visible_width
-                    } else {
-                        0
-                    };
-                    frame.set_cursor(
-                        inner.x + cursor_x as u16 + base_offset + (wrap_offset % visible_width) as u16,
-                        inner.y + self.cursor_position.1 as u16 - self.scroll_offset
-                    );
+                        text.push(Line::styled(format!("   {}", Self::format_path(path)), Style::default().fg(Color::DarkGray)));
+                    }
+                    frame.render_widget(Paragraph::new(text), inner_area);
                 },
-                PopupType::JumpToLine => {
+                PopupType::CommandPalette => {
+                    let width = (area.width * 3 / 5).max(20).min(area.width);
+                    let height = (area.height * 3 / 5).max(6).min(area.height);
                     let area = Rect::new(
-                        area.width / 4,
-                        area.height / 2 - 2,
-                        area.width / 2,
-                        3
+                        (area.width - width) / 2,
+                        (area.height - height) / 4,
+                        width,
+                        height,
                     );
                     frame.render_widget(Clear, area);
                     let popup_block = Block::default()
-                        .title("Jump to Line")
+                        .title(" Command Palette ")
                         .title_alignment(Alignment::Center)
                         .borders(Borders::ALL)
-                        .border_style(Style::default().fg(Color::White));
+                        .border_style(Style::default().fg(Color::Cyan));
                     let inner_area = popup_block.inner(area);
                     frame.render_widget(popup_block, area);
-                    let input = Paragraph::new(format!("Line number: {}", self.search_query))
-                        .style(Style::default().fg(Color::White));
-                    frame.render_widget(input, inner_area);
-                    frame.set_cursor(
-                        area.x + 11 + self.search_query.len() as u16,
-                        area.y + 1
+                    frame.render_widget(
+                        Paragraph::new(format!("> {}", self.palette_query)).style(Style::default().fg(Color::White)),
+                        Rect::new(inner_area.x, inner_area.y, inner_area.width, 1),
+                    );
+                    frame.set_cursor_position((
+                        inner_area.x + 2 + self.palette_query.width() as u16,
+                        inner_area.y
+                    ));
+                    let list_height = inner_area.height.saturating_sub(2) as usize;
+                    let first = (self.palette_selection + 1).saturating_sub(list_height);
+                    let text: Vec<Line> = self.palette_results.iter().enumerate()
+                        .skip(first)
+                        .take(list_height)
+                        .map(|(i, (index, indices))| {
+                            let info = &COMMANDS[*index];
+                            let style = if i == self.palette_selection {
+                                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+                            } else {
+                                Style::default().fg(Color::White)
+                            };
+                            let label = format!("{}: {}", info.category, info.name);
+                            let keys = self.keymap.keys_for(info.command).join(" / ");
+                            let mut spans = vec![
+                                Span::styled(format!(" {} ", if i == self.palette_selection { "󰄾" } else { " " }), style),
+                            ];
+                            spans.extend(label.chars().enumerate().map(|(j, c)| {
+                                if indices.contains(&j) {
+                                    Span::styled(c.to_string(), style.fg(Color::Yellow).add_modifier(Modifier::BOLD))
+                                } else {
+                                    Span::styled(c.to_string(), style)
+                                }
+                            }));
+                            spans.push(Span::styled(format!("  {}", info.description), Style::default().fg(Color::DarkGray)));
+                            let used: usize = spans.iter().map(|span| span.content.width()).sum();
+                            let padding = (inner_area.width as usize).saturating_sub(used + keys.width() + 1);
+                            spans.push(Span::raw(" ".repeat(padding)));
+                            spans.push(Span::styled(keys, Style::default().fg(Color::Green)));
+                            Line::from(spans)
+                        })
+                        .collect();
+                    frame.render_widget(
+                        Paragraph::new(text),
+                        Rect::new(inner_area.x, inner_area.y + 2, inner_area.width, list_height as u16),
                     );
                 },
-                PopupType::ReplaceQuery => {
+                PopupType::FileFinder => {
+                    let width = (area.width * 3 / 5).max(20).min(area.width);
+                    let height = (area.height * 3 / 5).max(6).min(area.height);
                     let area = Rect::new(
-                        area.width / 4,
-                        area.height / 2 - 2,
-                        area.width / 2,
-                        5
+                        (area.width - width) / 2,
+                        (area.height - height) / 4,
+                        width,
+                        height,
                     );
                     frame.render_widget(Clear, area);
                     let popup_block = Block::default()
-                        .title("Find")
+                        .title(" Find File ")
                         .title_alignment(Alignment::Center)
+                        .title_bottom(Line::styled(
+                            format!(
+                                " {}{} of {} files ",
+                                if self.file_index_receiver.is_some() { "Indexing… " } else { "" },
+                                self.file_finder_results.len(),
+                                self.file_finder_files.len()
+                            ),
+                            Style::default().fg(Color::DarkGray)
+                        ))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Cyan));
+                    let inner_area = popup_block.inner(area);
+                    frame.render_widget(popup_block, area);
+                    frame.render_widget(
+                        Paragraph::new(format!("> {}", self.file_finder_query)).style(Style::default().fg(Color::White)),
+                        Rect::new(inner_area.x, inner_area.y, inner_area.width, 1),
+                    );
+                    frame.set_cursor_position((
+                        inner_area.x + 2 + self.file_finder_query.width() as u16,
+                        inner_area.y
+                    ));
+                    let list_height = inner_area.height.saturating_sub(2) as usize;
+                    let first = (self.file_finder_selection + 1).saturating_sub(list_height);
+                    let text: Vec<Line> = self.file_finder_results.iter().enumerate()
+                        .skip(first)
+                        .take(list_height)
+                        .map(|(i, (index, indices))| {
+                            let style = if i == self.file_finder_selection {
+                                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+                            } else {
+                                Style::default().fg(Color::White)
+                            };
+                            let mut spans = vec![
+                                Span::styled(format!(" {} ", if i == self.file_finder_selection { "󰄾" } else { " " }), style),
+                            ];
+                            spans.extend(self.file_finder_files[*index].1.chars().enumerate().map(|(j, c)| {
+                                if indices.contains(&j) {
+                                    Span::styled(c.to_string(), style.fg(Color::Yellow).add_modifier(Modifier::BOLD))
+                                } else {
+                                    Span::styled(c.to_string(), style)
+                                }
+                            }));
+                            Line::from(spans)
+                        })
+                        .collect();
+                    frame.render_widget(
+                        Paragraph::new(text),
+                        Rect::new(inner_area.x, inner_area.y + 2, inner_area.width, list_height as u16),
+                    );
+                },
+                PopupType::GoToSymbol => {
+                    let width = (area.width * 3 / 5).max(20).min(area.width);
+                    let height = (area.height * 3 / 5).max(6).min(area.height);
+                    let area = Rect::new(
+                        (area.width - width) / 2,
+                        (area.height - height) / 4,
+                        width,
+                        height,
+                    );
+                    frame.render_widget(Clear, area);
+                    let status = format!(
+                        " {}{} of {} symbols · Tab: {} ",
+                        if self.symbol_project && self.symbol_index_receiver.is_some() { "Indexing… " } else { "" },
+                        self.symbol_results.len(),
+                        self.symbol_entries.len(),
+                        if self.symbol_project { "file" } else { "project" },
+                    );
+                    let popup_block = Block::default()
+                        .title(if self.symbol_project { " Go to Symbol in Project " } else { " Go to Symbol " })
+                        .title_alignment(Alignment::Center)
+                        .title_bottom(Line::styled(status, Style::default().fg(Color::DarkGray)))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Cyan));
+                    let inner_area = popup_block.inner(area);
+                    frame.render_widget(popup_block, area);
+                    frame.render_widget(
+                        Paragraph::new(format!("@ {}", self.symbol_query)).style(Style::default().fg(Color::White)),
+                        Rect::new(inner_area.x, inner_area.y, inner_area.width, 1),
+                    );
+                    frame.set_cursor_position((
+                        inner_area.x + 2 + self.symbol_query.width() as u16,
+                        inner_area.y
+                    ));
+                    let list_height = inner_area.height.saturating_sub(2) as usize;
+                    let first = (self.symbol_selection + 1).saturating_sub(list_height);
+                    let text: Vec<Line> = self.symbol_results.iter().enumerate()
+                        .skip(first)
+                        .take(list_height)
+                        .map(|(i, (index, indices))| {
+                            let (_, location, symbol) = &self.symbol_entries[*index];
+                            let style = if i == self.symbol_selection {
+                                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+                            } else {
+                                Style::default().fg(Color::White)
+                            };
+                            let (icon, color) = Self::symbol_icon(&symbol.kind);
+                            let mut spans = vec![
+                                Span::styled(format!(" {} ", if i == self.symbol_selection { "󰄾" } else { " " }), style),
+                                Span::styled(format!("{} ", icon), style.fg(color)),
+                            ];
+                            spans.extend(symbol.name.chars().enumerate().map(|(j, c)| {
+                                if indices.contains(&j) {
+                                    Span::styled(c.to_string(), style.fg(Color::Yellow).add_modifier(Modifier::BOLD))
+                                } else {
+                                    Span::styled(c.to_string(), style)
+                                }
+                            }));
+                            spans.push(Span::styled(format!("  {}", location), Style::default().fg(Color::DarkGray)));
+                            Line::from(spans)
+                        })
+                        .collect();
+                    frame.render_widget(
+                        Paragraph::new(text),
+                        Rect::new(inner_area.x, inner_area.y + 2, inner_area.width, list_height as u16),
+                    );
+                },
+                PopupType::ProjectReplaceReport => {
+                    let width = (area.width * 3 / 5).max(20).min(area.width);
+                    let height = (self.project_replace_report.len() as u16 + 2).clamp(3, area.height);
+                    let area = Rect::new(
+                        (area.width - width) / 2,
+                        (area.height - height) / 2,
+                        width,
+                        height,
+                    );
+                    frame.render_widget(Clear, area);
+                    let popup_block = Block::default()
+                        .title(" Files Changed ")
+                        .title_alignment(Alignment::Center)
+                        .title_bottom(Line::styled(" Enter: review  Esc: close ", Style::default().fg(Color::DarkGray)))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Cyan));
+                    let inner_area = popup_block.inner(area);
+                    frame.render_widget(popup_block, area);
+                    let visible = inner_area.height as usize;
+                    let first = (self.project_search_selection + 1).saturating_sub(visible);
+                    let text: Vec<Line> = self.project_replace_report.iter().enumerate()
+                        .skip(first)
+                        .take(visible)
+                        .map(|(i, (path, note))| {
+                            let style = if i == self.project_search_selection {
+                                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+                            } else {
+                                Style::default().fg(Color::White)
+                            };
+                            let path = path.strip_prefix(&self.current_dir).unwrap_or(path);
+                            Line::from(vec![
+                                Span::styled(format!(" {} ", if i == self.project_search_selection { "󰄾" } else { " " }), style),
+                                Span::styled(path.display().to_string(), style),
+                                Span::styled(format!("  {}", note), Style::default().fg(Color::DarkGray)),
+                            ])
+                        })
+                        .collect();
+                    frame.render_widget(Paragraph::new(text), inner_area);
+                },
+                PopupType::QueryReplace => {
+                    let area = Rect::new(
+                        area.width / 4,
+                        area.height.saturating_sub(6),
+                        area.width / 2,
+                        3
+                    );
+                    frame.render_widget(Clear, area);
+                    let total = self.query_replace_matches.len();
+                    let done = total - self.query_replace_matches.iter().filter(|m| m.pending).count();
+                    let popup_block = Block::default()
+                        .title(format!(" Replace {}/{} ", done, total))
+                        .title_alignment(Alignment::Center)
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Green));
+                    let inner_area = popup_block.inner(area);
+                    frame.render_widget(popup_block, area);
+                    let text = Paragraph::new(Line::from(vec![
+                        Span::styled("Replace this match? ", Style::default().fg(Color::White)),
+                        Span::styled("y", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
+                        Span::raw("es "),
+                        Span::styled("n", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+                        Span::raw("o "),
+                        Span::styled("a", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::raw("ll "),
+                        Span::styled("q", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+                        Span::raw("uit"),
+                    ]))
+                        .alignment(Alignment::Center);
+                    frame.render_widget(text, inner_area);
+                },
+                PopupType::UndoTree => {
+                    let width = 56.min(area.width);
+                    let height = (area.height * 2 / 3).max(8).min(area.height);
+                    let area = Rect::new(
+                        (area.width - width) / 2,
+                        (area.height - height) / 2,
+                        width,
+                        height,
+                    );
+                    frame.render_widget(Clear, area);
+                    let popup_block = Block::default()
+                        .title(" Undo Tree ")
+                        .title_alignment(Alignment::Center)
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Cyan));
+                    let inner_area = popup_block.inner(area);
+                    frame.render_widget(popup_block, area);
+                    let list_height = inner_area.height.saturating_sub(1) as usize;
+                    let first_row = (self.undo_tree_selection + 1).saturating_sub(list_height);
+                    let text: Vec<Line> = self.undo_tree.rows().iter().enumerate()
+                        .skip(first_row)
+                        .take(list_height)
+                        .map(|(i, &(node, depth))| {
+                            let entry = &self.undo_tree.nodes[node];
+                            let style = if i == self.undo_tree_selection {
+                                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+                            } else {
+                                Style::default().fg(Color::White)
+                            };
+                            let marker = if node == self.undo_tree.current { "◆" } else { "○" };
+                            let summary = match &entry.delta {
+                                Some(delta) => format!(
+                                    "#{} line {} +{} -{}",
+                                    node,
+                                    delta.start_line + 1,
+                                    delta.new_lines.len(),
+                                    delta.old_lines.len()
+                                ),
+                                None => format!("#{} original", node),
+                            };
+                            Line::from(vec![
+                                Span::styled(format!(" {}{} ", "│ ".repeat(depth), marker), Style::default().fg(Color::Cyan)),
+                                Span::styled(summary, style),
+                                Span::styled(format!("  {}", Self::format_age(entry.timestamp)), Style::default().fg(Color::DarkGray)),
+                            ])
+                        })
+                        .collect();
+                    let list_area = Rect::new(inner_area.x, inner_area.y, inner_area.width, list_height as u16);
+                    frame.render_widget(Paragraph::new(text), list_area);
+                    let footer = if self.undo_time_input.is_empty() {
+                        " ↑↓ preview  Enter keep  Esc cancel  5m/+5m travel".to_string()
+                    } else {
+                        format!(" Travel: {}", self.undo_time_input)
+                    };
+                    let footer_area = Rect::new(inner_area.x, inner_area.y + list_height as u16, inner_area.width, 1);
+                    frame.render_widget(Paragraph::new(footer).style(Style::default().fg(Color::DarkGray)), footer_area);
+                },
+                PopupType::EncodingSelect(action) => {
+                    let height = (ENCODINGS.len() as u16 + 2).min(area.height);
+                    let area = Rect::new(
+                        area.width / 2 - 14,
+                        (area.height.saturating_sub(height)) / 2,
+                        28.min(area.width),
+                        height,
+                    );
+                    frame.render_widget(Clear, area);
+                    let popup_block = Block::default()
+                        .title(match action {
+                            EncodingAction::Reopen => " Reopen with Encoding ",
+                            EncodingAction::Save => " Save with Encoding ",
+                        })
+                        .title_alignment(Alignment::Center)
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Cyan));
+                    let inner_area = popup_block.inner(area);
+                    frame.render_widget(popup_block, area);
+                    let text: Vec<Line> = ENCODINGS.iter().enumerate().map(|(i, label)| {
+                        let style = if i == self.encoding_selection {
+                            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+                        } else {
+                            Style::default().fg(Color::White)
+                        };
+                        Line::from(vec![
+                            Span::styled(format!(" {} ", if i == self.encoding_selection { "󰄾" } else { " " }), style),
+                            Span::styled(*label, style),
+                        ])
+                    }).collect();
+                    frame.render_widget(Paragraph::new(text), inner_area);
+                },
+                PopupType::RecentFiles => {
+                    let area = Rect::new(
+                        area.width / 2 - (area.width / 4) / 2,
+                        area.height / 3,
+                        area.width / 4,
+                        area.height / 3,
+                    );
+                    frame.render_widget(Clear, area);
+                    let popup_block = Block::default()
+                        .title(" Recent Files ")
+                        .title_alignment(Alignment::Center)
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Cyan));
+                    let inner_area = popup_block.inner(area);
+                    frame.render_widget(popup_block, area);
+                    let recent_files_text: Vec<Line> = self.recent_files
+                        .iter()
+                        .enumerate()
+                        .map(|(i, rf)| {
+                            let status = "  ";
+                            let path = Self::format_path(&rf.path);
+                            let style = if i == self.recent_files_selection {
+                                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+                            } else {
+                                Style::default().fg(Color::White)
+                            };
+                            Line::from(vec![
+                                Span::styled(format!(" {} ", if i == self.recent_files_selection { "󰄾" } else { " " }), style),
+                                Span::styled(status, if rf.exists { style } else { style.fg(Color::Red) }),
+                                Span::styled(path, style),
+                            ])
+                        })
+                        .collect();
+                    let text = if recent_files_text.is_empty() {
+                        vec![Line::from(Span::styled("No recent files", Style::default().fg(Color::Gray)))]
+                    } else {
+                        let mut text = recent_files_text;
+                        text.push(Line::from(""));
+                        text.push(Line::from(Span::styled("         Enter to select, Esc to cancel", Style::default().fg(Color::DarkGray))));
+                        text
+                    };
+                    let paragraph = Paragraph::new(text)
+                        .alignment(Alignment::Left);
+                    frame.render_widget(paragraph, inner_area);
+                },
+                PopupType::None => {
+                    let visible_width = inner.width.saturating_sub(if self.show_numbers { 5 } else { 1 }).max(1) as usize;
+                    let cursor_x = cursor_display % visible_width;
+                    let base_offset = if self.show_numbers { 5 } else { 1 };
+                    frame.set_cursor_position((
+                        inner.x + cursor_x as u16 + base_offset,
+                        inner.y + cursor_row as u16
+                    ));
+                },
+                PopupType::JumpToLine => {
+                    let area = Rect::new(
+                        area.width / 4,
+                        area.height / 2 - 2,
+                        area.width / 2,
+                        3
+                    );
+                    frame.render_widget(Clear, area);
+                    let popup_block = Block::default()
+                        .title("Jump to Line")
+                        .title_alignment(Alignment::Center)
+                        .title_bottom(Line::styled(" @ for symbols ", Style::default().fg(Color::DarkGray)).right_aligned())
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::White));
+                    let inner_area = popup_block.inner(area);
+                    frame.render_widget(popup_block, area);
+                    let input = Paragraph::new(format!("Line number: {}", self.search_query))
+                        .style(Style::default().fg(Color::White));
+                    frame.render_widget(input, inner_area);
+                    frame.set_cursor_position((
+                        area.x + 11 + self.search_query.len() as u16,
+                        area.y + 1
+                    ));
+                },
+                PopupType::ReplaceQuery => {
+                    let area = Rect::new(
+                        area.width / 4,
+                        area.height / 2 - 2,
+                        area.width / 2,
+                        5
+                    );
+                    frame.render_widget(Clear, area);
+                    let popup_block = Block::default()
+                        .title("Find")
+                        .title_alignment(Alignment::Center)
+                        .title(Line::from(search_options.as_str()).right_aligned())
                         .borders(Borders::ALL)
                         .border_style(Style::default().fg(Color::White));
                     let inner_area = popup_block.inner(area);
                     frame.render_widget(popup_block, area);
+                    let footer = match &self.search_error {
+                        Some(error) => Span::styled(error.clone(), Style::default().fg(Color::Red)),
+                        None => Span::styled(
+                            "Enter: confirm  Esc: cancel  Alt+r/c/w: regex/case/word",
+                            Style::default().fg(Color::DarkGray)
+                        ),
+                    };
                     let input = Paragraph::new(vec![
                         Line::from(format!("Find: {}", self.search_query)),
                         Line::from(""),
-                        Line::from(Span::styled("Enter: confirm  Esc: cancel", Style::default().fg(Color::DarkGray)))
+                        Line::from(footer)
                     ])
                         .style(Style::default().fg(Color::White));
                     frame.render_widget(input, inner_area);
-                    frame.set_cursor(
+                    frame.set_cursor_position((
                         area.x + 7 + self.search_query.len() as u16,
                         area.y + 1
-                    );
+                    ));
                 },
                 PopupType::ReplaceWithQuery => {
                     let area = Rect::new(
@@ -1221,10 +1899,10 @@ impl Editor {
                     ])
                         .style(Style::default().fg(Color::White));
                     frame.render_widget(input, inner_area);
-                    frame.set_cursor(
+                    frame.set_cursor_position((
                         area.x + 14 + self.replace_text.len() as u16,
                         area.y + 1
-                    );
+                    ));
                 },
                 PopupType::FileChanged => {
                     let area = Rect::new(
@@ -1249,6 +1927,7 @@ impl Editor {
                 PopupType::Replace => {
                     self.popup_state = PopupType::ReplaceQuery;
                     self.search_query.clear();
+                    self.search_error = None;
                     self.replace_text.clear();
                 },
                 PopupType::NewFile => {
@@ -1269,10 +1948,10 @@ impl Editor {
                     let input = Paragraph::new(format!("Filename: {}", self.temp_filename))
                         .style(Style::default().fg(Color::White));
                     frame.render_widget(input, inner_area);
-                    frame.set_cursor(
+                    frame.set_cursor_position((
                         area.x + 11 + self.temp_filename.len() as u16,
                         area.y + 1
-                    );
+                    ));
                 },
                 PopupType::NewDirectory => {
                     let area = Rect::new(
@@ -1292,44 +1971,24 @@ impl Editor {
                     let input = Paragraph::new(format!("Directory name: {}", self.temp_filename))
                         .style(Style::default().fg(Color::White));
                     frame.render_widget(input, inner_area);
-                    frame.set_cursor(
+                    frame.set_cursor_position((
                         area.x + 17 + self.temp_filename.len() as u16,
                         area.y + 1
-                    );
+                    ));
                 },
-                PopupType::FileChanged => {
-                    let area = Rect::new(
-                        area.width / 4,
-                        area.height / 2 - 2,
-                        area.width / 2,
-                        3
-                    );
-                    frame.render_widget(Clear, area);
-                    let popup_block = Block::default()
-                        .title("File Changed")
-                        .title_alignment(Alignment::Center)
-                        .borders(Borders::ALL)
-                        .border_style(Style::default().fg(Color::Yellow));
-                    let inner_area = popup_block.inner(area);
-                    frame.render_widget(popup_block, area);
-                    let text = Paragraph::new("File changed on disk. Reload? (y/n)")
-                        .style(Style::default().fg(Color::White))
-                        .alignment(Alignment::Center);
-                    frame.render_widget(text, inner_area);
-                }
             }
             if suggestions_visible {
                 if let Some(word) = &current_word {
                     let suggestions_height = (self.suggestions.len() + 2) as u16;
                     let suggestions_width = self.suggestions.iter()
-                        .map(|s| s.len())
+                        .map(|s| s.width())
                         .max()
                         .unwrap_or(0)
-                        .max(word.len()) as u16 + 4;
-                    let visible_width = inner.width.saturating_sub(if self.show_numbers { 5 } else { 1 }) as usize;
-                    let cursor_x = inner.x + (self.cursor_position.0 % visible_width) as u16 + if self.show_numbers { 5 } else { 1 };
-                    let cursor_y = inner.y + self.cursor_position.1 as u16 - self.scroll_offset;
-                    let mut suggestions_x = cursor_x.saturating_sub(word.len() as u16);
+                        .max(word.width()) as u16 + 4;
+                    let visible_width = inner.width.saturating_sub(if self.show_numbers { 5 } else { 1 }).max(1) as usize;
+                    let cursor_x = inner.x + (cursor_display % visible_width) as u16 + if self.show_numbers { 5 } else { 1 };
+                    let cursor_y = inner.y + cursor_row as u16;
+                    let mut suggestions_x = cursor_x.saturating_sub(word.width() as u16);
                     if suggestions_x + suggestions_width > inner.x + inner.width {
                         suggestions_x = (inner.x + inner.width).saturating_sub(suggestions_width);
                     }
@@ -1384,27 +2043,36 @@ impl Editor {
             self.popup_state = PopupType::OverwriteConfirm(path.to_string_lossy().into_owned());
             return Ok(());
         }
-        let content = self.content.join("\n") + "\n";
-        match fs::write(&path, &content) {
+        if self.content.decode_errors && path.exists() {
+            self.popup_state = PopupType::LossySaveConfirm;
+            return Ok(());
+        }
+        self.write_current_file(&path);
+        Ok(())
+    }
+    fn write_current_file(&mut self, path: &PathBuf) {
+        match self.content.write_file_atomic(path) {
             Ok(_) => {
                 self.modified = false;
+                self.content.decode_errors = false;
                 self.popup_state = PopupType::None;
                 self.add_to_recent_files(path.clone());
-                if let Ok(metadata) = fs::metadata(&path) {
+                self.save_undo_history(path);
+                self.undo_tree.mark_saved();
+                if let Ok(metadata) = fs::metadata(path) {
                     let modified = metadata.modified().ok();
                     self.last_modified = modified;
                     self.last_save_time = modified;
                 }
-                self.set_status_message(format!("Saved {}", Self::format_path(&path)));
-                Ok(())
+                self.set_status_message(format!("Saved {}", Self::format_path(path)));
             }
             Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                self.popup_state = PopupType::None;
                 self.set_status_message("Permission denied. Use 'sudo red' to edit this file.");
-                Ok(())
             }
             Err(e) => {
+                self.popup_state = PopupType::None;
                 self.set_status_message(format!("Error saving file: {}", e));
-                Ok(())
             }
         }
     }
@@ -1412,9 +2080,6 @@ impl Editor {
         self.status_message = Some((message.into(), std::time::Instant::now()));
     }
     fn run(&mut self) -> std::io::Result<()> {
-        if self.content.is_empty() {
-            self.content.push(String::new());
-        }
         let panic_hook = std::panic::take_hook();
         std::panic::set_hook(Box::new(move |panic_info| {
             let _ = terminal::disable_raw_mode();
@@ -1438,10 +2103,7 @@ impl Editor {
                     "Unknown error".to_string()
                 };
                 self.log_error(&error_msg);
-                Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    error_msg
-                ))
+                Err(std::io::Error::other(error_msg))
             }
         }
     }
@@ -1450,6 +2112,10 @@ impl Editor {
         let draw_timeout = std::time::Duration::from_millis(16);
         loop {
             self.check_file_changes()?;
+            self.check_config_changes();
+            self.poll_project_search();
+            self.poll_symbol_index();
+            self.poll_file_index();
             if last_draw.elapsed() >= draw_timeout {
                 if let Err(e) = self.draw() {
                     self.log_error(&format!("Draw error: {}", e));
@@ -1459,12 +2125,10 @@ impl Editor {
             }
             if event::poll(draw_timeout)? {
                 match event::read()? {
-                    Event::Key(key) => {
-                        if key.kind == KeyEventKind::Press {
-                            if let Err(e) = self.handle_keypress(key) {
-                                self.log_error(&format!("Keypress error: {}", e));
-                                return Err(e);
-                            }
+                    Event::Key(key) if key.kind == KeyEventKind::Press => {
+                        if let Err(e) = self.handle_keypress(key) {
+                            self.log_error(&format!("Keypress error: {}", e));
+                            return Err(e);
                         }
                     }
                     Event::Mouse(mouse_event) => {
@@ -1485,74 +2149,78 @@ impl Editor {
         }
     }
     fn handle_mouse_event(&mut self, mouse_event: event::MouseEvent) -> std::io::Result<()> {
-        match mouse_event.kind {
-            event::MouseEventKind::Down(event::MouseButton::Left) => {
-                let (x, y) = (mouse_event.column as usize, mouse_event.row as usize);
-                self.update_cursor_position_from_mouse(x, y);
-            }
-            _ => {}
+        self.update_syntax();
+        if let event::MouseEventKind::Down(event::MouseButton::Left) = mouse_event.kind {
+            let (x, y) = (mouse_event.column as usize, mouse_event.row as usize);
+            self.extra_cursors.clear();
+            self.update_cursor_position_from_mouse(x, y);
         }
         Ok(())
     }
     fn update_cursor_position_from_mouse(&mut self, x: usize, y: usize) {
-        let x_offset = if self.show_numbers { 5 } else { 0 };
-        let adjusted_x = if x > x_offset { x - x_offset } else { 0 };
-        let line_index = y + self.scroll_offset as usize;
-        if line_index < self.content.len() {
-            let line = &self.content[line_index];
-            let mut char_index = 0;
-            let mut visual_position = 0;
-            let target_x = adjusted_x;
-            for (idx, ch) in line.chars().enumerate() {
-                if visual_position >= target_x {
-                    break;
-                }
-                let width = if ch == '\t' {
-                    4 - (visual_position % 4)
-                } else {
-                    1
-                };
-                char_index = idx + 1;
-                visual_position += width;
-            }
-            if visual_position < target_x && !line.is_empty() {
-                char_index = line.chars().count();
-            }
-            self.cursor_position = (char_index, line_index);
+        let position = Position::new(x as u16, y as u16);
+        let Some(index) = self.pane_areas.iter().position(|area| area.contains(position)) else {
+            return;
+        };
+        if index != self.focused_split {
+            self.focus_pane(index);
+        }
+        let inner = Block::default().borders(Borders::ALL).inner(self.pane_areas[index]);
+        if !inner.contains(position) {
+            return;
         }
+        let gutter = if self.show_numbers { 5 } else { 1 };
+        let tab_width = self.tab_width();
+        let row = y - inner.y as usize;
+        let col = (x - inner.x as usize).saturating_sub(gutter);
+        let line_index = self.folds.step(self.scroll_offset, row as isize, self.content.len_lines());
+        let visible_width = (inner.width as usize).saturating_sub(gutter);
+        let visible_start = if line_index == self.cursor_position.1 && visible_width > 0 {
+            self.content.display_col(self.cursor_position, tab_width) / visible_width * visible_width
+        } else {
+            0
+        };
+        let char_index = self.content.col_for_display(line_index, visible_start + col, tab_width);
+        self.cursor_position = (char_index, line_index);
     }
     fn handle_enter_key(&mut self) {
-        let current_line = &self.content[self.cursor_position.1];
-        let indent = current_line.chars().take_while(|c| c.is_whitespace()).collect::<String>();
-        let remainder = current_line[self.cursor_position.0..].to_string();
-        self.content[self.cursor_position.1] = current_line[..self.cursor_position.0].to_string();
-        self.content.insert(self.cursor_position.1 + 1, format!("{}{}", indent, remainder));
-        self.cursor_position.1 += 1;
-        self.cursor_position.0 = indent.len();
+        self.cursor_position = self.content.clamp(self.cursor_position);
+        let indent = self.content.line(self.cursor_position.1)
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect::<String>();
+        self.cursor_position = self.content.insert(self.cursor_position, &format!("\n{}", indent));
         self.modified = true;
     }
-    fn handle_left_key(&mut self) {
-        if self.cursor_position.0 > 0 {
-            self.cursor_position.0 -= 1;
-        } else if self.cursor_position.1 > 0 {
-            self.cursor_position.1 -= 1;
-            self.cursor_position.0 = self.content[self.cursor_position.1].len();
+    fn set_cursor_line(&mut self, line_idx: usize) {
+        let display = self.content.display_col(self.cursor_position, self.tab_width());
+        let mut line_idx = line_idx.min(self.content.len_lines() - 1);
+        if self.folds.is_hidden(line_idx) {
+            let fold_start = self.folds.visible_line(line_idx);
+            line_idx = if line_idx > self.cursor_position.1 {
+                self.folds.next_visible(fold_start, self.content.len_lines()).unwrap_or(fold_start)
+            } else {
+                fold_start
+            };
+        }
+        self.cursor_position = (self.content.col_for_display(line_idx, display, self.tab_width()), line_idx);
+    }
+    fn handle_left_key(&mut self) {
+        if self.cursor_position.0 > 0 {
+            self.cursor_position.0 = self.content.prev_grapheme(self.cursor_position);
+        } else if let Some(line_idx) = self.folds.prev_visible(self.cursor_position.1) {
+            self.cursor_position = (self.content.line_len(line_idx), line_idx);
         }
     }
     fn handle_right_key(&mut self) {
-        let current_line = &self.content[self.cursor_position.1];
-        let current_line_len = current_line.len();
-        if self.cursor_position.0 < current_line_len {
-            self.cursor_position.0 += 1;
-        } else if self.cursor_position.1 < self.content.len() - 1 {
-            self.cursor_position.1 += 1;
-            self.cursor_position.0 = 0;
+        if self.cursor_position.0 < self.content.line_len(self.cursor_position.1) {
+            self.cursor_position.0 = self.content.next_grapheme(self.cursor_position);
+        } else if let Some(line_idx) = self.folds.next_visible(self.cursor_position.1, self.content.len_lines()) {
+            self.cursor_position = (0, line_idx);
         }
     }
     fn handle_keypress(&mut self, key: KeyEvent) -> std::io::Result<()> {
-            match key.code {
-                _ => {}
-            }
+        self.update_syntax();
         if matches!((key.code, key.modifiers),
             (KeyCode::Tab, KeyModifiers::ALT) |
             (KeyCode::Tab, KeyModifiers::NONE))
@@ -1566,13 +2234,11 @@ impl Editor {
                         self.popup_state = PopupType::None;
                         self.temp_filename.clear();
                     }
-                    (KeyCode::Enter, _) => {
-                        if !self.temp_filename.is_empty() {
-                            self.filename = Some(PathBuf::from(&self.temp_filename));
-                            self.temp_filename.clear();
-                            self.popup_state = PopupType::None;
-                            self.save()?;
-                        }
+                    (KeyCode::Enter, _) if !self.temp_filename.is_empty() => {
+                        self.filename = Some(PathBuf::from(&self.temp_filename));
+                        self.temp_filename.clear();
+                        self.popup_state = PopupType::None;
+                        self.save()?;
                     }
                     (KeyCode::Esc, _) => {
                         self.popup_state = PopupType::None;
@@ -1600,12 +2266,10 @@ impl Editor {
                             PopupType::None
                         };
                     }
-                    KeyCode::Up => {
-                        if self.file_explorer_selection > 0 {
-                            self.file_explorer_selection -= 1;
-                            if self.file_explorer_selection < self.file_tree_scroll_offset as usize {
-                                self.file_tree_scroll_offset = self.file_explorer_selection as u16;
-                            }
+                    KeyCode::Up if self.file_explorer_selection > 0 => {
+                        self.file_explorer_selection -= 1;
+                        if self.file_explorer_selection < self.file_tree_scroll_offset as usize {
+                            self.file_tree_scroll_offset = self.file_explorer_selection as u16;
                         }
                     }
                     KeyCode::Down => {
@@ -1650,19 +2314,7 @@ impl Editor {
                 match key.code {
                     KeyCode::Char('y') | KeyCode::Char('Y') => {
                         let path = self.filename.as_ref().unwrap().clone();
-                        let content = self.content.join("\n") + "\n";
-                        match fs::write(&path, &content) {
-                            Ok(_) => {
-                                self.modified = false;
-                                self.popup_state = PopupType::None;
-                                self.add_to_recent_files(path.clone());
-                                self.set_status_message(format!("Saved {}", Self::format_path(&path)));
-                            }
-                            Err(e) => {
-                                self.popup_state = PopupType::None;
-                                self.set_status_message(format!("Error saving file: {}", e));
-                            }
-                        }
+                        self.write_current_file(&path);
                     }
                     KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
                         self.popup_state = PopupType::Save;
@@ -1671,8 +2323,30 @@ impl Editor {
                     _ => {}
                 }
             }
+            PopupType::LossySaveConfirm => {
+                match key.code {
+                    KeyCode::Char('y') | KeyCode::Char('Y') => {
+                        let path = self.filename.as_ref().unwrap().clone();
+                        self.write_current_file(&path);
+                    }
+                    KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                        self.popup_state = PopupType::None;
+                        self.set_status_message("Not saved. Reopen with the correct encoding to keep the original bytes");
+                    }
+                    _ => {}
+                }
+            }
             PopupType::Find => {
                 match key.code {
+                    _ if self.keymap.lookup(&key, &[Context::Search]).is_some_and(|command| self.toggle_search_option(command)) => {
+                        self.search_index = None;
+                        self.find_next();
+                    }
+                    KeyCode::Enter if key.modifiers.contains(KeyModifiers::ALT) => {
+                        self.find_next();
+                        self.popup_state = PopupType::None;
+                        self.add_cursors_at_matches();
+                    }
                     KeyCode::Enter => {
                         self.find_next();
                         if !self.highlighted_matches.is_empty() {
@@ -1691,14 +2365,12 @@ impl Editor {
                     KeyCode::Char(c) => {
                         self.handle_search_input(c);
                     }
-                    KeyCode::Backspace => {
+                    KeyCode::Backspace if !self.search_query.is_empty() => {
+                        self.search_query.pop();
                         if !self.search_query.is_empty() {
-                            self.search_query.pop();
-                            if !self.search_query.is_empty() {
-                                self.find_next();
-                            } else {
-                                self.highlighted_matches.clear();
-                            }
+                            self.find_next();
+                        } else {
+                            self.highlighted_matches.clear();
                         }
                     }
                     _ => {}
@@ -1706,19 +2378,11 @@ impl Editor {
             }
             PopupType::Open => {
                 match key.code {
-                    KeyCode::Enter => {
-                        if !self.temp_filename.is_empty() {
-                            let path = PathBuf::from(&self.temp_filename);
-                            let temp_filename = self.temp_filename.clone();
-                            if self.modified {
-                                self.temp_filename = temp_filename;
-                                self.popup_state = PopupType::SaveConfirm(SaveAction::OpenFile);
-                            } else {
-                                self.open_file(&path)?;
-                                self.temp_filename.clear();
-                                self.popup_state = PopupType::None;
-                            }
-                        }
+                    KeyCode::Enter if !self.temp_filename.is_empty() => {
+                        let path = PathBuf::from(&self.temp_filename);
+                        self.open_file(&path)?;
+                        self.temp_filename.clear();
+                        self.popup_state = PopupType::None;
                     }
                     KeyCode::Esc => {
                         self.popup_state = if self.show_initial_menu {
@@ -1783,11 +2447,7 @@ impl Editor {
                                 }
                             }
                             3 => {
-                                self.content = vec![String::new()];
-                                self.cursor_position = (0, 0);
-                                self.filename = None;
-                                self.modified = false;
-                                self.scroll_offset = 0;
+                                self.new_tab(EditorTab::default());
                                 self.show_initial_menu = false;
                                 self.popup_state = PopupType::None;
                             }
@@ -1795,12 +2455,7 @@ impl Editor {
                                 self.show_help();
                             }
                             5 => {
-                                if self.modified {
-                                    self.popup_state = PopupType::SaveConfirm(SaveAction::Exit);
-                                } else {
-                                    self.cleanup()?;
-                                    std::process::exit(0);
-                                }
+                                self.try_exit();
                             }
                             _ => {}
                         }
@@ -1808,6 +2463,334 @@ impl Editor {
                     _ => {}
                 }
             }
+            PopupType::ProjectSearch => {
+                match key.code {
+                    _ if self.keymap.lookup(&key, &[Context::Search]).is_some_and(|command| self.toggle_search_option(command)) => {
+                        self.project_search_dirty = true;
+                    }
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::ALT) && !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.project_search_query.push(c);
+                        self.project_search_dirty = true;
+                    }
+                    KeyCode::Backspace => {
+                        self.project_search_query.pop();
+                        self.project_search_dirty = true;
+                    }
+                    KeyCode::Up => {
+                        self.project_search_selection = self.project_search_selection.saturating_sub(1);
+                    }
+                    KeyCode::Down => {
+                        self.project_search_selection = (self.project_search_selection + 1)
+                            .min(self.project_search_hits.len().saturating_sub(1));
+                    }
+                    KeyCode::PageUp => {
+                        self.project_search_selection = self.project_search_selection.saturating_sub(10);
+                    }
+                    KeyCode::PageDown => {
+                        self.project_search_selection = (self.project_search_selection + 10)
+                            .min(self.project_search_hits.len().saturating_sub(1));
+                    }
+                    _ if self.keymap.lookup(&key, &[Context::ProjectSearch]) == Some(Command::ProjectReplace) => {
+                        self.open_project_replace();
+                    }
+                    KeyCode::Enter if self.project_search_dirty || self.project_search_hits.is_empty() => {
+                        self.start_project_search();
+                    }
+                    KeyCode::Enter => {
+                        self.open_project_search_hit()?;
+                    }
+                    KeyCode::Esc => {
+                        self.popup_state = PopupType::None;
+                    }
+                    _ => {}
+                }
+            }
+            PopupType::ProjectReplace => {
+                let len = self.project_search_hits.len();
+                match key.code {
+                    KeyCode::Char('a') if key.modifiers == KeyModifiers::ALT => {
+                        let all = self.project_replace_checked.iter().all(|&on| on);
+                        self.project_replace_checked.iter_mut().for_each(|on| *on = !all);
+                    }
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::ALT) && !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.project_replace_text.push(c);
+                    }
+                    KeyCode::Backspace => {
+                        self.project_replace_text.pop();
+                    }
+                    KeyCode::Up => {
+                        self.project_search_selection = self.project_search_selection.saturating_sub(1);
+                    }
+                    KeyCode::Down => {
+                        self.project_search_selection = (self.project_search_selection + 1).min(len.saturating_sub(1));
+                    }
+                    KeyCode::PageUp => {
+                        self.project_search_selection = self.project_search_selection.saturating_sub(10);
+                    }
+                    KeyCode::PageDown => {
+                        self.project_search_selection = (self.project_search_selection + 10).min(len.saturating_sub(1));
+                    }
+                    KeyCode::Tab => {
+                        if let Some(on) = self.project_replace_checked.get_mut(self.project_search_selection) {
+                            *on = !*on;
+                        }
+                    }
+                    KeyCode::BackTab => {
+                        if let Some(hit) = self.project_search_hits.get(self.project_search_selection) {
+                            let path = hit.path.clone();
+                            let indices: Vec<usize> = (0..len).filter(|&i| self.project_search_hits[i].path == path).collect();
+                            let all = indices.iter().all(|&i| self.project_replace_checked[i]);
+                            for i in indices {
+                                self.project_replace_checked[i] = !all;
+                            }
+                        }
+                    }
+                    KeyCode::Enter => {
+                        self.apply_project_replace();
+                    }
+                    KeyCode::Esc => {
+                        self.popup_state = PopupType::ProjectSearch;
+                    }
+                    _ => {}
+                }
+            }
+            PopupType::Settings => {
+                let rows = self.settings_rows().len();
+                match key.code {
+                    KeyCode::Up => {
+                        self.settings_selection = self.settings_selection.checked_sub(1).unwrap_or(rows - 1);
+                    }
+                    KeyCode::Down => {
+                        self.settings_selection = (self.settings_selection + 1) % rows;
+                    }
+                    KeyCode::Left => self.change_setting(false),
+                    KeyCode::Right | KeyCode::Enter | KeyCode::Char(' ') => self.change_setting(true),
+                    KeyCode::Char('o') => self.open_config_file(),
+                    KeyCode::Esc => {
+                        self.popup_state = PopupType::None;
+                    }
+                    _ => {}
+                }
+            }
+            PopupType::CommandPalette => {
+                let len = self.palette_results.len();
+                match key.code {
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::ALT) && !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.palette_query.push(c);
+                        self.update_command_palette();
+                    }
+                    KeyCode::Backspace => {
+                        self.palette_query.pop();
+                        self.update_command_palette();
+                    }
+                    KeyCode::Up => {
+                        self.palette_selection = self.palette_selection
+                            .checked_sub(1)
+                            .unwrap_or(len.saturating_sub(1));
+                    }
+                    KeyCode::Down => {
+                        self.palette_selection = (self.palette_selection + 1) % len.max(1);
+                    }
+                    KeyCode::Enter => {
+                        if let Some(&(index, _)) = self.palette_results.get(self.palette_selection) {
+                            self.popup_state = PopupType::None;
+                            self.run_command(COMMANDS[index].command)?;
+                        }
+                    }
+                    KeyCode::Esc => {
+                        self.popup_state = PopupType::None;
+                    }
+                    _ => {}
+                }
+            }
+            PopupType::FileFinder => {
+                let len = self.file_finder_results.len();
+                match key.code {
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::ALT) && !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.file_finder_query.push(c);
+                        self.update_file_finder();
+                    }
+                    KeyCode::Backspace => {
+                        self.file_finder_query.pop();
+                        self.update_file_finder();
+                    }
+                    KeyCode::Up => {
+                        self.file_finder_selection = self.file_finder_selection
+                            .checked_sub(1)
+                            .unwrap_or(len.saturating_sub(1));
+                    }
+                    KeyCode::Down => {
+                        self.file_finder_selection = (self.file_finder_selection + 1) % len.max(1);
+                    }
+                    KeyCode::PageUp => {
+                        self.file_finder_selection = self.file_finder_selection.saturating_sub(10);
+                    }
+                    KeyCode::PageDown => {
+                        self.file_finder_selection = (self.file_finder_selection + 10).min(len.saturating_sub(1));
+                    }
+                    KeyCode::Enter => {
+                        if let Some(&(index, _)) = self.file_finder_results.get(self.file_finder_selection) {
+                            let path = self.file_finder_files[index].0.clone();
+                            self.popup_state = PopupType::None;
+                            self.open_file(&path)?;
+                        }
+                    }
+                    KeyCode::Esc => {
+                        self.popup_state = PopupType::None;
+                    }
+                    _ => {}
+                }
+            }
+            PopupType::GoToSymbol => {
+                let len = self.symbol_results.len();
+                match key.code {
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::ALT) && !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.symbol_query.push(c);
+                        self.update_symbol_finder();
+                    }
+                    KeyCode::Backspace => {
+                        self.symbol_query.pop();
+                        self.update_symbol_finder();
+                    }
+                    KeyCode::Tab => {
+                        self.symbol_project = !self.symbol_project;
+                        if self.symbol_project {
+                            self.refresh_symbol_index();
+                        }
+                        self.load_symbol_entries();
+                    }
+                    KeyCode::Up => {
+                        self.symbol_selection = self.symbol_selection
+                            .checked_sub(1)
+                            .unwrap_or(len.saturating_sub(1));
+                    }
+                    KeyCode::Down => {
+                        self.symbol_selection = (self.symbol_selection + 1) % len.max(1);
+                    }
+                    KeyCode::PageUp => {
+                        self.symbol_selection = self.symbol_selection.saturating_sub(10);
+                    }
+                    KeyCode::PageDown => {
+                        self.symbol_selection = (self.symbol_selection + 10).min(len.saturating_sub(1));
+                    }
+                    KeyCode::Enter => {
+                        self.open_symbol_entry()?;
+                    }
+                    KeyCode::Esc => {
+                        self.popup_state = PopupType::None;
+                    }
+                    _ => {}
+                }
+            }
+            PopupType::ProjectReplaceReport => {
+                match key.code {
+                    KeyCode::Up => {
+                        self.project_search_selection = self.project_search_selection.saturating_sub(1);
+                    }
+                    KeyCode::Down => {
+                        self.project_search_selection = (self.project_search_selection + 1)
+                            .min(self.project_replace_report.len().saturating_sub(1));
+                    }
+                    KeyCode::Enter => {
+                        if let Some((path, _)) = self.project_replace_report.get(self.project_search_selection).cloned() {
+                            self.popup_state = PopupType::None;
+                            self.open_file(&path)?;
+                        }
+                    }
+                    KeyCode::Esc => {
+                        self.popup_state = PopupType::None;
+                    }
+                    _ => {}
+                }
+            }
+            PopupType::QueryReplace => {
+                match key.code {
+                    KeyCode::Char('y') | KeyCode::Char(' ') => self.answer_query_replace(true),
+                    KeyCode::Char('n') | KeyCode::Backspace => self.answer_query_replace(false),
+                    KeyCode::Char('a') | KeyCode::Char('!') => {
+                        self.query_replace_count += 1;
+                        self.query_replace_matches[self.current_match_index].pending = false;
+                        for index in 0..self.query_replace_matches.len() {
+                            if self.query_replace_matches[index].pending {
+                                self.query_replace_matches[index].pending = false;
+                                self.swap_query_replace(index, true);
+                                self.query_replace_count += 1;
+                            }
+                        }
+                        self.finish_query_replace();
+                    }
+                    KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter => {
+                        self.swap_query_replace(self.current_match_index, false);
+                        self.finish_query_replace();
+                    }
+                    _ => {}
+                }
+            }
+            PopupType::UndoTree => {
+                let rows = self.undo_tree.rows();
+                match key.code {
+                    KeyCode::Up | KeyCode::Down => {
+                        self.undo_tree_selection = if key.code == KeyCode::Up {
+                            self.undo_tree_selection.saturating_sub(1)
+                        } else {
+                            (self.undo_tree_selection + 1).min(rows.len() - 1)
+                        };
+                        self.jump_to_undo_state(rows[self.undo_tree_selection].0);
+                    }
+                    KeyCode::Enter => {
+                        self.popup_state = PopupType::None;
+                        self.set_status_message(format!("Restored state #{}", self.undo_tree.current));
+                    }
+                    KeyCode::Esc => {
+                        self.jump_to_undo_state(self.undo_tree_origin);
+                        self.popup_state = PopupType::None;
+                    }
+                    KeyCode::Char(c) if c.is_ascii_digit() || (c == '+' && self.undo_time_input.is_empty()) => {
+                        self.undo_time_input.push(c);
+                    }
+                    KeyCode::Char(unit @ ('s' | 'm' | 'h')) => {
+                        let newer = self.undo_time_input.starts_with('+');
+                        if let Ok(amount) = self.undo_time_input.trim_start_matches('+').parse::<u64>() {
+                            let seconds = match unit {
+                                's' => amount,
+                                'm' => amount * 60,
+                                _ => amount * 3600,
+                            };
+                            self.undo_by_time(Duration::from_secs(seconds), !newer);
+                            let current = self.undo_tree.current;
+                            self.undo_tree_selection = self.undo_tree.rows().iter()
+                                .position(|&(node, _)| node == current)
+                                .unwrap_or(0);
+                        }
+                        self.undo_time_input.clear();
+                    }
+                    KeyCode::Backspace => {
+                        self.undo_time_input.pop();
+                    }
+                    _ => {}
+                }
+            }
+            PopupType::EncodingSelect(action) => {
+                match key.code {
+                    KeyCode::Up => {
+                        self.encoding_selection = self.encoding_selection
+                            .checked_sub(1)
+                            .unwrap_or(ENCODINGS.len() - 1);
+                    }
+                    KeyCode::Down => {
+                        self.encoding_selection = (self.encoding_selection + 1) % ENCODINGS.len();
+                    }
+                    KeyCode::Enter => {
+                        let action = action.clone();
+                        self.popup_state = PopupType::None;
+                        self.handle_encoding_selection(action)?;
+                    }
+                    KeyCode::Esc => {
+                        self.popup_state = PopupType::None;
+                    }
+                    _ => {}
+                }
+            }
             PopupType::RecentFiles => {
                 match key.code {
                     KeyCode::Up => {
@@ -1821,13 +2804,8 @@ impl Editor {
                     KeyCode::Enter => {
                         if let Some(rf) = self.recent_files.get(self.recent_files_selection) {
                             let path = rf.path.clone();
-                            if self.modified {
-                                self.temp_filename = path.to_string_lossy().into_owned();
-                                self.popup_state = PopupType::SaveConfirm(SaveAction::OpenFile);
-                            } else {
-                                self.open_file(&path)?;
-                                self.popup_state = PopupType::None;
-                            }
+                            self.open_file(&path)?;
+                            self.popup_state = PopupType::None;
                         }
                     }
                     KeyCode::Esc => {
@@ -1837,54 +2815,45 @@ impl Editor {
                 }
             }
             PopupType::None => {
-                if self.tree_focused {
-                    let visible_height = self.terminal.size()?.height.saturating_sub(2) as usize;
-                    let max_scroll = self.file_entries.len().saturating_sub(visible_height);
+                if self.tree_focused && self.show_outline {
+                    match self.resolve_key(key, &[Context::Global]) {
+                        KeyMatch::Command(command) => return self.run_command(command),
+                        KeyMatch::Pending => return Ok(()),
+                        KeyMatch::Unbound => {}
+                    }
+                    self.handle_outline_key(key);
+                } else if self.tree_focused {
+                    match self.resolve_key(key, &[Context::Tree, Context::Global]) {
+                        KeyMatch::Command(command) => return self.run_command(command),
+                        KeyMatch::Pending => return Ok(()),
+                        KeyMatch::Unbound => {}
+                    }
                     match (key.code, key.modifiers) {
-                        (KeyCode::Left, KeyModifiers::CONTROL) => {
-                            if self.cursor_position.0 > 0 {
-                                let line = &self.content[self.cursor_position.1];
-                                let before_cursor = &line[..self.cursor_position.0];
-                                if let Some(pos) = before_cursor.rfind(char::is_whitespace) {
-                                    self.cursor_position.0 = pos;
-                                } else {
-                                    self.cursor_position.0 = 0;
-                                }
-                            }
+                        (KeyCode::Left, KeyModifiers::CONTROL) if self.cursor_position.0 > 0 => {
+                            self.cursor_position.0 = self.previous_whitespace().unwrap_or(0);
                         }
                         (KeyCode::Right, KeyModifiers::CONTROL) => {
-                            let line = &self.content[self.cursor_position.1];
-                            if self.cursor_position.0 < line.len() {
-                                let after_cursor = &line[self.cursor_position.0..];
-                                if let Some(pos) = after_cursor.find(char::is_whitespace) {
-                                    self.cursor_position.0 += pos;
-                                } else {
-                                    self.cursor_position.0 = line.len();
-                                }
+                            let line_len = self.content.line_len(self.cursor_position.1);
+                            if self.cursor_position.0 < line_len {
+                                self.cursor_position.0 = self.next_whitespace().unwrap_or(line_len);
                             }
                         }
                         (KeyCode::Up, modifiers) => {
                             if modifiers.contains(KeyModifiers::CONTROL) {
                                 if self.cursor_position.1 > 0 {
-                                    self.cursor_position.1 = self.cursor_position.1.saturating_sub(5);
-                                    let line_len = self.content[self.cursor_position.1].len();
-                                    self.cursor_position.0 = self.cursor_position.0.min(line_len);
+                                    self.set_cursor_line(self.cursor_position.1.saturating_sub(5));
                                 }
-                            } else {
-                                if self.file_explorer_selection > 0 {
-                                    self.file_explorer_selection -= 1;
-                                    if self.file_explorer_selection < self.file_tree_scroll_offset as usize {
-                                        self.file_tree_scroll_offset = self.file_explorer_selection as u16;
-                                    }
+                            } else if self.file_explorer_selection > 0 {
+                                self.file_explorer_selection -= 1;
+                                if self.file_explorer_selection < self.file_tree_scroll_offset as usize {
+                                    self.file_tree_scroll_offset = self.file_explorer_selection as u16;
                                 }
                             }
                         }
                         (KeyCode::Down, modifiers) => {
                             if modifiers.contains(KeyModifiers::CONTROL) {
-                                if self.cursor_position.1 < self.content.len() - 1 {
-                                    self.cursor_position.1 = (self.cursor_position.1 + 5).min(self.content.len() - 1);
-                                    let line_len = self.content[self.cursor_position.1].len();
-                                    self.cursor_position.0 = self.cursor_position.0.min(line_len);
+                                if self.cursor_position.1 < self.content.len_lines() - 1 {
+                                    self.set_cursor_line((self.cursor_position.1 + 5).min(self.content.len_lines() - 1));
                                 }
                             } else {
                                 let visible_height = self.terminal.size()?.height.saturating_sub(2);
@@ -1904,15 +2873,9 @@ impl Editor {
                                     self.file_entries = Self::read_directory(&self.current_dir)?;
                                     self.file_explorer_selection = 0;
                                 } else {
-                                    if self.modified {
-                                        self.temp_filename = entry.path.to_string_lossy().into_owned();
-                                        self.popup_state = PopupType::SaveConfirm(SaveAction::OpenFile);
-                                    } else {
-                                        self.open_file(&entry.path)?;
-                                        self.tree_focused = false;
-                                    }
+                                    self.open_file(&entry.path)?;
+                                    self.tree_focused = false;
                                 }
-                                self.file_explorer_selection = self.file_explorer_selection;
                             }
                         }
                         (KeyCode::Backspace, _) => {
@@ -1925,39 +2888,26 @@ impl Editor {
                         (KeyCode::Esc, _) => {
                             self.tree_focused = false;
                         }
-                        (KeyCode::Char('e'), KeyModifiers::ALT) => {
-                            self.tree_focused = false;
-                        }
-                        (KeyCode::Char('n'), KeyModifiers::ALT) => {
-                            self.popup_state = PopupType::NewFile;
-                            self.temp_filename.clear();
-                            return Ok(());
-                        }
-                        (KeyCode::Char('d'), KeyModifiers::ALT) => {
-                            if self.tree_focused {
-                                self.popup_state = PopupType::NewDirectory;
-                                self.temp_filename.clear();
-                            }
-                            return Ok(());
-                        }
                         _ => {}
                     }
                 } else {
+                    self.update_selection_for_key(&key);
+                    if !self.extra_cursors.is_empty()
+                        && key.modifiers == KeyModifiers::NONE
+                        && matches!(key.code, KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down | KeyCode::Home | KeyCode::End)
+                    {
+                        self.for_each_cursor(|editor| editor.move_cursor(key.code));
+                        return Ok(());
+                    }
+                    if self.mode == EditorMode::VisualBlock && self.handle_block_key(&key) {
+                        return Ok(());
+                    }
+                    match self.resolve_key(key, &[Context::Editor, Context::Global]) {
+                        KeyMatch::Command(command) => return self.run_command(command),
+                        KeyMatch::Pending => return Ok(()),
+                        KeyMatch::Unbound => {}
+                    }
                     match (key.code, key.modifiers) {
-                        (KeyCode::Tab, KeyModifiers::NONE) => {
-                            if !self.showing_suggestions || self.suggestions.is_empty() {
-                                let spaces = "    ";
-                                if self.cursor_position.1 >= self.content.len() {
-                                    self.content.push(String::new());
-                                }
-                                let line = &mut self.content[self.cursor_position.1];
-                                line.insert_str(self.cursor_position.0, spaces);
-                                self.cursor_position.0 += 4;
-                                self.modified = true;
-                            } else {
-                                self.apply_suggestion();
-                            }
-                        }
                         (KeyCode::Left, KeyModifiers::NONE) => {
                             self.handle_left_key();
                         }
@@ -1965,14 +2915,14 @@ impl Editor {
                             self.handle_right_key();
                         }
                         (KeyCode::Enter, KeyModifiers::NONE) => {
-                            self.handle_enter_key();
+                            self.save_state();
+                            self.delete_selection();
+                            self.for_each_cursor(Self::handle_enter_key);
                         }
                         (KeyCode::Left, modifiers) => {
                             if modifiers.contains(KeyModifiers::ALT) || modifiers.contains(KeyModifiers::CONTROL) {
                                 if self.cursor_position.0 > 0 {
-                                    let line = &self.content[self.cursor_position.1];
-                                    let before_cursor = &line[..self.cursor_position.0];
-                                    if let Some(pos) = before_cursor.rfind(char::is_whitespace) {
+                                    if let Some(pos) = self.previous_whitespace() {
                                         self.cursor_position.0 = pos + if modifiers.contains(KeyModifiers::ALT) { 1 } else { 0 };
                                     } else {
                                         self.cursor_position.0 = 0;
@@ -1984,20 +2934,17 @@ impl Editor {
                         }
                         (KeyCode::Right, modifiers) => {
                             if modifiers.contains(KeyModifiers::ALT) || modifiers.contains(KeyModifiers::CONTROL) {
-                                let line = &self.content[self.cursor_position.1];
-                                if self.cursor_position.0 < line.len() {
-                                    let after_cursor = &line[self.cursor_position.0..];
-                                    let next_space = after_cursor.find(|c: char| c.is_whitespace());
-                                    if let Some(space_pos) = next_space {
-                                        let slice_after_space = &after_cursor[space_pos..];
-                                        if let Some(word_pos) = slice_after_space.find(|c: char| !c.is_whitespace()) {
-                                            self.cursor_position.0 += space_pos + word_pos;
-                                        } else {
-                                            self.cursor_position.0 = line.len();
-                                        }
-                                    } else {
-                                        self.cursor_position.0 = line.len();
-                                    }
+                                let line_len = self.content.line_len(self.cursor_position.1);
+                                if self.cursor_position.0 < line_len {
+                                    self.cursor_position.0 = self.next_whitespace()
+                                        .and_then(|space_pos| {
+                                            self.content.line(self.cursor_position.1)
+                                                .chars()
+                                                .skip(space_pos)
+                                                .position(|c| !c.is_whitespace())
+                                                .map(|word_pos| space_pos + word_pos)
+                                        })
+                                        .unwrap_or(line_len);
                                 }
                             } else {
                                 self.handle_right_key();
@@ -2011,20 +2958,12 @@ impl Editor {
                                         self.file_tree_scroll_offset = self.file_explorer_selection as u16;
                                     }
                                 }
-                            } else {
-                                if modifiers.contains(KeyModifiers::CONTROL) {
-                                    if self.cursor_position.1 > 0 {
-                                        self.cursor_position.1 = self.cursor_position.1.saturating_sub(5).max(0);
-                                        let line_len = self.content[self.cursor_position.1].len();
-                                        self.cursor_position.0 = self.cursor_position.0.min(line_len);
-                                    }
-                                } else {
-                                    if self.cursor_position.1 > 0 {
-                                        self.cursor_position.1 -= 1;
-                                        let line_len = self.content[self.cursor_position.1].len();
-                                        self.cursor_position.0 = self.cursor_position.0.min(line_len);
-                                    }
+                            } else if modifiers.contains(KeyModifiers::CONTROL) {
+                                if self.cursor_position.1 > 0 {
+                                    self.set_cursor_line(self.cursor_position.1.saturating_sub(5));
                                 }
+                            } else if self.cursor_position.1 > 0 {
+                                self.set_cursor_line(self.cursor_position.1 - 1);
                             }
                         }
                         (KeyCode::Down, modifiers) => {
@@ -2036,233 +2975,42 @@ impl Editor {
                                         self.file_tree_scroll_offset = (self.file_explorer_selection - max_scroll as usize) as u16;
                                     }
                                 }
-                            } else {
-                                if modifiers.contains(KeyModifiers::CONTROL) {
-                                    if self.cursor_position.1 < self.content.len() - 1 {
-                                        self.cursor_position.1 = (self.cursor_position.1 + 5).min(self.content.len() - 1);
-                                        let line_len = self.content[self.cursor_position.1].len();
-                                        self.cursor_position.0 = self.cursor_position.0.min(line_len);
-                                    }
-                                } else {
-                                    if self.cursor_position.1 < self.content.len() - 1 {
-                                        self.cursor_position.1 += 1;
-                                        let line_len = self.content[self.cursor_position.1].len();
-                                        self.cursor_position.0 = self.cursor_position.0.min(line_len);
-                                    }
-                                }
-                            }
-                        }
-                        (KeyCode::Home, _) => {
-                            self.cursor_position.0 = 0;
-                        }
-                        (KeyCode::End, _) => {
-                            self.cursor_position.0 = self.content[self.cursor_position.1].len();
-                        }
-                        (KeyCode::PageUp, _) => {
-                            let page_size = self.terminal.size().unwrap().height as usize;
-                            self.cursor_position.1 = self.cursor_position.1.saturating_sub(page_size);
-                            let line_len = self.content[self.cursor_position.1].len();
-                            self.cursor_position.0 = self.cursor_position.0.min(line_len);
-                        }
-                        (KeyCode::PageDown, _) => {
-                            let page_size = self.terminal.size().unwrap().height as usize;
-                            self.cursor_position.1 = (self.cursor_position.1 + page_size).min(self.content.len() - 1);
-                            let line_len = self.content[self.cursor_position.1].len();
-                            self.cursor_position.0 = self.cursor_position.0.min(line_len);
-                        }
-                        (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
-                            self.save()?;
-                        }
-                        (KeyCode::Char('o'), KeyModifiers::ALT) => {
-                            self.popup_state = PopupType::Open;
-                            self.temp_filename.clear();
-                        }
-                        (KeyCode::Char('w'), KeyModifiers::ALT) => {
-                            self.try_close_tab();
-                        }
-                        (KeyCode::Char('q'), KeyModifiers::ALT) => {
-                            self.try_exit();
-                        }
-                        (KeyCode::Char('b'), KeyModifiers::ALT) => {
-                            self.show_tree = !self.show_tree;
-                            if (!self.show_tree) {
-                                self.tree_focused = false;
-                            }
-                        }
-                        (KeyCode::Char('l'), KeyModifiers::ALT) => {
-                            self.show_numbers = !self.show_numbers;
-                        }
-                        (KeyCode::Char('x'), KeyModifiers::CONTROL) => {
-                            if self.cursor_position.1 < self.content.len() {
-                                let _line = self.content.remove(self.cursor_position.1);
-                                if !_line.is_empty() {
-                                    if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                                        if let Err(e) = clipboard.set_text(_line) {
-                                            self.set_status_message(&format!("Failed to cut: {}", e));
-                                            return Ok(());
-                                        }
-                                    }
-                                }
-                                if self.content.is_empty() {
-                                    self.content.push(String::new());
-                                }
-                                if self.cursor_position.1 >= self.content.len() {
-                                    self.cursor_position.1 = self.content.len() - 1;
-                                }
-                                self.cursor_position.0 = 0;
-                                self.modified = true;
-                                self.set_status_message("Line cut");
-                            }
-                            return Ok(());
-                        }
-                        (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                            if self.cursor_position.1 < self.content.len() {
-                                let line = &self.content[self.cursor_position.1];
-                                if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                                    match clipboard.set_text(line.clone()) {
-                                        Ok(_) => self.set_status_message("Line copied"),
-                                        Err(e) => self.set_status_message(&format!("Failed to copy: {}", e)),
-                                    }
-                                }
-                            }
-                            return Ok(());
-                        }
-                        (KeyCode::Char('v'), KeyModifiers::CONTROL) => {
-                            if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                                match clipboard.get_text() {
-                                    Ok(text) => {
-                                        if self.cursor_position.1 < self.content.len() {
-                                            let current_line = &mut self.content[self.cursor_position.1];
-                                            current_line.insert_str(self.cursor_position.0, &text);
-                                            self.cursor_position.0 += text.chars().count();
-                                            self.modified = true;
-                                            self.set_status_message("Pasted from clipboard");
-                                        }
-                                    }
-                                    Err(e) => {
-                                        self.set_status_message(&format!("Failed to paste: {}", e));
-                                    }
+                            } else if modifiers.contains(KeyModifiers::CONTROL) {
+                                if self.cursor_position.1 < self.content.len_lines() - 1 {
+                                    self.set_cursor_line((self.cursor_position.1 + 5).min(self.content.len_lines() - 1));
                                 }
-                            } else {
-                                self.set_status_message("Failed to access clipboard");
+                            } else if self.cursor_position.1 < self.content.len_lines() - 1 {
+                                self.set_cursor_line(self.cursor_position.1 + 1);
                             }
-                            return Ok(());
                         }
-                        (KeyCode::Char('z'), KeyModifiers::CONTROL) => {
-                            self.undo();
-                            return Ok(());
-                        }
-                        (KeyCode::Char('y'), KeyModifiers::CONTROL) => {
-                            self.redo();
-                            return Ok(());
-                        }
-                        (KeyCode::Char('f'), KeyModifiers::CONTROL) => {
-                            self.popup_state = PopupType::Find;
-                            self.search_query.clear();
-                        }
-                        (KeyCode::Char('r'), KeyModifiers::ALT) => {
-                            if let Some(filename) = &self.filename {
-                                let path = filename.to_str().unwrap_or_default();
-                                let current_dir = match std::env::current_dir() {
-                                    Ok(dir) => dir.display().to_string(),
-                                    Err(e) => {
-                                        self.set_status_message(format!("Failed to get current directory: {}", e));
-                                        return Ok(());
-                                    }
-                                };
-                                let run_command = match path {
-                                    p if p.ends_with(".rs") => format!("cd '{}' && cargo run", current_dir),
-                                    p if p.ends_with(".cs") => format!("dotnet run '{}'", path),
-                                    p if p.ends_with(".py") => format!("python3 '{}'", path),
-                                    p if p.ends_with(".js") => format!("node '{}'", path),
-                                    p if p.ends_with(".go") => format!("go run '{}'", path),
-                                    _ => {
-                                        self.set_status_message("Unsupported file type");
-                                        return Ok(());
-                                    }
-                                };
-                                terminal::disable_raw_mode()?;
-                                crossterm::execute!(
-                                    self.terminal.backend_mut(),
-                                    terminal::LeaveAlternateScreen,
-                                    terminal::Clear(ClearType::All)
-                                )?;
-                                let status = std::process::Command::new("sh")
-                                    .arg("-c")
-                                    .arg(&run_command)
-                                    .status();
-                                println!("\nPress Enter to continue...");
-                                let _ = std::io::stdin().read_line(&mut String::new());
-                                terminal::enable_raw_mode()?;
-                                crossterm::execute!(
-                                    self.terminal.backend_mut(),
-                                    terminal::EnterAlternateScreen,
-                                    terminal::Clear(ClearType::All),
-                                    cursor::Show
-                                )?;
-                                self.terminal.clear()?;
-                                self.draw()?;
-                                match status {
-                                    Ok(status) if status.success() => {
-                                        self.set_status_message("Program ran successfully.");
-                                    }
-                                    Ok(status) => {
-                                        self.set_status_message(format!("Program exited with status: {}", status));
-                                    }
-                                    Err(e) => {
-                                        self.set_status_message(format!("Failed to run: {}", e));
-                                    }
-                                }
-                            }
+                        (KeyCode::Char(c), _) => {
+                            self.handle_text_input(c);
                         }
-                        (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
-                            self.search_query.clear();
-                            self.mode = EditorMode::Replace;
-                            self.popup_state = PopupType::Replace;
+                        (KeyCode::Enter, _) => {
+                            self.save_state();
+                            self.delete_selection();
+                            self.for_each_cursor(Self::handle_enter_key);
                         }
-                        (KeyCode::Char('n'), KeyModifiers::ALT) => {
-                            self.find_next();
+                        (KeyCode::Backspace, _) if self.selection_range().is_some() => {
+                            self.save_state();
+                            self.delete_selection();
                         }
-                        (KeyCode::Char('e'), KeyModifiers::ALT) => {
-                            if self.show_tree {
-                                self.tree_focused = !self.tree_focused;
-                                if self.tree_focused {
-                                    self.file_entries = Self::read_directory(&self.current_dir)?;
-                                }
+                        (KeyCode::Delete, _) => {
+                            self.save_state();
+                            if !self.delete_selection() {
+                                self.for_each_cursor(Self::delete_forward);
                             }
                         }
-                        (KeyCode::Char('t'), KeyModifiers::ALT) => {
-                            self.popup_state = PopupType::ToolMenu;
-                            self.tool_menu_selection = 0;
-                        }
-                        (KeyCode::Char('p'), KeyModifiers::ALT) => {
-                            self.set_status_message("Settings not implemented yet");
-                        }
-                                (KeyCode::Char('h'), KeyModifiers::ALT) => {
-                                    self.show_help();
-                                }
-                        (KeyCode::Char(c), _) => {
-                            self.handle_text_input(c);
+                        (KeyCode::Esc, _) if self.selection_anchor.is_some() || matches!(self.mode, EditorMode::Visual | EditorMode::VisualBlock) => {
+                            self.clear_selection();
                         }
-                        (KeyCode::Enter, _) => {
-                            self.handle_enter_key();
+                        (KeyCode::Esc, _) if !self.extra_cursors.is_empty() => {
+                            self.extra_cursors.clear();
                         }
                         (KeyCode::Backspace, _) => {
                             let delete_count = if key.modifiers.contains(KeyModifiers::SHIFT) { 5 } else { 1 };
-                            for _ in 0..delete_count {
-                                if self.cursor_position.0 > 0 {
-                                    let current_line = &mut self.content[self.cursor_position.1];
-                                    current_line.remove(self.cursor_position.0 - 1);
-                                    self.cursor_position.0 -= 1;
-                                    self.modified = true;
-                                } else if self.cursor_position.1 > 0 {
-                                    let _line = self.content.remove(self.cursor_position.1);
-                                    self.cursor_position.1 -= 1;
-                                    self.cursor_position.0 = self.content[self.cursor_position.1].len();
-                                    self.content[self.cursor_position.1].push_str(&_line);
-                                    self.modified = true;
-                                }
-                            }
+                            self.save_state();
+                            self.for_each_cursor(|editor| editor.backspace(delete_count));
                         }
                         (KeyCode::Esc, _) => {
                             self.has_edited = true;
@@ -2276,7 +3024,7 @@ impl Editor {
                 match key.code {
                     KeyCode::Enter => {
                         if let Ok(line_num) = self.search_query.parse::<usize>() {
-                            if line_num > 0 && line_num <= self.content.len() {
+                            if line_num > 0 && line_num <= self.content.len_lines() {
                                 self.cursor_position.1 = line_num - 1;
                                 self.cursor_position.0 = 0;
                                 self.popup_state = PopupType::None;
@@ -2296,15 +3044,14 @@ impl Editor {
                         };
                         self.search_query.clear();
                     }
-                    KeyCode::Char(c) => {
-                        if c.is_ascii_digit() {
-                            self.search_query.push(c);
-                        }
+                    KeyCode::Char(c) if c.is_ascii_digit() => {
+                        self.search_query.push(c);
                     }
-                    KeyCode::Backspace => {
-                        if !self.search_query.is_empty() {
-                            self.search_query.pop();
-                        }
+                    KeyCode::Char('@') if self.search_query.is_empty() => {
+                        self.open_symbol_finder(false);
+                    }
+                    KeyCode::Backspace if !self.search_query.is_empty() => {
+                        self.search_query.pop();
                     }
                     _ => {}
                 }
@@ -2312,19 +3059,39 @@ impl Editor {
             PopupType::Replace => {
                 self.popup_state = PopupType::ReplaceQuery;
                 self.search_query.clear();
+                self.search_error = None;
                 self.replace_text.clear();
             }
             PopupType::ReplaceQuery => {
                 match key.code {
+                    _ if self.keymap.lookup(&key, &[Context::Search]).is_some_and(|command| self.toggle_search_option(command)) => {
+                        self.search_error = self.search_regex().err().map(Self::describe_regex_error);
+                    }
                     KeyCode::Char(c) => {
                         self.search_query.push(c);
+                        self.search_error = self.search_regex().err().map(Self::describe_regex_error);
                     }
                     KeyCode::Backspace => {
                         self.search_query.pop();
+                        self.search_error = self.search_regex().err().map(Self::describe_regex_error);
                     }
                     KeyCode::Enter => {
-                        self.find_next();
-                        self.popup_state = PopupType::ReplaceWithQuery;
+                        if let Some((start, end)) = self.selection_range() {
+                            self.highlighted_matches = self.search_matches()
+                                .into_iter()
+                                .filter(|&(match_start, match_end)| {
+                                    (start.1, start.0) <= (match_start.1, match_start.0)
+                                        && (match_end.1, match_end.0) <= (end.1, end.0)
+                                })
+                                .collect();
+                        } else {
+                            self.find_next();
+                        }
+                        if let Some(error) = &self.search_error {
+                            self.set_status_message(format!("Invalid pattern: {}", error));
+                        } else {
+                            self.popup_state = PopupType::ReplaceWithQuery;
+                        }
                     }
                     KeyCode::Esc => {
                         self.popup_state = PopupType::None;
@@ -2341,12 +3108,7 @@ impl Editor {
                         self.replace_text.pop();
                     }
                     KeyCode::Enter => {
-                        if self.highlighted_matches.is_empty() {
-                            self.set_status_message("No matches found.");
-                        } else {
-                            self.replace_current();
-                            self.find_next();
-                        }
+                        self.start_query_replace();
                     }
                     KeyCode::Esc => {
                         self.popup_state = PopupType::None;
@@ -2363,8 +3125,8 @@ impl Editor {
                         self.tool_menu_selection = (self.tool_menu_selection + 1) % self.tools.len();
                     }
                     KeyCode::Enter => {
-                        self.handle_tool_menu_selection(self.tool_menu_selection);
                         self.popup_state = PopupType::None;
+                        self.handle_tool_menu_selection(self.tool_menu_selection);
                     }
                     KeyCode::Esc => {
                         self.popup_state = PopupType::None;
@@ -2423,258 +3185,1271 @@ impl Editor {
         }
         Ok(())
     }
-    fn cleanup(&mut self) -> std::io::Result<()> {
-        terminal::disable_raw_mode()?;
-        crossterm::execute!(
-            self.terminal.backend_mut(),
-            terminal::LeaveAlternateScreen
-        )?;
-        Ok(())
-    }
-    fn run_command(command: &str) -> Result<String, std::io::Error> {
-        use std::process::Command;
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg(command)
-            .output()?;
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-        if !stderr.is_empty() {
-            Ok(stderr)
-        } else {
-            Ok(stdout)
-        }
-    }
-    fn try_exit(&mut self) {
-        if self.is_modified() {
-            self.popup_state = PopupType::SaveConfirm(SaveAction::Exit);
-        } else {
-            self.cleanup().unwrap_or(());
-            std::process::exit(0);
-        }
-    }
-    fn handle_save_confirm(&mut self, save: bool, action: SaveAction) -> std::io::Result<()> {
-        if save {
-            if self.filename.is_none() {
-                self.filename = Some(self.current_dir.join(&self.temp_filename));
-                self.popup_state = PopupType::Save;
-                return Ok(());
+    fn run_command(&mut self, command: Command) -> std::io::Result<()> {
+        match command {
+            Command::WordLeft => {
+                if self.cursor_position.0 > 0 {
+                    self.cursor_position.0 = self.previous_whitespace().unwrap_or(0);
+                }
             }
-            self.save()?;
-        }
-        match action {
-            SaveAction::Exit => {
-                if save {
-                    self.cleanup()?;
-                    std::process::exit(0);
+            Command::WordRight => {
+                let line_len = self.content.line_len(self.cursor_position.1);
+                if self.cursor_position.0 < line_len {
+                    self.cursor_position.0 = self.next_whitespace()
+                        .and_then(|space_pos| {
+                            self.content.line(self.cursor_position.1)
+                                .chars()
+                                .skip(space_pos)
+                                .position(|c| !c.is_whitespace())
+                                .map(|word_pos| space_pos + word_pos)
+                        })
+                        .unwrap_or(line_len);
+                }
+            }
+            Command::LineStart => {
+                self.cursor_position.0 = 0;
+            }
+            Command::LineEnd => {
+                self.cursor_position.0 = self.content.line_len(self.cursor_position.1);
+            }
+            Command::PageUp => {
+                let page_size = self.terminal.size()?.height as isize;
+                self.set_cursor_line(self.folds.step(self.cursor_position.1, -page_size, self.content.len_lines()));
+            }
+            Command::PageDown => {
+                let page_size = self.terminal.size()?.height as isize;
+                self.set_cursor_line(self.folds.step(self.cursor_position.1, page_size, self.content.len_lines()));
+            }
+            Command::GoToLine => {
+                self.search_query.clear();
+                self.popup_state = PopupType::JumpToLine;
+            }
+            Command::GoToSymbol => {
+                self.open_symbol_finder(false);
+            }
+            Command::GoToProjectSymbol => {
+                self.open_symbol_finder(true);
+            }
+            Command::Fold => {
+                self.settle_folds();
+                match self.folds.fold(self.cursor_position.1) {
+                    Some((start, end)) => {
+                        if self.cursor_position.1 != start {
+                            self.cursor_position = self.content.clamp((self.cursor_position.0, start));
+                        }
+                        self.set_status_message(format!("Folded lines {}-{}", start + 1, end + 1));
+                    }
+                    None => self.set_status_message("Nothing to fold here"),
+                }
+            }
+            Command::Unfold => {
+                self.settle_folds();
+                if !self.folds.unfold(self.cursor_position.1) {
+                    self.set_status_message("No fold on this line");
+                }
+            }
+            Command::ToggleAllFolds => {
+                self.settle_folds();
+                if self.folds.toggle_all() {
+                    self.cursor_position.1 = self.folds.visible_line(self.cursor_position.1);
+                    self.cursor_position = self.content.clamp(self.cursor_position);
+                    self.set_status_message("Folded all blocks");
                 } else {
-                    self.cleanup()?;
-                    std::process::exit(0);
+                    self.set_status_message("Unfolded all blocks");
                 }
             }
-            SaveAction::OpenFile => {
-                let path = PathBuf::from(&self.temp_filename);
-                self.open_file(&path)?;
+            Command::Save => {
+                self.save()?;
+            }
+            Command::Open => {
+                self.popup_state = PopupType::Open;
                 self.temp_filename.clear();
-                self.popup_state = PopupType::None;
             }
-        }
-        Ok(())
-    }
-    fn is_modified(&self) -> bool {
-        if !self.modified {
-            return false;
-        }
-        if let Some(path) = &self.filename {
-            if let Ok(content) = fs::read_to_string(path) {
-                let current = self.content.join("\n") + "\n";
-                return current != content;
+            Command::FindFile => {
+                self.open_file_finder();
             }
-        }
-        true
-    }
-    fn safe_insert_char(&mut self, c: char) {
-        if self.content.is_empty() {
-            self.content.push(String::new());
-        }
-        self.cursor_position.1 = self.cursor_position.1.min(self.content.len() - 1);
-        let current_line = &mut self.content[self.cursor_position.1];
-        let mut chars: Vec<char> = current_line.chars().collect();
-        self.cursor_position.0 = self.cursor_position.0.min(chars.len());
-        let ascii_char = deunicode(&c.to_string());
-        for ch in ascii_char.chars() {
-            chars.insert(self.cursor_position.0, ch);
-            self.cursor_position.0 += 1;
-        }
-        *current_line = chars.into_iter().collect();
-        self.modified = true;
-    }
-    fn handle_text_input(&mut self, c: char) {
-        if self.tree_focused {
-            return;
-        }
-        if !c.is_control() {
-            self.save_state();
-            match c {
-                '{' => self.insert_and_move_cursor("{}", 1),
-                '(' => self.insert_and_move_cursor("()", 1),
-                '[' => self.insert_and_move_cursor("[]", 1),
-                '"' => self.insert_and_move_cursor("\"\"", 1),
-                '\'' => self.insert_and_move_cursor("''", 1),
-                _ => self.safe_insert_char(c),
+            Command::CloseTab => {
+                self.try_close_tab();
             }
-            if c.is_alphanumeric() || c == '_' || c == '.' {
-                self.update_word_database();
-                self.update_suggestions();
-            } else {
-                self.showing_suggestions = false;
+            Command::NextTab => {
+                self.cycle_tab(true);
             }
-        }
-    }
-    fn insert_and_move_cursor(&mut self, text: &str, cursor_offset: usize) {
-        if self.content.is_empty() {
-            self.content.push(String::new());
-        }
-        self.cursor_position.1 = self.cursor_position.1.min(self.content.len() - 1);
-        let current_line = &mut self.content[self.cursor_position.1];
-        let mut chars: Vec<char> = current_line.chars().collect();
-        self.cursor_position.0 = self.cursor_position.0.min(chars.len());
-        for (i, ch) in text.chars().enumerate() {
-            chars.insert(self.cursor_position.0 + i, ch);
-        }
-        *current_line = chars.into_iter().collect();
-        self.cursor_position.0 += cursor_offset;
-        self.modified = true;
-    }
-    fn get_char_position(&self, text: &str, byte_pos: usize) -> usize {
-        text.chars()
-            .take_while(|_| text[..byte_pos.min(text.len())].len() > 0)
-            .count()
-    }
-    fn get_line_slice(line: &str, start: usize, end: usize) -> String {
-        line.chars()
-            .skip(start)
-            .take(end.saturating_sub(start))
-            .collect()
-    }
-    fn get_char_index(text: &str, byte_pos: usize) -> usize {
-        text[..byte_pos.min(text.len())]
-            .chars()
-            .count()
-    }
-    fn ensure_cursor_in_bounds(&mut self) {
-        if self.content.is_empty() {
-            self.content.push(String::new());
-        }
-        let line = &self.content[self.cursor_position.1];
-        let char_count = line.chars().count();
-        self.cursor_position.0 = self.cursor_position.0.min(char_count);
-    }
-    fn find_next(&mut self) {
-        if self.search_query.is_empty() {
-            self.highlighted_matches.clear();
-            return;
-        }
-        self.highlighted_matches.clear();
-        for (line_idx, line) in self.content.iter().enumerate() {
-            let mut start = 0;
-            while let Some(pos) = line[start..].find(&self.search_query) {
-                let abs_pos = start + pos;
-                self.highlighted_matches.push((line_idx, abs_pos));
-                start = abs_pos + 1;
+            Command::PreviousTab => {
+                self.cycle_tab(false);
             }
-        }
-        if !self.highlighted_matches.is_empty() {
-            if let Some(search_index) = self.search_index {
-                let next_index = (search_index + 1) % self.highlighted_matches.len();
-                self.search_index = Some(next_index);
-                let (line, col) = self.highlighted_matches[next_index];
-                self.cursor_position = (col, line);
-            } else {
-                self.search_index = Some(0);
-                let (line, col) = self.highlighted_matches[0];
-                self.cursor_position = (col, line);
+            Command::MoveTabRight => {
+                self.move_tab(true);
             }
-        } else {
-            self.search_index = None;
-            self.set_status_message("No matches found");
-        }
-    }
-    fn handle_search_input(&mut self, c: char) {
-        match c {
-            '\n' => self.find_next(),
-            c if !c.is_control() => {
-                self.search_query.push(c);
-                self.find_next();
+            Command::MoveTabLeft => {
+                self.move_tab(false);
             }
-            _ => {}
-        }
-    }
-    fn open_file(&mut self, path: &PathBuf) -> std::io::Result<()> {
-        if path.is_dir() {
-            self.log_error(&format!("Attempted to open directory: {}", path.display()));
-            self.set_status_message("Cannot open a directory");
-            return Ok(());
-        }
-        if self.modified && self.filename.is_some() {
-            self.save_state();
-        }
-        if let Some(index) = self.file_entries.iter().position(|entry| entry.path == *path) {
-            self.file_explorer_selection = index;
-        }
-        match fs::read_to_string(path) {
-            Ok(content) => {
-                let ascii_content = content.lines()
-                                           .map(|line| {
-                                               if line.contains("󰆍") || line.contains("") {
-                                                   line.to_string()
-                                               } else {
-                                                   deunicode(line)
-                                               }
-                                           })
-                                           .collect::<Vec<String>>()
-                                           .join("\n");
-                self.content = ascii_content.lines().map(String::from).collect();
-                if self.content.is_empty() {
-                    self.content.push(String::new());
-                }
-                self.cursor_position = (0, 0);
-                self.filename = Some(path.clone());
-                self.modified = false;
-                self.scroll_offset = 0;
-                self.add_to_recent_files(path.clone());
-                self.set_status_message(format!("Opened {}", Self::format_path(path)));
-                self.show_initial_menu = false;
-                self.current_syntax = Self::detect_syntax(&self.syntax_set, path);
-                self.last_save_state = Some(self.content.clone());
-                if let Some(index) = self.file_entries.iter().position(|entry| entry.path == *path) {
-                    self.file_explorer_selection = index;
+            Command::Quit => {
+                self.try_exit();
+            }
+            Command::ToggleTree => {
+                self.show_tree = !self.show_tree;
+                if !self.show_tree {
+                    self.tree_focused = false;
                 }
-                Ok(())
             }
-            Err(e) => {
-                let error_msg = format!("Error opening file: {}", e);
-                self.log_error(&error_msg);
-                self.set_status_message(&error_msg);
-                return Ok(());
+            Command::ToggleOutline => {
+                if self.show_tree && self.show_outline {
+                    self.show_outline = false;
+                    self.tree_focused = false;
+                } else {
+                    self.show_tree = true;
+                    self.show_outline = true;
+                    self.tree_focused = true;
+                    self.outline_query.clear();
+                    self.outline_revision = None;
+                }
             }
-        }
-    }
-    fn load_recent_files() -> Vec<RecentFile> {
-        let home = env::var("HOME").ok().map(PathBuf::from);
-        let config_dir = home.map(|h| h.join(".config").join("red"));
-        if let Some(config_dir) = config_dir {
-            if !config_dir.exists() {
-                let _ = fs::create_dir_all(&config_dir);
+            Command::ToggleLineNumbers => {
+                self.show_numbers = !self.show_numbers;
             }
-            let history_file = config_dir.join("history");
-            if let Ok(content) = fs::read_to_string(history_file) {
-                return content
-                    .lines()
-                    .filter_map(|line| {
-                        let path = PathBuf::from(line);
-                        Some(RecentFile {
-                            exists: path.exists(),
+            Command::SplitHorizontal => {
+                self.split_pane(true);
+            }
+            Command::SplitVertical => {
+                self.split_pane(false);
+            }
+            Command::FocusLeft => {
+                self.focus_direction(-1, 0);
+            }
+            Command::FocusRight => {
+                self.focus_direction(1, 0);
+            }
+            Command::FocusUp => {
+                self.focus_direction(0, -1);
+            }
+            Command::FocusDown => {
+                self.focus_direction(0, 1);
+            }
+            Command::GrowPane => {
+                self.resize_pane(5);
+            }
+            Command::ShrinkPane => {
+                self.resize_pane(-5);
+            }
+            Command::ClosePane => {
+                self.close_pane();
+            }
+            Command::Cut if self.selection_range().is_some() => {
+                let text = self.selected_text();
+                match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text)) {
+                    Ok(_) => {
+                        self.save_state();
+                        self.delete_selection();
+                        self.set_status_message("Selection cut");
+                    }
+                    Err(e) => self.set_status_message(format!("Failed to cut: {}", e)),
+                }
+            }
+            Command::Cut => {
+                if self.cursor_position.1 < self.content.len_lines() {
+                    self.save_state();
+                    let line = self.content.remove_line(self.cursor_position.1);
+                    if !line.is_empty() {
+                        if let Ok(mut clipboard) = arboard::Clipboard::new() {
+                            if let Err(e) = clipboard.set_text(line) {
+                                self.set_status_message(format!("Failed to cut: {}", e));
+                                return Ok(());
+                            }
+                        }
+                    }
+                    if self.cursor_position.1 >= self.content.len_lines() {
+                        self.cursor_position.1 = self.content.len_lines() - 1;
+                    }
+                    self.cursor_position.0 = 0;
+                    self.modified = true;
+                    self.set_status_message("Line cut");
+                }
+            }
+            Command::Copy if self.selection_range().is_some() => {
+                let text = self.selected_text();
+                match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text)) {
+                    Ok(_) => self.set_status_message("Selection copied"),
+                    Err(e) => self.set_status_message(format!("Failed to copy: {}", e)),
+                }
+            }
+            Command::Copy => {
+                if self.cursor_position.1 < self.content.len_lines() {
+                    let line = self.content.line(self.cursor_position.1).into_owned();
+                    if let Ok(mut clipboard) = arboard::Clipboard::new() {
+                        match clipboard.set_text(line) {
+                            Ok(_) => self.set_status_message("Line copied"),
+                            Err(e) => self.set_status_message(format!("Failed to copy: {}", e)),
+                        }
+                    }
+                }
+            }
+            Command::Paste if self.block_clipboard_matches() => {
+                self.save_state();
+                self.delete_selection();
+                self.paste_block();
+            }
+            Command::Paste => {
+                if let Ok(mut clipboard) = arboard::Clipboard::new() {
+                    match clipboard.get_text() {
+                        Ok(text) => {
+                            if self.cursor_position.1 < self.content.len_lines() {
+                                let text = text.replace("\r\n", "\n").replace('\r', "\n");
+                                self.save_state();
+                                self.delete_selection();
+                                self.cursor_position = self.content.insert(self.cursor_position, &text);
+                                self.modified = true;
+                                self.set_status_message("Pasted from clipboard");
+                            }
+                        }
+                        Err(e) => {
+                            self.set_status_message(format!("Failed to paste: {}", e));
+                        }
+                    }
+                } else {
+                    self.set_status_message("Failed to access clipboard");
+                }
+            }
+            Command::Indent => {
+                if self.selection_range().is_some() {
+                    self.indent_selection(false);
+                } else if !self.showing_suggestions || self.suggestions.is_empty() {
+                    self.save_state();
+                    let indent = self.indent_unit();
+                    self.for_each_cursor(|editor| {
+                        editor.cursor_position = editor.content.insert(editor.cursor_position, &indent);
+                    });
+                    self.modified = true;
+                } else {
+                    self.apply_suggestion();
+                }
+            }
+            Command::Outdent => {
+                self.indent_selection(true);
+            }
+            Command::Undo => {
+                self.clear_selection();
+                self.undo();
+            }
+            Command::Redo => {
+                self.clear_selection();
+                self.redo();
+            }
+            Command::UndoTree => {
+                self.clear_selection();
+                self.open_undo_tree();
+            }
+            Command::OlderState => {
+                self.clear_selection();
+                self.undo_chronologically(true);
+            }
+            Command::NewerState => {
+                self.clear_selection();
+                self.undo_chronologically(false);
+            }
+            Command::SelectAll => {
+                let last_line = self.content.len_lines() - 1;
+                self.selection_anchor = Some((0, 0));
+                self.cursor_position = (self.content.line_len(last_line), last_line);
+            }
+            Command::SelectLine => {
+                self.select_line();
+            }
+            Command::SelectWord => {
+                self.select_word();
+            }
+            Command::ExpandSelection => {
+                self.expand_selection();
+            }
+            Command::ShrinkSelection => {
+                self.shrink_selection();
+            }
+            Command::NextSibling => {
+                self.jump_to_node(|highlighter, range| highlighter.sibling_start(range, true));
+            }
+            Command::PreviousSibling => {
+                self.jump_to_node(|highlighter, range| highlighter.sibling_start(range, false));
+            }
+            Command::ParentNode => {
+                self.jump_to_node(Highlighter::parent_start);
+            }
+            Command::VisualMode => {
+                if self.mode == EditorMode::Visual {
+                    self.clear_selection();
+                } else {
+                    self.mode = EditorMode::Visual;
+                    self.selection_anchor = Some(self.cursor_position);
+                }
+            }
+            Command::BlockMode => {
+                if self.mode == EditorMode::VisualBlock {
+                    self.clear_selection();
+                } else {
+                    self.extra_cursors.clear();
+                    self.mode = EditorMode::VisualBlock;
+                    self.selection_anchor = Some(self.cursor_position);
+                }
+            }
+            Command::NextOccurrence => {
+                self.add_cursor_at_next_occurrence();
+            }
+            Command::CursorAbove => {
+                self.add_cursor_vertical(false);
+            }
+            Command::CursorBelow => {
+                self.add_cursor_vertical(true);
+            }
+            Command::CursorPerMatch => {
+                self.add_cursors_at_matches();
+            }
+            Command::Find => {
+                self.popup_state = PopupType::Find;
+                self.search_query.clear();
+                self.search_error = None;
+            }
+            Command::Replace => {
+                self.search_query.clear();
+                self.mode = EditorMode::Replace;
+                self.popup_state = PopupType::Replace;
+            }
+            Command::FindNext => {
+                self.find_next();
+            }
+            Command::ProjectSearch => {
+                self.open_project_search();
+            }
+            Command::ProjectReplace => {
+                self.open_project_replace();
+            }
+            Command::ToggleRegex | Command::ToggleIgnoreCase | Command::ToggleWholeWord => {
+                self.toggle_search_option(command);
+            }
+            Command::FocusExplorer => {
+                self.tree_focused = self.show_tree && !self.tree_focused;
+                if self.tree_focused {
+                    self.file_entries = Self::read_directory(&self.current_dir)?;
+                }
+            }
+            Command::NewFile => {
+                self.popup_state = PopupType::NewFile;
+                self.temp_filename.clear();
+            }
+            Command::NewDirectory => {
+                self.popup_state = PopupType::NewDirectory;
+                self.temp_filename.clear();
+            }
+            Command::DeleteComments => self.delete_comments(),
+            Command::RemoveEmptyLines => self.remove_empty_lines(),
+            Command::TransliterateToAscii => self.transliterate_to_ascii(),
+            Command::ConvertLineEndings => self.convert_line_endings(),
+            Command::ReopenWithEncoding => self.open_encoding_select(EncodingAction::Reopen),
+            Command::SaveWithEncoding => self.open_encoding_select(EncodingAction::Save),
+            Command::ClearCache => {
+                if let Err(e) = self.clear_cache() {
+                    self.set_status_message(format!("Error clearing cache: {}", e));
+                }
+            }
+            Command::RunFile => {
+                self.run_file()?;
+            }
+            Command::ToolMenu => {
+                self.popup_state = PopupType::ToolMenu;
+                self.tool_menu_selection = 0;
+            }
+            Command::Settings => {
+                self.settings_selection = 0;
+                self.popup_state = PopupType::Settings;
+            }
+            Command::Palette => {
+                self.open_command_palette();
+            }
+            Command::Help => {
+                self.show_help();
+            }
+        }
+        Ok(())
+    }
+    fn run_file(&mut self) -> std::io::Result<()> {
+        let Some(filename) = &self.filename else {
+            return Ok(());
+        };
+        let path = filename.to_str().unwrap_or_default();
+        let current_dir = match std::env::current_dir() {
+            Ok(dir) => dir.display().to_string(),
+            Err(e) => {
+                self.set_status_message(format!("Failed to get current directory: {}", e));
+                return Ok(());
+            }
+        };
+        let run_command = match path {
+            p if p.ends_with(".rs") => format!("cd '{}' && cargo run", current_dir),
+            p if p.ends_with(".cs") => format!("dotnet run '{}'", path),
+            p if p.ends_with(".py") => format!("python3 '{}'", path),
+            p if p.ends_with(".js") => format!("node '{}'", path),
+            p if p.ends_with(".go") => format!("go run '{}'", path),
+            _ => {
+                self.set_status_message("Unsupported file type");
+                return Ok(());
+            }
+        };
+        terminal::disable_raw_mode()?;
+        crossterm::execute!(
+            self.terminal.backend_mut(),
+            terminal::LeaveAlternateScreen,
+            terminal::Clear(ClearType::All)
+        )?;
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&run_command)
+            .status();
+        println!("\nPress Enter to continue...");
+        let _ = std::io::stdin().read_line(&mut String::new());
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(
+            self.terminal.backend_mut(),
+            terminal::EnterAlternateScreen,
+            terminal::Clear(ClearType::All),
+            cursor::Show
+        )?;
+        self.terminal.clear()?;
+        self.draw()?;
+        match status {
+            Ok(status) if status.success() => {
+                self.set_status_message("Program ran successfully.");
+            }
+            Ok(status) => {
+                self.set_status_message(format!("Program exited with status: {}", status));
+            }
+            Err(e) => {
+                self.set_status_message(format!("Failed to run: {}", e));
+            }
+        }
+        Ok(())
+    }
+    fn open_command_palette(&mut self) {
+        self.palette_query.clear();
+        self.update_command_palette();
+        self.popup_state = PopupType::CommandPalette;
+    }
+    fn update_command_palette(&mut self) {
+        let mut results: Vec<(i64, usize, Vec<usize>)> = COMMANDS
+            .iter()
+            .enumerate()
+            .filter_map(|(index, info)| {
+                if self.palette_query.is_empty() {
+                    return Some((0, index, Vec::new()));
+                }
+                let label = format!("{}: {}", info.category, info.name);
+                let (score, indices) = self.suggestion_matcher.fuzzy_indices(&label, &self.palette_query)?;
+                Some((score, index, indices))
+            })
+            .collect();
+        results.sort_by_key(|&(score, index, _)| (std::cmp::Reverse(score), index));
+        self.palette_results = results.into_iter().map(|(_, index, indices)| (index, indices)).collect();
+        self.palette_selection = 0;
+    }
+    fn cleanup(&mut self) -> std::io::Result<()> {
+        terminal::disable_raw_mode()?;
+        crossterm::execute!(
+            self.terminal.backend_mut(),
+            terminal::LeaveAlternateScreen
+        )?;
+        Ok(())
+    }
+    fn try_exit(&mut self) {
+        if self.is_modified() {
+            self.popup_state = PopupType::SaveConfirm(SaveAction::Exit);
+        } else if let Some(index) = self.tabs.iter().position(|tab| tab.modified) {
+            self.switch_tab(index);
+            self.popup_state = PopupType::SaveConfirm(SaveAction::Exit);
+        } else {
+            self.cleanup().unwrap_or(());
+            std::process::exit(0);
+        }
+    }
+    fn handle_save_confirm(&mut self, save: bool, action: SaveAction) -> std::io::Result<()> {
+        if save {
+            if self.filename.is_none() {
+                self.filename = Some(self.current_dir.join(&self.temp_filename));
+                self.popup_state = PopupType::Save;
+                return Ok(());
+            }
+            self.save()?;
+            if self.modified {
+                return Ok(());
+            }
+        }
+        self.popup_state = PopupType::None;
+        match action {
+            SaveAction::Exit => {
+                self.modified = false;
+                self.try_exit();
+            }
+            SaveAction::CloseTab => {
+                self.close_tab();
+            }
+        }
+        Ok(())
+    }
+    fn is_modified(&self) -> bool {
+        if !self.modified {
+            return false;
+        }
+        if let Some(path) = &self.filename {
+            if let Ok(bytes) = fs::read(path) {
+                return TextBuffer::from_bytes_with_encoding(&bytes, self.content.encoding) != self.content;
+            }
+        }
+        true
+    }
+    fn safe_insert_char(&mut self, c: char) {
+        self.cursor_position = self.content.clamp(self.cursor_position);
+        self.cursor_position = self.content.insert_char(self.cursor_position, c);
+        self.modified = true;
+    }
+    fn handle_text_input(&mut self, c: char) {
+        if self.tree_focused {
+            return;
+        }
+        if !c.is_control() {
+            self.save_typing_state(c);
+            self.delete_selection();
+            self.for_each_cursor(|editor| match c {
+                '{' => editor.insert_and_move_cursor("{}", 1),
+                '(' => editor.insert_and_move_cursor("()", 1),
+                '[' => editor.insert_and_move_cursor("[]", 1),
+                '"' => editor.insert_and_move_cursor("\"\"", 1),
+                '\'' => editor.insert_and_move_cursor("''", 1),
+                _ => editor.safe_insert_char(c),
+            });
+            self.typing_group = Some((c, self.cursor_position));
+            self.last_edit_time = Instant::now();
+            if c.is_alphanumeric() || c == '_' || c == '.' {
+                self.update_word_database();
+                self.update_suggestions();
+            } else {
+                self.showing_suggestions = false;
+            }
+        }
+    }
+    fn insert_and_move_cursor(&mut self, text: &str, cursor_offset: usize) {
+        self.cursor_position = self.content.clamp(self.cursor_position);
+        self.content.insert(self.cursor_position, text);
+        self.cursor_position.0 += cursor_offset;
+        self.modified = true;
+    }
+    fn previous_whitespace(&self) -> Option<usize> {
+        self.content.line(self.cursor_position.1)
+            .chars()
+            .take(self.cursor_position.0)
+            .enumerate()
+            .filter(|(_, c)| c.is_whitespace())
+            .map(|(i, _)| i)
+            .last()
+    }
+    fn next_whitespace(&self) -> Option<usize> {
+        self.content.line(self.cursor_position.1)
+            .chars()
+            .enumerate()
+            .skip(self.cursor_position.0)
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, _)| i)
+    }
+    fn find_next(&mut self) {
+        if self.search_query.is_empty() {
+            self.highlighted_matches.clear();
+            return;
+        }
+        self.highlighted_matches = self.search_matches();
+        if !self.highlighted_matches.is_empty() {
+            if let Some(search_index) = self.search_index {
+                let next_index = (search_index + 1) % self.highlighted_matches.len();
+                self.search_index = Some(next_index);
+                self.cursor_position = self.highlighted_matches[next_index].0;
+            } else {
+                self.search_index = Some(0);
+                self.cursor_position = self.highlighted_matches[0].0;
+            }
+        } else {
+            self.search_index = None;
+            match &self.search_error {
+                Some(error) => self.set_status_message(format!("Invalid pattern: {}", error)),
+                None => self.set_status_message("No matches found"),
+            }
+        }
+    }
+    fn search_regex(&self) -> Result<Regex, regex::Error> {
+        Self::build_regex(&self.search_query, self.search_options)
+    }
+    fn build_regex(query: &str, options: SearchOptions) -> Result<Regex, regex::Error> {
+        let pattern = if options.regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        let pattern = if options.whole_word {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(options.ignore_case)
+            .multi_line(true)
+            .build()
+    }
+    fn open_file_finder(&mut self) {
+        let root = fs::canonicalize(&self.current_dir).unwrap_or_else(|_| self.current_dir.clone());
+        if self.file_index.as_ref().is_some_and(|(indexed, _)| *indexed != root) {
+            self.file_index = None;
+        }
+        if self.file_index_receiver.is_none() {
+            self.file_index_receiver = Some(search::spawn_file_index(root));
+        }
+        self.file_finder_query.clear();
+        self.load_file_finder_files();
+        self.popup_state = PopupType::FileFinder;
+    }
+    fn load_file_finder_files(&mut self) {
+        let recent: Vec<PathBuf> = self.recent_files
+            .iter()
+            .filter_map(|rf| fs::canonicalize(&rf.path).ok())
+            .collect();
+        let Some((root, files)) = &self.file_index else {
+            self.file_finder_files.clear();
+            self.update_file_finder();
+            return;
+        };
+        self.file_finder_files = files.iter()
+            .map(|path| {
+                let display = path.strip_prefix(root).unwrap_or(path).display().to_string();
+                let boost = recent.iter()
+                    .position(|recent| recent == path)
+                    .map_or(0, |rank| (recent.len() - rank) as i64 * 10);
+                (path.clone(), display, boost)
+            })
+            .collect();
+        self.update_file_finder();
+    }
+    fn poll_file_index(&mut self) {
+        let Some(receiver) = &self.file_index_receiver else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok(index) => {
+                self.file_index_receiver = None;
+                let changed = self.file_index.as_ref() != Some(&index);
+                self.file_index = Some(index);
+                if changed && self.popup_state == PopupType::FileFinder {
+                    self.load_file_finder_files();
+                }
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.file_index_receiver = None,
+        }
+    }
+    fn update_file_finder(&mut self) {
+        let mut results: Vec<(i64, usize, Vec<usize>)> = self.file_finder_files
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (_, display, boost))| {
+                let (score, indices) = if self.file_finder_query.is_empty() {
+                    (0, Vec::new())
+                } else {
+                    self.suggestion_matcher.fuzzy_indices(display, &self.file_finder_query)?
+                };
+                Some((score + boost, index, indices))
+            })
+            .collect();
+        results.sort_by_key(|&(score, index, _)| (std::cmp::Reverse(score), index));
+        results.truncate(FILE_FINDER_LIMIT);
+        self.file_finder_results = results.into_iter().map(|(_, index, indices)| (index, indices)).collect();
+        self.file_finder_selection = 0;
+    }
+    fn update_outline(&mut self) {
+        if !self.show_tree || !self.show_outline {
+            return;
+        }
+        let revision = self.content.revision();
+        if self.outline_revision != Some(revision) {
+            if self.outline_edit.is_none_or(|(edited, _)| edited != revision) {
+                self.outline_edit = Some((revision, Instant::now()));
+            }
+            let settled = self.outline_revision.is_none()
+                || self.outline_edit.is_some_and(|(_, at)| at.elapsed() >= OUTLINE_DELAY);
+            let symbols = self.highlighter.as_ref()
+                .and_then(|highlighter| highlighter.symbols(&self.content))
+                .or_else(|| settled.then(|| self.syntect_symbols()));
+            if let Some(symbols) = symbols {
+                self.outline_revision = Some(revision);
+                self.outline_symbols = symbols;
+                self.filter_outline();
+            }
+        }
+        if !self.tree_focused {
+            let line = self.cursor_position.1;
+            self.outline_selection = self.outline_results.iter()
+                .rposition(|&(index, _)| self.outline_symbols[index].line <= line)
+                .unwrap_or(0);
+        }
+    }
+    fn filter_outline(&mut self) {
+        self.outline_results = self.outline_symbols.iter()
+            .enumerate()
+            .filter_map(|(index, symbol)| {
+                if self.outline_query.is_empty() {
+                    Some((index, Vec::new()))
+                } else {
+                    let (_, indices) = self.suggestion_matcher.fuzzy_indices(&symbol.name, &self.outline_query)?;
+                    Some((index, indices))
+                }
+            })
+            .collect();
+        self.outline_selection = self.outline_selection.min(self.outline_results.len().saturating_sub(1));
+    }
+    fn buffer_symbols(&self) -> Vec<Symbol> {
+        self.highlighter.as_ref()
+            .and_then(|highlighter| highlighter.symbols(&self.content))
+            .unwrap_or_else(|| self.syntect_symbols())
+    }
+    fn syntect_symbols(&self) -> Vec<Symbol> {
+        if self.content.len_lines() > SYNTECT_OUTLINE_LINE_LIMIT {
+            return Vec::new();
+        }
+        let Some(syntax) = self.current_syntax.as_deref().and_then(|name| self.syntax_set.find_syntax_by_name(name)) else {
+            return Vec::new();
+        };
+        let kinds: Vec<(Scope, &'static str)> = [
+            ("entity.name.function", "function"),
+            ("entity.name.class", "class"),
+            ("entity.name.struct", "struct"),
+            ("entity.name.enum", "enum"),
+            ("entity.name.union", "union"),
+            ("entity.name.trait", "interface"),
+            ("entity.name.interface", "interface"),
+            ("entity.name.impl", "impl"),
+            ("entity.name.type", "type"),
+            ("entity.name.namespace", "module"),
+            ("entity.name.module", "module"),
+            ("entity.name.section", "section"),
+        ].into_iter()
+            .filter_map(|(scope, kind)| Some((Scope::new(scope).ok()?, kind)))
+            .collect();
+        let tab_width = self.tab_width();
+        let mut state = ParseState::new(syntax);
+        let mut stack = ScopeStack::new();
+        let mut symbols: Vec<Symbol> = Vec::new();
+        let mut indents: Vec<usize> = Vec::new();
+        for (line_idx, line) in self.content.lines().enumerate() {
+            let line = format!("{}\n", line);
+            let Ok(ops) = state.parse_line(&line, &self.syntax_set) else {
+                break;
+            };
+            let mut last_end = None;
+            for (range, op) in ScopeRangeIterator::new(&ops, &line) {
+                if stack.apply(op).is_err() {
+                    break;
+                }
+                let text = &line[range.clone()];
+                if text.trim().is_empty() {
+                    continue;
+                }
+                let Some(kind) = stack.as_slice().iter().rev()
+                    .find_map(|scope| kinds.iter().find(|(prefix, _)| prefix.is_prefix_of(*scope)).map(|&(_, kind)| kind))
+                else {
+                    last_end = None;
+                    continue;
+                };
+                if last_end == Some(range.start) {
+                    if let Some(symbol) = symbols.last_mut() {
+                        symbol.name.push_str(text);
+                        last_end = Some(range.end);
+                        continue;
+                    }
+                }
+                let col = line[..range.start].chars().count();
+                let indent_col = line.chars().position(|c| !c.is_whitespace()).unwrap_or(col);
+                let indent = self.content.display_col((indent_col, line_idx), tab_width);
+                while indents.last().is_some_and(|&open| open >= indent) {
+                    indents.pop();
+                }
+                symbols.push(Symbol {
+                    name: text.trim_start().to_string(),
+                    kind: kind.to_string(),
+                    line: line_idx,
+                    col: col + text.len() - text.trim_start().len(),
+                    depth: indents.len(),
+                });
+                indents.push(indent);
+                last_end = Some(range.end);
+            }
+        }
+        for symbol in &mut symbols {
+            symbol.name = symbol.name.trim_end().to_string();
+        }
+        symbols
+    }
+    fn symbol_icon(kind: &str) -> (&'static str, Color) {
+        match kind {
+            "function" | "method" => ("󰊕", Color::Magenta),
+            "class" | "struct" | "union" | "type" => ("󰠱", Color::Yellow),
+            "enum" => ("", Color::Yellow),
+            "interface" => ("", Color::Cyan),
+            "impl" => ("󰆧", Color::Cyan),
+            "module" => ("󰏗", Color::Blue),
+            "section" => ("#", Color::Blue),
+            _ => ("󰏿", Color::Gray),
+        }
+    }
+    fn handle_outline_key(&mut self, key: KeyEvent) {
+        let len = self.outline_results.len();
+        match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::ALT) && !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.outline_query.push(c);
+                self.outline_selection = 0;
+                self.filter_outline();
+            }
+            KeyCode::Backspace => {
+                self.outline_query.pop();
+                self.outline_selection = 0;
+                self.filter_outline();
+            }
+            KeyCode::Up => {
+                self.outline_selection = self.outline_selection
+                    .checked_sub(1)
+                    .unwrap_or(len.saturating_sub(1));
+            }
+            KeyCode::Down => {
+                self.outline_selection = (self.outline_selection + 1) % len.max(1);
+            }
+            KeyCode::PageUp => {
+                self.outline_selection = self.outline_selection.saturating_sub(10);
+            }
+            KeyCode::PageDown => {
+                self.outline_selection = (self.outline_selection + 10).min(len.saturating_sub(1));
+            }
+            KeyCode::Enter => {
+                if let Some(&(index, _)) = self.outline_results.get(self.outline_selection) {
+                    let symbol = &self.outline_symbols[index];
+                    self.selection_anchor = None;
+                    self.extra_cursors.clear();
+                    self.cursor_position = self.content.clamp((symbol.col, symbol.line));
+                    self.tree_focused = false;
+                }
+            }
+            KeyCode::Esc => {
+                if self.outline_query.is_empty() {
+                    self.tree_focused = false;
+                } else {
+                    self.outline_query.clear();
+                    self.filter_outline();
+                }
+            }
+            _ => {}
+        }
+    }
+    fn symbol_index_path(root: &Path) -> Option<PathBuf> {
+        let home = env::var("HOME").ok().map(PathBuf::from)?;
+        let key = md5::compute(root.to_string_lossy().as_bytes());
+        Some(home.join(".config").join("red").join("symbols").join(format!("{:x}", key)))
+    }
+    fn open_symbol_finder(&mut self, project: bool) {
+        self.symbol_project = project;
+        if project {
+            self.refresh_symbol_index();
+        }
+        self.symbol_query.clear();
+        self.load_symbol_entries();
+        self.popup_state = PopupType::GoToSymbol;
+    }
+    fn refresh_symbol_index(&mut self) {
+        if self.symbol_index_receiver.is_some() {
+            return;
+        }
+        let root = fs::canonicalize(&self.current_dir).unwrap_or_else(|_| self.current_dir.clone());
+        let cache = Self::symbol_index_path(&root);
+        let index = match self.symbol_index.take() {
+            Some(index) if index.root() == root => index,
+            _ => cache.as_deref().map_or_else(|| SymbolIndex::new(&root), |cache| SymbolIndex::load(cache, &root)),
+        };
+        self.symbol_index_receiver = Some(symbols::spawn_refresh(index.clone(), cache));
+        self.symbol_index = Some(index);
+    }
+    fn poll_symbol_index(&mut self) {
+        let Some(receiver) = &self.symbol_index_receiver else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok((index, changed)) => {
+                self.symbol_index = Some(index);
+                self.symbol_index_receiver = None;
+                if changed > 0 && self.popup_state == PopupType::GoToSymbol && self.symbol_project {
+                    self.load_symbol_entries();
+                }
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.symbol_index_receiver = None,
+        }
+    }
+    fn load_symbol_entries(&mut self) {
+        let current = self.filename.as_ref().and_then(|path| fs::canonicalize(path).ok());
+        let buffer_symbols = self.buffer_symbols().into_iter();
+        self.symbol_entries = if self.symbol_project {
+            let root = self.symbol_index.as_ref().map(SymbolIndex::root);
+            let display = |path: &Path| root
+                .and_then(|root| path.strip_prefix(root).ok())
+                .unwrap_or(path)
+                .display()
+                .to_string();
+            let current_display = current.as_deref().map_or_else(|| "untitled".to_string(), display);
+            let indexed = self.symbol_index.iter()
+                .flat_map(SymbolIndex::symbols)
+                .filter(|&(path, _)| Some(path) != current.as_deref())
+                .map(|(path, symbol)| (Some(path.to_path_buf()), format!("{}:{}", display(path), symbol.line + 1), symbol.clone()));
+            buffer_symbols
+                .map(|symbol| (None, format!("{}:{}", current_display, symbol.line + 1), symbol))
+                .chain(indexed)
+                .collect()
+        } else {
+            buffer_symbols
+                .map(|symbol| (None, format!(":{}", symbol.line + 1), symbol))
+                .collect()
+        };
+        self.update_symbol_finder();
+    }
+    fn update_symbol_finder(&mut self) {
+        let mut results: Vec<(i64, usize, Vec<usize>)> = self.symbol_entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (_, _, symbol))| {
+                let (score, indices) = if self.symbol_query.is_empty() {
+                    (0, Vec::new())
+                } else {
+                    self.suggestion_matcher.fuzzy_indices(&symbol.name, &self.symbol_query)?
+                };
+                Some((score, index, indices))
+            })
+            .collect();
+        results.sort_by_key(|&(score, index, _)| (std::cmp::Reverse(score), index));
+        results.truncate(SYMBOL_FINDER_LIMIT);
+        self.symbol_results = results.into_iter().map(|(_, index, indices)| (index, indices)).collect();
+        self.symbol_selection = 0;
+    }
+    fn open_symbol_entry(&mut self) -> std::io::Result<()> {
+        let Some((path, _, symbol)) = self.symbol_results.get(self.symbol_selection)
+            .map(|&(index, _)| self.symbol_entries[index].clone())
+        else {
+            return Ok(());
+        };
+        self.popup_state = PopupType::None;
+        if let Some(path) = &path {
+            self.open_file(path)?;
+            if self.filename.as_deref() != Some(path.as_path()) {
+                return Ok(());
+            }
+        }
+        self.clear_selection();
+        self.extra_cursors.clear();
+        self.cursor_position = self.content.clamp((symbol.col, symbol.line));
+        Ok(())
+    }
+    fn open_project_search(&mut self) {
+        self.search_error = None;
+        self.popup_state = PopupType::ProjectSearch;
+    }
+    fn start_project_search(&mut self) {
+        self.project_search_hits.clear();
+        self.project_search_selection = 0;
+        self.project_search_dirty = false;
+        self.project_search_receiver = None;
+        if self.project_search_query.is_empty() {
+            return;
+        }
+        match Self::build_regex(&self.project_search_query, self.search_options) {
+            Ok(regex) => {
+                self.search_error = None;
+                self.project_search_receiver = Some(search::spawn_search(self.current_dir.clone(), regex));
+            }
+            Err(e) => self.search_error = Some(Self::describe_regex_error(e)),
+        }
+    }
+    fn poll_project_search(&mut self) {
+        let Some(receiver) = &self.project_search_receiver else {
+            return;
+        };
+        for _ in 0..1000 {
+            match receiver.try_recv() {
+                Ok(hit) => self.project_search_hits.push(hit),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.project_search_receiver = None;
+                    return;
+                }
+            }
+        }
+    }
+    fn open_project_search_hit(&mut self) -> std::io::Result<()> {
+        let Some(hit) = self.project_search_hits.get(self.project_search_selection).cloned() else {
+            return Ok(());
+        };
+        self.popup_state = PopupType::None;
+        self.open_file(&hit.path)?;
+        if self.filename.as_deref() == Some(hit.path.as_path()) {
+            self.clear_selection();
+            self.extra_cursors.clear();
+            self.cursor_position = self.content.clamp((hit.col, hit.line));
+        }
+        Ok(())
+    }
+    fn open_project_replace(&mut self) {
+        if self.project_search_dirty || self.project_search_hits.is_empty() {
+            self.set_status_message("Run the search before replacing");
+            return;
+        }
+        if self.project_search_receiver.is_some() {
+            self.set_status_message("Search is still running");
+            return;
+        }
+        self.project_replace_checked = vec![true; self.project_search_hits.len()];
+        self.popup_state = PopupType::ProjectReplace;
+    }
+    fn apply_project_replace(&mut self) {
+        let regex = match Self::build_regex(&self.project_search_query, self.search_options) {
+            Ok(regex) => regex,
+            Err(e) => {
+                self.search_error = Some(Self::describe_regex_error(e));
+                return;
+            }
+        };
+        let expand = self.search_options.regex;
+        let template = self.project_replace_text.clone();
+        let hits = std::mem::take(&mut self.project_search_hits);
+        let mut files: Vec<(PathBuf, Vec<&SearchHit>)> = Vec::new();
+        for (hit, _) in hits.iter().zip(&self.project_replace_checked).filter(|(_, &on)| on) {
+            match files.last_mut() {
+                Some((path, file_hits)) if *path == hit.path => file_hits.push(hit),
+                _ => files.push((hit.path.clone(), vec![hit])),
+            }
+        }
+        let selected: usize = files.iter().map(|(_, file_hits)| file_hits.len()).sum();
+        let mut report = Vec::new();
+        let mut replaced = 0;
+        let mut touched = 0;
+        let mut stale = 0;
+        let stale_note = |skipped: usize| if skipped > 0 {
+            format!(", {} skipped (file changed since search)", skipped)
+        } else {
+            String::new()
+        };
+        for (path, file_hits) in files {
+            if let Some(index) = self.find_tab(&path) {
+                let original_tab = self.active_tab;
+                self.switch_tab(index);
+                self.save_state();
+                let applied = search::replace_hits(&mut self.content, &file_hits, &regex, &template, expand);
+                let skipped = file_hits.len() - applied;
+                if applied > 0 {
+                    self.modified = true;
+                    self.save_state();
+                    touched += 1;
+                    report.push((path, format!("{} replaced in open tab, not saved{}", applied, stale_note(skipped))));
+                } else {
+                    report.push((path, format!("{} skipped (open tab changed since search)", skipped)));
+                }
+                self.switch_tab(original_tab);
+                replaced += applied;
+                stale += skipped;
+                continue;
+            }
+            let bytes = match fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    report.push((path, format!("failed: {}", e)));
+                    continue;
+                }
+            };
+            let mut content = TextBuffer::from_bytes(&bytes);
+            if content.decode_errors {
+                report.push((path, format!("skipped: not valid {}", content.encoding.name())));
+                continue;
+            }
+            let applied = search::replace_hits(&mut content, &file_hits, &regex, &template, expand);
+            let skipped = file_hits.len() - applied;
+            stale += skipped;
+            if applied == 0 {
+                report.push((path, format!("{} skipped (file changed since search)", skipped)));
+                continue;
+            }
+            match content.write_file_atomic(&path) {
+                Ok(_) => {
+                    replaced += applied;
+                    touched += 1;
+                    report.push((path, format!("{} replaced{}", applied, stale_note(skipped))));
+                }
+                Err(e) => report.push((path, format!("failed: {}", e))),
+            }
+        }
+        self.project_search_hits = hits;
+        self.project_search_dirty = true;
+        self.set_status_message(format!(
+            "Replaced {} of {} selected occurrences in {} files{}",
+            replaced,
+            selected,
+            touched,
+            stale_note(stale)
+        ));
+        self.project_replace_report = report;
+        self.project_search_selection = 0;
+        self.popup_state = if self.project_replace_report.is_empty() {
+            PopupType::ProjectSearch
+        } else {
+            PopupType::ProjectReplaceReport
+        };
+    }
+    fn project_search_status(&self) -> String {
+        let mut files = self.project_search_hits.iter().map(|hit| &hit.path).collect::<Vec<_>>();
+        files.dedup();
+        let state = if self.project_search_receiver.is_some() { "Searching… " } else { "" };
+        format!(" {}{} hits in {} files ", state, self.project_search_hits.len(), files.len())
+    }
+    fn search_matches(&mut self) -> Vec<TextRange> {
+        if self.search_query.is_empty() {
+            self.search_error = None;
+            return Vec::new();
+        }
+        match self.search_regex() {
+            Ok(regex) => {
+                self.search_error = None;
+                self.content.find_regex(&regex)
+            }
+            Err(e) => {
+                self.search_error = Some(Self::describe_regex_error(e));
+                Vec::new()
+            }
+        }
+    }
+    fn describe_regex_error(error: regex::Error) -> String {
+        match error {
+            regex::Error::Syntax(message) => message.lines().last().unwrap_or_default().trim().to_string(),
+            error => error.to_string(),
+        }
+    }
+    fn replacement_at(&self, regex: &Regex, text: &str, start: usize) -> String {
+        if !self.search_options.regex {
+            return self.replace_text.clone();
+        }
+        let mut replacement = String::new();
+        if let Some(captures) = regex.captures_at(text, start) {
+            captures.expand(&self.replace_text, &mut replacement);
+        }
+        replacement
+    }
+    fn toggle_search_option(&mut self, command: Command) -> bool {
+        let (option, label) = match command {
+            Command::ToggleRegex => (&mut self.search_options.regex, "Regex"),
+            Command::ToggleIgnoreCase => (&mut self.search_options.ignore_case, "Ignore case"),
+            Command::ToggleWholeWord => (&mut self.search_options.whole_word, "Whole word"),
+            _ => return false,
+        };
+        *option = !*option;
+        let message = format!("{} {}", label, if *option { "on" } else { "off" });
+        self.set_status_message(message);
+        true
+    }
+    fn search_options_label(&self) -> String {
+        let flag = |enabled: bool, label: &str| if enabled { format!("[{}]", label) } else { format!(" {} ", label) };
+        format!(
+            " {}{}{} ",
+            flag(self.search_options.regex, ".*"),
+            flag(self.search_options.ignore_case, "Aa"),
+            flag(self.search_options.whole_word, "W")
+        )
+    }
+    fn handle_search_input(&mut self, c: char) {
+        match c {
+            '\n' => self.find_next(),
+            c if !c.is_control() => {
+                self.search_query.push(c);
+                self.find_next();
+            }
+            _ => {}
+        }
+    }
+    fn open_file(&mut self, path: &PathBuf) -> std::io::Result<()> {
+        if path.is_dir() {
+            self.log_error(&format!("Attempted to open directory: {}", path.display()));
+            self.set_status_message("Cannot open a directory");
+            return Ok(());
+        }
+        if self.modified && self.filename.is_some() {
+            self.save_state();
+        }
+        if let Some(index) = self.file_entries.iter().position(|entry| entry.path == *path) {
+            self.file_explorer_selection = index;
+        }
+        if let Some(index) = self.find_tab(path) {
+            self.switch_tab(index);
+            self.show_initial_menu = false;
+            self.set_status_message(format!("Switched to {}", Self::format_path(path)));
+            return Ok(());
+        }
+        match fs::read(path) {
+            Ok(bytes) => {
+                let content = TextBuffer::from_bytes(&bytes);
+                let undo_tree = Self::load_undo_history(path, &content).unwrap_or_default();
+                self.new_tab(EditorTab {
+                    last_save_state: Some(content.clone()),
+                    undo_tree,
+                    content,
+                    filename: Some(path.clone()),
+                    current_syntax: Self::detect_syntax(&self.syntax_set, path),
+                    last_modified: fs::metadata(path).and_then(|metadata| metadata.modified()).ok(),
+                    ..EditorTab::default()
+                });
+                self.add_to_recent_files(path.clone());
+                match Self::load_warning(&self.content) {
+                    Some(warning) => self.set_status_message(warning),
+                    None => self.set_status_message(format!("Opened {}", Self::format_path(path))),
+                }
+                self.show_initial_menu = false;
+                if let Some(index) = self.file_entries.iter().position(|entry| entry.path == *path) {
+                    self.file_explorer_selection = index;
+                }
+                Ok(())
+            }
+            Err(e) => {
+                let error_msg = format!("Error opening file: {}", e);
+                self.log_error(&error_msg);
+                self.set_status_message(&error_msg);
+                Ok(())
+            }
+        }
+    }
+    fn load_warning(content: &TextBuffer) -> Option<String> {
+        if content.decode_errors {
+            Some(format!(
+                "File is not valid {}: invalid bytes shown as �, saving will ask before overwriting",
+                content.encoding.name()
+            ))
+        } else if content.mixed_line_endings {
+            Some("File has mixed line endings; kept as-is until you convert line endings".to_string())
+        } else {
+            None
+        }
+    }
+    fn load_recent_files() -> Vec<RecentFile> {
+        let home = env::var("HOME").ok().map(PathBuf::from);
+        let config_dir = home.map(|h| h.join(".config").join("red"));
+        if let Some(config_dir) = config_dir {
+            if !config_dir.exists() {
+                let _ = fs::create_dir_all(&config_dir);
+            }
+            let history_file = config_dir.join("history");
+            if let Ok(content) = fs::read_to_string(history_file) {
+                return content
+                    .lines()
+                    .filter_map(|line| {
+                        let path = PathBuf::from(line);
+                        Some(RecentFile {
+                            exists: path.exists(),
                             last_modified: path.metadata().ok()?.modified().ok()?,
                             path,
                         })
@@ -2698,6 +4473,200 @@ impl Editor {
             let _ = fs::write(history_file, content);
         }
     }
+    fn config_path() -> Option<PathBuf> {
+        let home = env::var("HOME").ok().map(PathBuf::from)?;
+        Some(home.join(".config").join("red").join("config.toml"))
+    }
+    fn config_mtime() -> Option<SystemTime> {
+        fs::metadata(Self::config_path()?).ok()?.modified().ok()
+    }
+    fn load_config(&mut self) -> bool {
+        let Some(path) = Self::config_path() else {
+            return false;
+        };
+        self.config_modified = Self::config_mtime();
+        match Config::load(&path) {
+            Ok(config) => self.apply_config(config),
+            Err(e) => {
+                self.set_status_message(format!("config.toml: {}", e));
+                false
+            }
+        }
+    }
+    fn apply_config(&mut self, mut config: Config) -> bool {
+        let known_theme = self.theme_set.themes.contains_key(&config.ui.theme);
+        if !known_theme {
+            self.set_status_message(format!("config.toml: unknown theme \"{}\"", config.ui.theme));
+            config.ui.theme = config::DEFAULT_THEME.to_string();
+        }
+        self.show_tree = config.ui.show_tree;
+        self.show_numbers = config.ui.show_numbers;
+        self.show_status = config.ui.show_status;
+        if !self.show_tree {
+            self.tree_focused = false;
+        }
+        self.config = config;
+        known_theme
+    }
+    fn check_config_changes(&mut self) {
+        if Self::config_mtime() != self.config_modified && self.load_config() {
+            self.set_status_message("Settings reloaded from config.toml");
+        }
+    }
+    fn save_config(&mut self) {
+        let Some(path) = Self::config_path() else {
+            return;
+        };
+        match self.config.save(&path) {
+            Ok(_) => self.config_modified = Self::config_mtime(),
+            Err(e) => self.set_status_message(format!("Failed to save config.toml: {}", e)),
+        }
+    }
+    fn settings_rows(&self) -> Vec<(&'static str, String)> {
+        let flag = |enabled: bool| if enabled { "[x]".to_string() } else { "[ ]".to_string() };
+        let language_count = self.config.languages.len();
+        vec![
+            ("Show file tree", flag(self.config.ui.show_tree)),
+            ("Show line numbers", flag(self.config.ui.show_numbers)),
+            ("Show status bar", flag(self.config.ui.show_status)),
+            ("Theme", format!("< {} >", self.config.ui.theme)),
+            ("Tab width", format!("< {} >", self.config.editor.tab_width)),
+            ("Insert spaces", flag(self.config.editor.insert_spaces)),
+            ("Language overrides", format!("{} (edit config.toml)", language_count)),
+        ]
+    }
+    fn change_setting(&mut self, forward: bool) {
+        let mut config = self.config.clone();
+        match self.settings_selection {
+            0 => config.ui.show_tree = !config.ui.show_tree,
+            1 => config.ui.show_numbers = !config.ui.show_numbers,
+            2 => config.ui.show_status = !config.ui.show_status,
+            3 => {
+                let themes: Vec<&String> = self.theme_set.themes.keys().collect();
+                let current = themes.iter().position(|name| **name == config.ui.theme).unwrap_or(0);
+                let next = if forward {
+                    (current + 1) % themes.len()
+                } else {
+                    (current + themes.len() - 1) % themes.len()
+                };
+                config.ui.theme = themes[next].clone();
+            }
+            4 => config.cycle_tab_width(forward),
+            5 => config.editor.insert_spaces = !config.editor.insert_spaces,
+            _ => {
+                self.open_config_file();
+                return;
+            }
+        }
+        self.apply_config(config);
+        self.save_config();
+    }
+    fn open_config_file(&mut self) {
+        let Some(path) = Self::config_path() else {
+            return;
+        };
+        if !path.exists() {
+            self.save_config();
+        }
+        self.popup_state = PopupType::None;
+        if let Err(e) = self.open_file(&path) {
+            self.set_status_message(format!("Failed to open config.toml: {}", e));
+        }
+    }
+    fn tab_width(&self) -> usize {
+        self.config.tab_width(self.filename.as_deref())
+    }
+    fn indent_unit(&self) -> String {
+        self.config.indent_unit(self.filename.as_deref())
+    }
+    fn keymap_path() -> Option<PathBuf> {
+        let home = env::var("HOME").ok().map(PathBuf::from)?;
+        Some(home.join(".config").join("red").join("keymap.toml"))
+    }
+    fn resolve_key(&mut self, key: KeyEvent, contexts: &[Context]) -> KeyMatch {
+        self.pending_keys.push(key);
+        let result = self.keymap.resolve(&self.pending_keys, contexts);
+        let sequence: Vec<_> = self.pending_keys.iter().map(|key| keymap::format_key(&keymap::chord_of(key))).collect();
+        match result {
+            KeyMatch::Pending => {
+                self.set_status_message(format!("{} …", sequence.join(" ")));
+                return KeyMatch::Pending;
+            }
+            KeyMatch::Unbound if self.pending_keys.len() > 1 => {
+                self.set_status_message(format!("{} is not bound", sequence.join(" ")));
+                self.pending_keys.clear();
+                return KeyMatch::Pending;
+            }
+            _ => {}
+        }
+        self.pending_keys.clear();
+        result
+    }
+    fn undo_history_dir() -> Option<PathBuf> {
+        let home = env::var("HOME").ok().map(PathBuf::from)?;
+        Some(home.join(".config").join("red").join("undo"))
+    }
+    fn undo_history_file(path: &Path) -> Option<(PathBuf, PathBuf)> {
+        let path = path.canonicalize().ok()?;
+        let key = md5::compute(path.to_string_lossy().as_bytes());
+        Some((Self::undo_history_dir()?.join(format!("{:x}", key)), path))
+    }
+    fn load_undo_history(path: &Path, content: &TextBuffer) -> Option<UndoTree> {
+        let (history_file, path) = Self::undo_history_file(path)?;
+        let bytes = fs::read(&history_file).ok()?;
+        match bincode::deserialize::<UndoHistory>(&bytes) {
+            Ok(mut history) if history.path == path && history.content_hash == md5::compute(content.text()).0 => {
+                history.tree.mark_saved();
+                Some(history.tree)
+            }
+            _ => {
+                let _ = fs::remove_file(history_file);
+                None
+            }
+        }
+    }
+    fn save_undo_history(&mut self, path: &Path) {
+        self.save_state();
+        let Some((history_file, path)) = Self::undo_history_file(path) else {
+            return;
+        };
+        if self.undo_tree.is_empty() {
+            let _ = fs::remove_file(history_file);
+            return;
+        }
+        let mut history = UndoHistory {
+            path,
+            content_hash: md5::compute(self.content.text()).0,
+            tree: self.undo_tree.clone(),
+        };
+        history.tree.trim(UNDO_HISTORY_FILE_LIMIT, |node| bincode::serialized_size(node).unwrap_or(0));
+        if let (Some(dir), Ok(bytes)) = (history_file.parent(), bincode::serialize(&history)) {
+            let _ = fs::create_dir_all(dir);
+            if fs::write(&history_file, bytes).is_ok() {
+                Self::prune_undo_histories(dir);
+            }
+        }
+    }
+    fn prune_undo_histories(dir: &Path) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let metadata = entry.metadata().ok()?;
+                Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+            })
+            .collect();
+        files.sort_by_key(|(modified, _, _)| std::cmp::Reverse(*modified));
+        let mut total = 0;
+        for (_, len, path) in files {
+            total += len;
+            if total > UNDO_HISTORY_DIR_LIMIT {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
     fn add_to_recent_files(&mut self, path: PathBuf) {
         if let Some(existing) = self.recent_files
             .iter()
@@ -2725,7 +4694,6 @@ impl Editor {
                 name: String::from(".."),
                 path: parent.to_path_buf(),
                 is_dir: true,
-                is_selected: false,
                 depth,
             });
         }
@@ -2742,8 +4710,7 @@ impl Editor {
                     name,
                     path,
                     is_dir,
-                    is_selected: false,
-                    depth,
+                        depth,
                 }
             })
             .collect();
@@ -2766,7 +4733,7 @@ impl Editor {
                 return "";
             }
             for (folder_name, icon) in FOLDER_ICONS {
-                if *folder_name == "" || name.to_lowercase() == *folder_name {
+                if folder_name.is_empty() || name.to_lowercase() == *folder_name {
                     return icon;
                 }
             }
@@ -2790,7 +4757,7 @@ impl Editor {
             _ => {}
         }
         for (extension, icon) in FILE_ICONS {
-            if *extension == "" {
+            if extension.is_empty() {
                 continue;
             }
             if ext.to_lowercase() == *extension {
@@ -2799,16 +4766,226 @@ impl Editor {
         }
         ""
     }
+    fn draw_pane(
+        frame: &mut Frame,
+        area: Rect,
+        pane: PaneView,
+        syntax_set: &SyntaxSet,
+        theme: &Theme,
+        show_numbers: bool,
+    ) -> (Rect, usize) {
+        let title = if let Some(path) = pane.filename {
+            format!("─[{}]", Self::format_path(path))
+        } else {
+            "─[New File]".to_string()
+        };
+        let block = Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(if pane.focused { Style::default().fg(Color::Green) } else { Style::default() });
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let len_lines = pane.content.len_lines();
+        let terminal_height = inner.height as usize;
+        let cursor_line = pane.folds.visible_line(pane.cursor_position.1);
+        let mut scroll_offset = pane.folds.visible_line(pane.scroll_offset.min(len_lines - 1));
+        if cursor_line < scroll_offset {
+            scroll_offset = cursor_line;
+        } else if pane.folds.rows_between(scroll_offset, cursor_line, len_lines) >= terminal_height {
+            scroll_offset = pane.folds.step(cursor_line, 1 - terminal_height.max(1) as isize, len_lines);
+        }
+        let cursor_display = pane.content.display_col(pane.cursor_position, pane.tab_width);
+        let text = {
+            let mut visible_lines = vec![scroll_offset];
+            while visible_lines.len() < terminal_height {
+                match pane.folds.next_visible(visible_lines[visible_lines.len() - 1], len_lines) {
+                    Some(line_idx) => visible_lines.push(line_idx),
+                    None => break,
+                }
+            }
+            let visible_width = (inner.width as usize).saturating_sub(if show_numbers { 5 } else { 1 });
+            let scope_lines: Option<HashMap<usize, Vec<syntax::ScopeRun>>> = pane.highlighter.map(|highlighter| {
+                visible_lines
+                    .chunk_by(|a, b| a + 1 == *b)
+                    .flat_map(|run| {
+                        let first = run[0];
+                        highlighter.highlight_lines(pane.content, first..first + run.len())
+                            .into_iter()
+                            .enumerate()
+                            .map(move |(i, runs)| (first + i, runs))
+                    })
+                    .collect()
+            });
+            let mut highlighter = pane.syntax
+                .filter(|_| scope_lines.is_none())
+                .and_then(|syntax_name| syntax_set.find_syntax_by_name(syntax_name))
+                .map(|syntax| HighlightLines::new(syntax, theme));
+            let theme_highlighter = syntect::highlighting::Highlighter::new(theme);
+            let mut scope_styles: HashMap<&str, Style> = HashMap::new();
+            for &(_, _, scope) in scope_lines.iter().flat_map(|lines| lines.values()).flatten() {
+                scope_styles.entry(scope).or_insert_with(|| Self::scope_style(&theme_highlighter, Some(scope)));
+            }
+            let default_style = Self::scope_style(&theme_highlighter, None);
+            let lines: Vec<Line> = visible_lines.iter()
+                .map(|&line_idx| {
+                    let mut spans = Vec::new();
+                    let marker = match pane.folds.marker(line_idx) {
+                        Some(true) => "▸",
+                        Some(false) => "▾",
+                        None => " ",
+                    };
+                    if show_numbers {
+                        spans.push(Span::styled(
+                            format!("{:4}{}", line_idx + 1, marker),
+                            Style::default().fg(Color::DarkGray)
+                        ));
+                    } else {
+                        spans.push(Span::styled(marker, Style::default().fg(Color::DarkGray)));
+                    }
+                    let visible_start = if line_idx == pane.cursor_position.1 && visible_width > 0 {
+                        (cursor_display / visible_width) * visible_width
+                    } else {
+                        0
+                    };
+                    let runs = scope_lines.as_ref().and_then(|lines| lines.get(&line_idx));
+                    let visible_text = if runs.is_some() {
+                        String::new()
+                    } else {
+                        pane.content.display_segment(line_idx, visible_start, visible_width, pane.tab_width)
+                    };
+                    let segments: Vec<(Style, String)> = if let Some(runs) = runs {
+                        pane.content.display_segment_styled(line_idx, visible_start, visible_width, pane.tab_width, |col| {
+                            runs.iter()
+                                .find(|&&(start, end, _)| (start..end).contains(&col))
+                                .map_or(default_style, |(_, _, scope)| scope_styles[scope])
+                        })
+                    } else if let Some(ranges) = highlighter.as_mut()
+                        .and_then(|highlighter| highlighter.highlight_line(&visible_text, syntax_set).ok())
+                    {
+                        ranges.into_iter()
+                            .map(|(style, text)| (Self::syntect_style(style), text.to_string()))
+                            .collect()
+                    } else {
+                        vec![(Style::default(), visible_text.to_string())]
+                    };
+                    let range_overlay = |(start, end): ((usize, usize), (usize, usize)), style: Style| {
+                        let from = if line_idx == start.1 { start.0 } else { 0 };
+                        let to = if line_idx == end.1 {
+                            pane.content.display_col((end.0, line_idx), pane.tab_width)
+                        } else {
+                            pane.content.display_col((pane.content.line_len(line_idx), line_idx), pane.tab_width) + 1
+                        };
+                        (
+                            pane.content.display_col((from, line_idx), pane.tab_width).saturating_sub(visible_start),
+                            to.saturating_sub(visible_start),
+                            style,
+                        )
+                    };
+                    let mut overlays: Vec<(usize, usize, Style)> = pane.matches.iter()
+                        .filter(|(start, end)| (start.1..=end.1).contains(&line_idx))
+                        .map(|&range| range_overlay(range, Style::default().bg(Color::DarkGray).fg(Color::White)))
+                        .collect();
+                    if let Some(range) = pane.selection.filter(|(start, end)| (start.1..=end.1).contains(&line_idx)) {
+                        overlays.push(range_overlay(range, Style::default().bg(Color::Blue).fg(Color::White)));
+                    }
+                    if let Some(range) = pane.preview.filter(|(start, end)| (start.1..=end.1).contains(&line_idx)) {
+                        overlays.push(range_overlay(range, Style::default().bg(Color::Green).fg(Color::Black)));
+                    }
+                    if let Some((_, _, left, right)) = pane.block.filter(|&(first, last, _, _)| (first..=last).contains(&line_idx)) {
+                        overlays.push((
+                            left.saturating_sub(visible_start),
+                            right.max(left + 1).saturating_sub(visible_start),
+                            Style::default().bg(Color::Blue).fg(Color::White)
+                        ));
+                    }
+                    let cursor_style = Style::default().add_modifier(Modifier::REVERSED);
+                    let mut end_cursor = false;
+                    for &(col, _) in pane.cursors.iter().filter(|&&(_, line)| line == line_idx) {
+                        let display = pane.content.display_col((col, line_idx), pane.tab_width);
+                        if display < visible_start || display >= visible_start + visible_width {
+                            continue;
+                        }
+                        if col >= pane.content.line_len(line_idx) {
+                            end_cursor = true;
+                        } else {
+                            overlays.push((display - visible_start, display - visible_start + 1, cursor_style));
+                        }
+                    }
+                    spans.extend(Self::apply_overlays(segments, &overlays));
+                    if end_cursor {
+                        spans.push(Span::styled(" ", cursor_style));
+                    }
+                    Line::from(spans)
+                })
+                .collect();
+            Text::from(lines)
+        };
+        let paragraph = Paragraph::new(text);
+        frame.render_widget(paragraph, inner);
+        (inner, scroll_offset)
+    }
+    fn syntect_style(style: syntect::highlighting::Style) -> Style {
+        Style::default().fg(Color::Rgb(style.foreground.r, style.foreground.g, style.foreground.b))
+    }
+    fn scope_style(theme_highlighter: &syntect::highlighting::Highlighter, scope: Option<&str>) -> Style {
+        let stack: Vec<Scope> = scope.and_then(|scope| Scope::new(scope).ok()).into_iter().collect();
+        Self::syntect_style(theme_highlighter.style_for_stack(&stack))
+    }
+    fn split_areas(area: Rect, splits: &[EditorSplit]) -> Vec<Rect> {
+        let mut areas = Vec::new();
+        let mut rest = area;
+        for (i, split) in splits.iter().enumerate() {
+            if i + 1 == splits.len() {
+                areas.push(rest);
+                break;
+            }
+            let chunks = Layout::default()
+                .direction(if split.is_horizontal { Direction::Vertical } else { Direction::Horizontal })
+                .constraints([Constraint::Percentage(split.size), Constraint::Min(1)])
+                .split(rest);
+            areas.push(chunks[0]);
+            rest = chunks[1];
+        }
+        areas
+    }
+    fn apply_overlays(segments: Vec<(Style, String)>, overlays: &[(usize, usize, Style)]) -> Vec<Span<'static>> {
+        if overlays.is_empty() {
+            return segments.into_iter()
+                .map(|(style, text)| Span::styled(text, style))
+                .collect();
+        }
+        let mut spans = Vec::new();
+        let mut current = String::new();
+        let mut current_style = Style::default();
+        let mut col = 0;
+        for (style, text) in segments {
+            for ch in text.chars() {
+                let style = overlays.iter()
+                    .filter(|(start, end, _)| col >= *start && col < *end)
+                    .fold(style, |acc, (_, _, overlay)| acc.patch(*overlay));
+                if style != current_style && !current.is_empty() {
+                    spans.push(Span::styled(std::mem::take(&mut current), current_style));
+                }
+                current_style = style;
+                current.push(ch);
+                col += ch.width().unwrap_or(0);
+            }
+        }
+        if !current.is_empty() {
+            spans.push(Span::styled(current, current_style));
+        }
+        spans
+    }
     fn truncate_to_width(text: &str, width: u16) -> String {
         let mut length = 0;
         let mut result = String::new();
-        for c in text.chars() {
-            let char_width = if c.is_ascii() { 1 } else { 2 };
-            if length + char_width > width as usize {
+        for grapheme in text.graphemes(true) {
+            let grapheme_width = grapheme.width();
+            if length + grapheme_width > width as usize {
                 break;
             }
-            length += char_width;
-            result.push(c);
+            length += grapheme_width;
+            result.push_str(grapheme);
         }
         result
     }
@@ -2840,7 +5017,9 @@ impl Editor {
     }
     fn update_word_database(&mut self) {
         let mut word_weights = HashMap::new();
-        for line in &self.content {
+        let start = self.cursor_position.1.saturating_sub(WORD_DATABASE_RADIUS);
+        let end = (self.cursor_position.1 + WORD_DATABASE_RADIUS).min(self.content.len_lines());
+        for line in (start..end).map(|line_idx| self.content.line(line_idx)) {
             for word in line.split_whitespace() {
                 if word.len() > 2 && !word.chars().all(|c| c.is_numeric()) {
                     *word_weights.entry(word.to_string()).or_insert(0.0) += 1.0;
@@ -2853,28 +5032,29 @@ impl Editor {
         self.word_database = word_weights;
     }
     fn get_current_word(&self) -> Option<(String, usize)> {
-        if self.cursor_position.1 >= self.content.len() {
+        if self.cursor_position.1 >= self.content.len_lines() {
             return None;
         }
-        let line = &self.content[self.cursor_position.1];
+        let line = self.content.line(self.cursor_position.1);
         if line.is_empty() || self.cursor_position.0 == 0 {
             return None;
         }
-        let before_cursor = &line[..self.cursor_position.0];
-        let word_start = before_cursor.rfind(|c: char| !c.is_alphanumeric() && c != '_' && c != '.')
+        let before_cursor: Vec<char> = line.chars().take(self.cursor_position.0).collect();
+        let word_start = before_cursor.iter()
+            .rposition(|&c| !c.is_alphanumeric() && c != '_' && c != '.')
             .map(|i| i + 1)
             .unwrap_or(0);
-        if word_start == self.cursor_position.0 {
+        if word_start == before_cursor.len() {
             return None;
         }
         Some((
-            before_cursor[word_start..].to_string(),
+            before_cursor[word_start..].iter().collect(),
             word_start
         ))
     }
     fn update_suggestions(&mut self) {
         if let Some((current_word, _)) = self.get_current_word() {
-            if current_word.len() < 1 {
+            if current_word.is_empty() {
                 self.showing_suggestions = false;
                 return;
             }
@@ -3056,12 +5236,18 @@ impl Editor {
         if !self.showing_suggestions || self.suggestions.is_empty() {
             return;
         }
+        let suggestion = self.suggestions[self.suggestion_index].clone();
+        self.save_state();
+        self.for_each_cursor(|editor| editor.insert_suggestion(&suggestion));
+        self.showing_suggestions = false;
+    }
+    fn insert_suggestion(&mut self, suggestion: &str) {
         if let Some((_, word_start)) = self.get_current_word() {
-            let suggestion = &self.suggestions[self.suggestion_index];
-            let line = &mut self.content[self.cursor_position.1];
+            let line_idx = self.cursor_position.1;
+            self.content.remove((word_start, line_idx), self.cursor_position);
             if suggestion.contains('\n') {
-                let indent = line.chars().take_while(|c| c.is_whitespace()).collect::<String>();
-                let lines: Vec<String> = suggestion
+                let indent = self.content.line(line_idx).chars().take_while(|c| c.is_whitespace()).collect::<String>();
+                let text = suggestion
                     .lines()
                     .enumerate()
                     .map(|(i, l)| {
@@ -3071,44 +5257,15 @@ impl Editor {
                             format!("{}{}", indent, l)
                         }
                     })
-                    .collect();
-                line.replace_range(word_start..self.cursor_position.0, &lines[0]);
-                if lines.len() > 1 {
-                    for (i, new_line) in lines.into_iter().skip(1).enumerate() {
-                        self.content.insert(self.cursor_position.1 + i + 1, new_line);
-                    }
-                }
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                self.content.insert((word_start, line_idx), &text);
+                self.cursor_position = self.content.clamp(self.cursor_position);
             } else {
-                line.replace_range(word_start..self.cursor_position.0, suggestion);
-                self.cursor_position.0 = word_start + suggestion.len();
+                self.cursor_position = self.content.insert((word_start, line_idx), suggestion);
             }
             self.modified = true;
         }
-        self.showing_suggestions = false;
-    }
-    fn get_word_start(&self, line: &str, cursor_x: usize) -> usize {
-        let count = line[..cursor_x].chars().rev()
-            .take_while(|c| c.is_alphanumeric() || *c == '_')
-            .count();
-        cursor_x.saturating_sub(count)
-    }
-    fn centered_rect(&self, width: u16, height: u16, r: Rect) -> Rect {
-        let popup_layout = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Length((r.height.saturating_sub(height)) / 2),
-                Constraint::Length(height),
-                Constraint::Min(0)
-            ])
-            .split(r);
-        Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([
-                Constraint::Length((r.width.saturating_sub(width)) / 2),
-                Constraint::Length(width),
-                Constraint::Min(0)
-            ])
-            .split(popup_layout[1])[1]
     }
     fn update_word_database_for_syntax(&mut self, syntax_name: &str) {
         self.language_keywords.clear();
@@ -3299,8 +5456,8 @@ impl Editor {
         }
         self.word_database = weighted_keywords;
     }
-    fn draw_help(frame: &mut Frame, help_text: &[(&str, &str, &str)], scroll_offset: u16) {
-        let area = frame.size();
+    fn draw_help(frame: &mut Frame, keymap: &Keymap, scroll_offset: u16) {
+        let area = frame.area();
         let width = area.width.saturating_sub(4).min(100);
         let height = area.height.saturating_sub(4);
         let help_area = Rect::new(
@@ -3318,70 +5475,297 @@ impl Editor {
         let inner = help_block.inner(help_area);
         frame.render_widget(help_block, help_area);
         let mut text = Vec::new();
-        let max_key_width = 12;
-        let max_action_width = 15;
+        let max_key_width = 15;
+        let max_action_width = 18;
         let desc_width = inner.width.saturating_sub(max_key_width as u16 + max_action_width as u16 + 6);
-        for (key, action, desc) in help_text {
-            if action.is_empty() {
+        let mut category = "";
+        for info in COMMANDS {
+            let keys = keymap.keys_for(info.command);
+            if keys.is_empty() {
+                continue;
+            }
+            if info.category != category {
+                category = info.category;
                 if !text.is_empty() {
                     text.push(Line::from(""));
                 }
                 text.push(Line::from(vec![
                     Span::styled(
-                        format!("─── {} ", key),
+                        format!("─── {} ", category),
                         Style::default()
                             .fg(Color::Yellow)
                             .add_modifier(Modifier::BOLD)
                     ),
                     Span::styled(
-                        "─".repeat((desc_width as usize).saturating_sub(key.len() + 4)),
+                        "─".repeat((desc_width as usize).saturating_sub(category.len() + 4)),
                         Style::default().fg(Color::DarkGray)
                     ),
                 ]));
-                continue;
             }
             text.push(Line::from(vec![
                 Span::styled(
-                    format!("{:width$}", key, width = max_key_width),
+                    format!("{:width$}", keys.join(" / "), width = max_key_width),
                     Style::default().fg(Color::Green)
                 ),
                 Span::raw(" "),
                 Span::styled(
-                    format!("{:width$}", action, width = max_action_width),
+                    format!("{:width$}", info.name, width = max_action_width),
                     Style::default().fg(Color::White)
                 ),
                 Span::raw(" "),
                 Span::styled(
-                    desc.to_string(),
+                    match info.context {
+                        Context::Tree => format!("{} (in file tree)", info.description),
+                        Context::Search => format!("{} (in search prompts)", info.description),
+                        Context::ProjectSearch => format!("{} (in project search)", info.description),
+                        Context::Global | Context::Editor => info.description.to_string(),
+                    },
                     Style::default().fg(Color::Gray)
                 )
             ]));
         }
-        let help_text = Paragraph::new(text)
-            .alignment(Alignment::Left)
-            .scroll((scroll_offset, 0));
-        frame.render_widget(help_text, inner);
+        let help_text = Paragraph::new(text)
+            .alignment(Alignment::Left)
+            .scroll((scroll_offset, 0));
+        frame.render_widget(help_text, inner);
+    }
+    fn try_close_tab(&mut self) {
+        if self.is_modified() {
+            self.popup_state = PopupType::SaveConfirm(SaveAction::CloseTab);
+        } else {
+            self.close_tab();
+        }
+    }
+    fn close_tab(&mut self) {
+        if self.tabs.len() > 1 {
+            let removed = self.active_tab;
+            self.tabs.remove(removed);
+            self.active_tab = removed.min(self.tabs.len() - 1);
+            for split in &mut self.splits {
+                if split.tab_index == removed {
+                    split.tab_index = self.active_tab;
+                    split.cursor_position = (0, 0);
+                    split.scroll_offset = 0;
+                } else if split.tab_index > removed {
+                    split.tab_index -= 1;
+                }
+            }
+            let tab = std::mem::take(&mut self.tabs[self.active_tab]);
+            self.load_tab(tab);
+        } else {
+            self.cleanup().unwrap_or(());
+            std::process::exit(0);
+        }
+    }
+    fn update_syntax(&mut self) {
+        let tab_width = self.tab_width();
+        Self::update_tab_syntax(&mut self.highlighter, &mut self.folds, &self.content, self.filename.as_deref(), tab_width);
+        self.folds.reveal(self.cursor_position.1);
+        for split in &self.splits {
+            if split.tab_index == self.active_tab {
+                continue;
+            }
+            if let Some(tab) = self.tabs.get_mut(split.tab_index) {
+                let tab_width = self.config.tab_width(tab.filename.as_deref());
+                Self::update_tab_syntax(&mut tab.highlighter, &mut tab.folds, &tab.content, tab.filename.as_deref(), tab_width);
+            }
+        }
+    }
+    fn settle_folds(&mut self) {
+        let tab_width = self.tab_width();
+        Self::update_tab_syntax(&mut self.highlighter, &mut self.folds, &self.content, self.filename.as_deref(), tab_width);
+        self.folds.settle(&self.content, self.highlighter.as_ref(), tab_width);
+    }
+    fn update_tab_syntax(
+        highlighter: &mut Option<Highlighter>,
+        folds: &mut Folds,
+        content: &TextBuffer,
+        path: Option<&Path>,
+        tab_width: usize,
+    ) {
+        if !highlighter.as_ref().is_some_and(|highlighter| highlighter.handles(path)) {
+            *highlighter = Highlighter::for_path(path);
+        }
+        if let Some(highlighter) = highlighter {
+            highlighter.update(content);
+        }
+        folds.update(content, highlighter.as_ref(), tab_width);
+    }
+    fn take_tab(&mut self) -> EditorTab {
+        self.save_state();
+        EditorTab {
+            content: std::mem::take(&mut self.content),
+            cursor_position: self.cursor_position,
+            filename: self.filename.take(),
+            modified: self.modified,
+            scroll_offset: self.scroll_offset,
+            undo_tree: std::mem::take(&mut self.undo_tree),
+            last_save_state: self.last_save_state.take(),
+            current_syntax: self.current_syntax.take(),
+            highlighter: self.highlighter.take(),
+            folds: std::mem::take(&mut self.folds),
+            last_modified: self.last_modified,
+            last_save_time: self.last_save_time,
+        }
+    }
+    fn load_tab(&mut self, tab: EditorTab) {
+        self.content = tab.content;
+        self.cursor_position = tab.cursor_position;
+        self.filename = tab.filename;
+        self.modified = tab.modified;
+        self.scroll_offset = tab.scroll_offset;
+        self.undo_tree = tab.undo_tree;
+        self.last_save_state = tab.last_save_state;
+        self.last_save_cursor = self.cursor_position;
+        self.current_syntax = tab.current_syntax;
+        self.highlighter = tab.highlighter;
+        self.folds = tab.folds;
+        self.outline_revision = None;
+        self.last_modified = tab.last_modified;
+        self.last_save_time = tab.last_save_time;
+        self.highlighted_matches.clear();
+        self.search_index = None;
+        self.showing_suggestions = false;
+        self.clear_selection();
+        self.extra_cursors.clear();
+        self.splits[self.focused_split].tab_index = self.active_tab;
+        if let Some(syntax) = self.current_syntax.clone() {
+            self.update_word_database_for_syntax(&syntax);
+        }
+    }
+    fn new_tab(&mut self, tab: EditorTab) {
+        let is_blank = self.filename.is_none() && !self.modified && self.content.len_chars() == 0;
+        if !is_blank {
+            self.tabs[self.active_tab] = self.take_tab();
+            for split in &mut self.splits {
+                if split.tab_index > self.active_tab {
+                    split.tab_index += 1;
+                }
+            }
+            self.active_tab += 1;
+            self.tabs.insert(self.active_tab, EditorTab::default());
+        }
+        self.load_tab(tab);
     }
-    fn try_close_tab(&mut self) {
-        if self.modified {
-            self.popup_state = PopupType::SaveConfirm(SaveAction::Exit);
+    fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab || index >= self.tabs.len() {
+            return;
+        }
+        self.tabs[self.active_tab] = self.take_tab();
+        self.active_tab = index;
+        let tab = std::mem::take(&mut self.tabs[index]);
+        self.load_tab(tab);
+    }
+    fn cycle_tab(&mut self, forward: bool) {
+        let len = self.tabs.len();
+        if forward {
+            self.switch_tab((self.active_tab + 1) % len);
+        } else {
+            self.switch_tab((self.active_tab + len - 1) % len);
+        }
+    }
+    fn move_tab(&mut self, forward: bool) {
+        let target = if forward {
+            self.active_tab + 1
         } else {
-            if self.tabs.len() > 1 {
-                self.tabs.remove(self.active_tab);
-                if self.active_tab >= self.tabs.len() {
-                    self.active_tab = self.tabs.len() - 1;
+            self.active_tab.wrapping_sub(1)
+        };
+        if target < self.tabs.len() {
+            for split in &mut self.splits {
+                if split.tab_index == target {
+                    split.tab_index = self.active_tab;
+                } else if split.tab_index == self.active_tab {
+                    split.tab_index = target;
                 }
-            } else {
-                self.cleanup().unwrap_or(());
-                std::process::exit(0);
             }
+            self.tabs.swap(self.active_tab, target);
+            self.active_tab = target;
         }
     }
-    fn enter_directory(&mut self, path: PathBuf, new_depth: usize) -> std::io::Result<()> {
-        self.current_dir = path;
-        self.file_entries = Self::read_directory_with_depth(&self.current_dir, new_depth)?;
-        self.file_explorer_selection = 0;
-        Ok(())
+    fn split_pane(&mut self, is_horizontal: bool) {
+        let focused = &mut self.splits[self.focused_split];
+        let split = EditorSplit {
+            tab_index: self.active_tab,
+            size: focused.size,
+            is_horizontal: focused.is_horizontal,
+            cursor_position: self.cursor_position,
+            scroll_offset: self.scroll_offset,
+        };
+        focused.size = 50;
+        focused.is_horizontal = is_horizontal;
+        self.splits.insert(self.focused_split + 1, split);
+        self.focus_pane(self.focused_split + 1);
+    }
+    fn close_pane(&mut self) {
+        if self.splits.len() == 1 {
+            self.set_status_message("Only one pane open");
+            return;
+        }
+        self.splits.remove(self.focused_split);
+        self.focused_split = self.focused_split.min(self.splits.len() - 1);
+        let split = self.splits[self.focused_split].clone();
+        self.switch_tab(split.tab_index);
+        self.cursor_position = self.content.clamp(split.cursor_position);
+        self.scroll_offset = split.scroll_offset;
+    }
+    fn focus_pane(&mut self, index: usize) {
+        if index >= self.splits.len() {
+            return;
+        }
+        let focused = &mut self.splits[self.focused_split];
+        focused.cursor_position = self.cursor_position;
+        focused.scroll_offset = self.scroll_offset;
+        self.focused_split = index;
+        self.clear_selection();
+        self.extra_cursors.clear();
+        let split = self.splits[index].clone();
+        self.switch_tab(split.tab_index);
+        self.cursor_position = self.content.clamp(split.cursor_position);
+        self.scroll_offset = split.scroll_offset;
+    }
+    fn focus_direction(&mut self, dx: i32, dy: i32) {
+        let Some(&current) = self.pane_areas.get(self.focused_split) else {
+            return;
+        };
+        let center = |area: Rect| (area.x as i32 * 2 + area.width as i32, area.y as i32 * 2 + area.height as i32);
+        let (cx, cy) = center(current);
+        let target = self.pane_areas.iter()
+            .enumerate()
+            .filter(|&(i, _)| i != self.focused_split)
+            .filter_map(|(i, &area)| {
+                let (x, y) = center(area);
+                let along = (x - cx) * dx + (y - cy) * dy;
+                let across = ((x - cx) * dy).abs() + ((y - cy) * dx).abs();
+                (along > 0).then_some((i, along + across * 2))
+            })
+            .min_by_key(|&(_, distance)| distance)
+            .map(|(i, _)| i);
+        if let Some(index) = target {
+            self.focus_pane(index);
+        }
+    }
+    fn resize_pane(&mut self, delta: i16) {
+        if self.splits.len() == 1 {
+            return;
+        }
+        let (index, delta) = if self.focused_split + 1 == self.splits.len() {
+            (self.focused_split - 1, -delta)
+        } else {
+            (self.focused_split, delta)
+        };
+        let split = &mut self.splits[index];
+        split.size = (split.size as i16 + delta).clamp(10, 90) as u16;
+    }
+    fn find_tab(&self, path: &Path) -> Option<usize> {
+        let canonical = fs::canonicalize(path).ok();
+        let same_file = |filename: &Option<PathBuf>| match filename {
+            Some(filename) => filename == path || (canonical.is_some() && fs::canonicalize(filename).ok() == canonical),
+            None => false,
+        };
+        if same_file(&self.filename) {
+            return Some(self.active_tab);
+        }
+        self.tabs.iter().position(|tab| same_file(&tab.filename))
     }
     fn log_error(&self, error: &str) {
         if let Some(home) = env::var("HOME").ok().map(PathBuf::from) {
@@ -3403,9 +5787,9 @@ impl Editor {
             let _ = writeln!(file, "Popup: {:?}", self.popup_state);
             let _ = writeln!(file, "Mode: {:?}", self.mode);
             let _ = writeln!(file, "\nLast few lines of content:");
-            let start = self.content.len().saturating_sub(5);
-            for (i, line) in self.content[start..].iter().enumerate() {
-                let _ = writeln!(file, "{}: {}", start + i, line);
+            let start = self.content.len_lines().saturating_sub(5);
+            for line_idx in start..self.content.len_lines() {
+                let _ = writeln!(file, "{}: {}", line_idx, self.content.line(line_idx));
             }
         }
     }
@@ -3422,7 +5806,7 @@ impl Editor {
                 if let Ok(modified) = metadata.modified() {
                     if modified > self.last_modified.unwrap_or(SystemTime::now())
                         && modified != self.last_modified.unwrap_or(SystemTime::now())
-                        && self.last_save_time.map_or(true, |last_save| modified != last_save) {
+                        && self.last_save_time.is_none_or(|last_save| modified != last_save) {
                         self.popup_state = PopupType::FileChanged;
                         return Ok(());
                     }
@@ -3432,13 +5816,14 @@ impl Editor {
         Ok(())
     }
     fn reload_file(&mut self) -> std::io::Result<()> {
-        if let Some(path) = &self.filename {
-            let content = fs::read_to_string(path)?;
-            self.content = content.lines().map(String::from).collect();
-            if self.content.is_empty() {
-                self.content.push(String::new());
-            }
-            if let Ok(metadata) = fs::metadata(path) {
+        if let Some(path) = self.filename.clone() {
+            let bytes = fs::read(&path)?;
+            self.save_state();
+            self.content = TextBuffer::from_bytes_with_encoding(&bytes, self.content.encoding);
+            self.cursor_position = self.content.clamp(self.cursor_position);
+            self.save_state();
+            self.undo_tree.mark_saved();
+            if let Ok(metadata) = fs::metadata(&path) {
                 self.last_modified = metadata.modified().ok();
             }
             self.modified = false;
@@ -3467,21 +5852,20 @@ impl Editor {
         }
     }
     fn handle_tool_menu_selection(&mut self, selection: usize) {
-        match selection {
-            0 => self.delete_comments(),
-            1 => self.remove_empty_lines(),
-            2 => {
-                if let Err(e) = self.clear_cache() {
-                    self.set_status_message(format!("Error clearing cache: {}", e));
-                }
+        let tool = COMMANDS.iter()
+            .filter(|info| info.category == "Tools")
+            .nth(selection);
+        if let Some(info) = tool {
+            if let Err(e) = self.run_command(info.command) {
+                self.set_status_message(format!("{} failed: {}", info.name, e));
             }
-            _ => {}
         }
     }
     fn delete_comments(&mut self) {
+        self.save_state();
         let mut new_content = Vec::new();
         let mut new_cursor_position = self.cursor_position;
-        for (line_index, line) in self.content.iter().enumerate() {
+        for (line_index, line) in self.content.lines().enumerate() {
             let mut result = line.to_string();
             for comment_start in ["//"] {
                 if let Some(pos) = result.find(comment_start) {
@@ -3500,6 +5884,7 @@ impl Editor {
                     }
                     if !in_string {
                         result = result[..pos].trim_end().to_string();
+                        let pos = result.chars().count();
                         if line_index == self.cursor_position.1 && self.cursor_position.0 > pos {
                             new_cursor_position.0 = pos;
                         }
@@ -3510,6 +5895,7 @@ impl Editor {
                 if let Some(end) = result[start..].find("*/") {
                     let before = &result[..start];
                     let after = &result[start + end + 2..];
+                    let start = before.trim_end().chars().count();
                     result = format!("{}{}", before.trim_end(), after);
                     if line_index == self.cursor_position.1 && self.cursor_position.0 > start {
                         new_cursor_position.0 = start;
@@ -3520,91 +5906,625 @@ impl Editor {
             }
             new_content.push(result);
         }
-        self.content = new_content;
-        self.cursor_position = new_cursor_position;
+        self.content.set_text(&new_content.join("\n"));
+        self.cursor_position = self.content.clamp(new_cursor_position);
         self.modified = true;
         self.set_status_message("Comments deleted");
     }
-    fn draw_tool_menu(&mut self, frame: &mut Frame) {
-        let area = Rect::new(
-            frame.size().width / 3,
-            frame.size().height / 3,
-            frame.size().width / 3,
-            frame.size().height / 3,
-        );
-        frame.render_widget(Clear, area);
-        let popup_block = Block::default()
-            .title(" Tools ")
-            .title_alignment(Alignment::Center)
-            .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::Cyan));
-        let inner_area = popup_block.inner(area);
-        frame.render_widget(popup_block, area);
-        let tools = vec![
-            ("  ", "Delete Comments", "Remove all comments from file"),
-            ("  ", "Clear Cache", "Clear editor history and cache files"),
-        ];
-        let text: Vec<Line> = tools.iter().enumerate().map(|(i, (icon, name, desc))| {
-            let style = if i == self.tool_menu_selection {
-                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+    fn start_query_replace(&mut self) {
+        let regex = match self.search_regex() {
+            Ok(regex) => regex,
+            Err(e) => {
+                let message = Self::describe_regex_error(e);
+                self.search_error = Some(message.clone());
+                self.set_status_message(format!("Invalid pattern: {}", message));
+                return;
+            }
+        };
+        let text = self.content.cached_text();
+        let bounds = self.selection_range()
+            .map(|(start, end)| (self.content.byte_index(start), self.content.byte_index(end)));
+        self.query_replace_matches = regex.find_iter(&text)
+            .filter(|found| !found.is_empty())
+            .filter(|found| bounds.is_none_or(|(start, end)| start <= found.start() && found.end() <= end))
+            .map(|found| QueryReplaceMatch {
+                start: self.content.char_index(self.content.byte_position(found.start())),
+                original: found.as_str().to_string(),
+                replacement: self.replacement_at(&regex, &text, found.start()),
+                pending: true,
+            })
+            .collect();
+        if self.query_replace_matches.is_empty() {
+            self.popup_state = PopupType::None;
+            self.set_status_message("No matches found.");
+            return;
+        }
+        self.save_state();
+        self.clear_selection();
+        let cursor = self.content.char_index(self.cursor_position);
+        self.current_match_index = self.query_replace_matches.iter()
+            .position(|m| m.start >= cursor)
+            .unwrap_or(0);
+        self.query_replace_count = 0;
+        self.swap_query_replace(self.current_match_index, true);
+        self.popup_state = PopupType::QueryReplace;
+    }
+    fn swap_query_replace(&mut self, index: usize, apply: bool) {
+        let entry = &self.query_replace_matches[index];
+        let (from, to) = if apply {
+            (&entry.original, &entry.replacement)
+        } else {
+            (&entry.replacement, &entry.original)
+        };
+        let start = entry.start;
+        let end = self.content.position(start + from.chars().count());
+        let delta = to.chars().count() as isize - from.chars().count() as isize;
+        let to = to.clone();
+        let start_position = self.content.position(start);
+        self.content.remove(start_position, end);
+        self.content.insert(start_position, &to);
+        for entry in self.query_replace_matches.iter_mut().filter(|entry| entry.start > start) {
+            entry.start = (entry.start as isize + delta) as usize;
+        }
+        self.cursor_position = start_position;
+        self.highlighted_matches = self.query_replace_matches.iter()
+            .filter(|entry| entry.pending && entry.start != start)
+            .map(|entry| (
+                self.content.position(entry.start),
+                self.content.position(entry.start + entry.original.chars().count()),
+            ))
+            .collect();
+    }
+    fn answer_query_replace(&mut self, accept: bool) {
+        let index = self.current_match_index;
+        self.query_replace_matches[index].pending = false;
+        if accept {
+            self.query_replace_count += 1;
+        } else {
+            self.swap_query_replace(index, false);
+        }
+        let len = self.query_replace_matches.len();
+        match (1..len).map(|offset| (index + offset) % len).find(|&next| self.query_replace_matches[next].pending) {
+            Some(next) => {
+                self.current_match_index = next;
+                self.swap_query_replace(next, true);
+            }
+            None => self.finish_query_replace(),
+        }
+    }
+    fn query_replace_preview(&self) -> Option<TextRange> {
+        if self.popup_state != PopupType::QueryReplace {
+            return None;
+        }
+        let entry = self.query_replace_matches.get(self.current_match_index)?;
+        Some((
+            self.content.position(entry.start),
+            self.content.position(entry.start + entry.replacement.chars().count()),
+        ))
+    }
+    fn finish_query_replace(&mut self) {
+        let total = self.query_replace_matches.len();
+        self.query_replace_matches.clear();
+        self.highlighted_matches.clear();
+        self.popup_state = PopupType::None;
+        if self.query_replace_count > 0 {
+            self.modified = true;
+        }
+        self.save_state();
+        self.set_status_message(format!("Replaced {} of {} occurrences", self.query_replace_count, total));
+    }
+    fn for_each_cursor<F: FnMut(&mut Self)>(&mut self, mut action: F) {
+        if self.extra_cursors.is_empty() {
+            action(self);
+            return;
+        }
+        let mut cursors: Vec<usize> = std::iter::once(self.cursor_position)
+            .chain(self.extra_cursors.iter().copied())
+            .map(|pos| self.content.char_index(pos))
+            .collect();
+        let mut order: Vec<usize> = (0..cursors.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(cursors[i]));
+        order.dedup_by_key(|i| cursors[*i]);
+        for i in order {
+            let start = cursors[i];
+            let len_before = self.content.len_chars();
+            self.cursor_position = self.content.position(start);
+            action(self);
+            let delta = self.content.len_chars() as isize - len_before as isize;
+            for (j, cursor) in cursors.iter_mut().enumerate() {
+                if j != i && *cursor > start {
+                    *cursor = (*cursor as isize + delta).max(start as isize) as usize;
+                }
+            }
+            cursors[i] = self.content.char_index(self.cursor_position);
+        }
+        self.cursor_position = self.content.position(cursors[0]);
+        let mut extra: Vec<(usize, usize)> = Vec::new();
+        for &cursor in &cursors[1..] {
+            let pos = self.content.position(cursor);
+            if pos != self.cursor_position && !extra.contains(&pos) {
+                extra.push(pos);
+            }
+        }
+        self.extra_cursors = extra;
+    }
+    fn move_cursor(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Left => self.handle_left_key(),
+            KeyCode::Right => self.handle_right_key(),
+            KeyCode::Up if self.cursor_position.1 > 0 => self.set_cursor_line(self.cursor_position.1 - 1),
+            KeyCode::Down if self.cursor_position.1 + 1 < self.content.len_lines() => {
+                self.set_cursor_line(self.cursor_position.1 + 1)
+            }
+            KeyCode::Home => self.cursor_position.0 = 0,
+            KeyCode::End => self.cursor_position.0 = self.content.line_len(self.cursor_position.1),
+            _ => {}
+        }
+    }
+    fn backspace(&mut self, count: usize) {
+        self.cursor_position = self.content.clamp(self.cursor_position);
+        for _ in 0..count {
+            if self.cursor_position.0 > 0 {
+                let start = (self.content.prev_grapheme(self.cursor_position), self.cursor_position.1);
+                self.content.remove(start, self.cursor_position);
+                self.cursor_position = start;
+                self.modified = true;
+            } else if self.cursor_position.1 > 0 {
+                let start = (self.content.line_len(self.cursor_position.1 - 1), self.cursor_position.1 - 1);
+                self.content.remove(start, self.cursor_position);
+                self.cursor_position = start;
+                self.modified = true;
+            }
+        }
+    }
+    fn delete_forward(&mut self) {
+        self.cursor_position = self.content.clamp(self.cursor_position);
+        let end = if self.cursor_position.0 < self.content.line_len(self.cursor_position.1) {
+            (self.content.next_grapheme(self.cursor_position), self.cursor_position.1)
+        } else {
+            (0, self.cursor_position.1 + 1)
+        };
+        if self.content.clamp(end) != self.cursor_position {
+            self.content.remove(self.cursor_position, end);
+            self.modified = true;
+        }
+    }
+    fn add_cursor(&mut self, pos: (usize, usize)) {
+        if pos != self.cursor_position && !self.extra_cursors.contains(&pos) {
+            self.extra_cursors.push(pos);
+        }
+    }
+    fn add_cursor_vertical(&mut self, below: bool) {
+        self.clear_selection();
+        let edge = self.extra_cursors.iter()
+            .copied()
+            .chain(std::iter::once(self.cursor_position))
+            .reduce(|a, b| if (b.1 > a.1) == below { b } else { a })
+            .unwrap_or(self.cursor_position);
+        let line = if below {
+            if edge.1 + 1 >= self.content.len_lines() {
+                return;
+            }
+            edge.1 + 1
+        } else {
+            match edge.1.checked_sub(1) {
+                Some(line) => line,
+                None => return,
+            }
+        };
+        let display = self.content.display_col(self.cursor_position, self.tab_width());
+        self.add_cursor((self.content.col_for_display(line, display, self.tab_width()), line));
+        self.set_status_message(format!("{} cursors", self.extra_cursors.len() + 1));
+    }
+    fn add_cursor_at_next_occurrence(&mut self) {
+        self.clear_selection();
+        let line: Vec<char> = self.content.line(self.cursor_position.1).chars().collect();
+        let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+        let col = self.cursor_position.0.min(line.len());
+        let start = col - line[..col].iter().rev().take_while(|c| is_word(c)).count();
+        let end = col + line[col..].iter().take_while(|c| is_word(c)).count();
+        if start == end {
+            self.set_status_message("No word under cursor");
+            return;
+        }
+        let word: String = line[start..end].iter().collect();
+        let offset = col - start;
+        let word_len = end - start;
+        let occurrences: Vec<(usize, usize)> = self.content.find_all(&word)
+            .into_iter()
+            .filter(|&(line_idx, col)| {
+                let line: Vec<char> = self.content.line(line_idx).chars().collect();
+                (col == 0 || !is_word(&line[col - 1])) && !line.get(col + word_len).is_some_and(is_word)
+            })
+            .map(|(line_idx, col)| (col + offset, line_idx))
+            .collect();
+        let last = self.extra_cursors.last().copied().unwrap_or(self.cursor_position);
+        let next = occurrences.iter()
+            .chain(occurrences.iter())
+            .skip_while(|pos| (pos.1, pos.0) <= (last.1, last.0))
+            .find(|&&pos| pos != self.cursor_position && !self.extra_cursors.contains(&pos))
+            .copied();
+        match next {
+            Some(pos) => {
+                self.add_cursor(pos);
+                self.set_status_message(format!("{} cursors", self.extra_cursors.len() + 1));
+            }
+            None => self.set_status_message(format!("No more occurrences of '{}'", word)),
+        }
+    }
+    fn add_cursors_at_matches(&mut self) {
+        if self.highlighted_matches.is_empty() {
+            self.set_status_message("No search matches");
+            return;
+        }
+        self.clear_selection();
+        let matches: Vec<(usize, usize)> = self.highlighted_matches.iter()
+            .map(|&(_, end)| end)
+            .collect();
+        self.cursor_position = matches[0];
+        self.extra_cursors.clear();
+        for pos in matches.into_iter().skip(1) {
+            self.add_cursor(pos);
+        }
+        self.highlighted_matches.clear();
+        self.set_status_message(format!("{} cursors", self.extra_cursors.len() + 1));
+    }
+    fn block_range(&self) -> Option<(usize, usize, usize, usize)> {
+        if self.mode != EditorMode::VisualBlock {
+            return None;
+        }
+        let anchor = self.content.clamp(self.selection_anchor?);
+        let cursor = self.content.clamp(self.cursor_position);
+        let anchor_display = self.content.display_col(anchor, self.tab_width());
+        let cursor_display = self.content.display_col(cursor, self.tab_width());
+        Some((
+            anchor.1.min(cursor.1),
+            anchor.1.max(cursor.1),
+            anchor_display.min(cursor_display),
+            anchor_display.max(cursor_display),
+        ))
+    }
+    fn block_columns(&self, line_idx: usize, left: usize, right: usize) -> (usize, usize) {
+        (
+            self.content.col_at_or_after_display(line_idx, left, self.tab_width()),
+            self.content.col_at_or_after_display(line_idx, right, self.tab_width()),
+        )
+    }
+    fn block_text(&self) -> Vec<String> {
+        let Some((first, last, left, right)) = self.block_range() else {
+            return Vec::new();
+        };
+        (first..=last)
+            .map(|line_idx| {
+                let (start, end) = self.block_columns(line_idx, left, right);
+                self.content.text_range((start, line_idx), (end, line_idx))
+            })
+            .collect()
+    }
+    fn delete_block(&mut self) {
+        let Some((first, last, left, right)) = self.block_range() else {
+            return;
+        };
+        for line_idx in first..=last {
+            let (start, end) = self.block_columns(line_idx, left, right);
+            self.content.remove((start, line_idx), (end, line_idx));
+        }
+        self.cursor_position = (self.block_columns(self.cursor_position.1, left, left).0, self.cursor_position.1);
+        self.clear_selection();
+        self.modified = true;
+    }
+    fn pad_to_display(&mut self, line_idx: usize, display: usize) -> usize {
+        let line_len = self.content.line_len(line_idx);
+        let width = self.content.display_col((line_len, line_idx), self.tab_width());
+        if width < display {
+            self.content.insert((line_len, line_idx), &" ".repeat(display - width));
+            self.modified = true;
+        }
+        self.content.col_at_or_after_display(line_idx, display, self.tab_width())
+    }
+    fn block_to_cursors(&mut self) {
+        let Some((first, last, left, _)) = self.block_range() else {
+            return;
+        };
+        let cursor_line = self.cursor_position.1;
+        self.delete_block();
+        self.extra_cursors.clear();
+        for line_idx in first..=last {
+            let pos = (self.pad_to_display(line_idx, left), line_idx);
+            if line_idx == cursor_line {
+                self.cursor_position = pos;
             } else {
-                Style::default().fg(Color::White)
-            };
-            Line::from(vec![
-                Span::raw(format!(" {} ", icon)),
-                Span::styled(*name, style),
-                Span::raw(" - "),
-                Span::styled(*desc, Style::default().fg(Color::Gray))
-            ])
-        }).collect();
-        let paragraph = Paragraph::new(text)
-            .alignment(Alignment::Left);
-        frame.render_widget(paragraph, inner_area);
-    }
-    fn replace_all(&mut self) {
-        for line in &mut self.content {
-            *line = line.replace(&self.search_query, &self.replace_text);
+                self.extra_cursors.push(pos);
+            }
+        }
+    }
+    fn block_clipboard_matches(&self) -> bool {
+        let Some(rows) = &self.block_clipboard else {
+            return false;
+        };
+        match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+            Ok(text) => text == rows.join("\n"),
+            Err(_) => true,
+        }
+    }
+    fn paste_block(&mut self) {
+        let Some(rows) = self.block_clipboard.clone() else {
+            return;
+        };
+        let display = self.content.display_col(self.cursor_position, self.tab_width());
+        let top = self.cursor_position.1;
+        for (i, row) in rows.iter().enumerate() {
+            let line_idx = top + i;
+            if line_idx >= self.content.len_lines() {
+                let last = self.content.len_lines() - 1;
+                self.content.insert((self.content.line_len(last), last), "\n");
+            }
+            let col = self.pad_to_display(line_idx, display);
+            self.content.insert((col, line_idx), row);
+        }
+        self.modified = true;
+        self.set_status_message(format!("Pasted block of {} lines", rows.len()));
+    }
+    fn copy_block(&mut self) {
+        let rows = self.block_text();
+        let result = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(rows.join("\n")));
+        self.block_clipboard = Some(rows);
+        if let Err(e) = result {
+            self.log_error(&format!("Clipboard unavailable, block kept internally: {}", e));
+        }
+    }
+    fn handle_block_key(&mut self, key: &KeyEvent) -> bool {
+        let plain = !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => {
+                self.copy_block();
+                self.clear_selection();
+                self.set_status_message("Block copied");
+            }
+            KeyCode::Char('x') if key.modifiers == KeyModifiers::CONTROL => {
+                self.copy_block();
+                self.save_state();
+                self.delete_block();
+                self.set_status_message("Block cut");
+            }
+            KeyCode::Char('v') if key.modifiers == KeyModifiers::CONTROL => {
+                self.save_state();
+                if let Some((first, _, left, _)) = self.block_range() {
+                    self.delete_block();
+                    self.cursor_position = (self.content.col_at_or_after_display(first, left, self.tab_width()), first);
+                }
+                self.paste_block();
+            }
+            KeyCode::Backspace | KeyCode::Delete => {
+                self.save_state();
+                match self.block_range() {
+                    Some((_, _, left, right)) if left < right => self.delete_block(),
+                    _ => {
+                        self.block_to_cursors();
+                        if key.code == KeyCode::Backspace {
+                            self.for_each_cursor(|editor| editor.backspace(1));
+                        } else {
+                            self.for_each_cursor(Self::delete_forward);
+                        }
+                    }
+                }
+            }
+            KeyCode::Char(c) if plain => {
+                self.save_state();
+                self.block_to_cursors();
+                self.for_each_cursor(|editor| editor.safe_insert_char(c));
+            }
+            KeyCode::Tab if plain => {
+                self.save_state();
+                self.block_to_cursors();
+                let indent = self.indent_unit();
+                self.for_each_cursor(|editor| editor.insert_and_move_cursor(&indent, indent.chars().count()));
+            }
+            KeyCode::Esc => self.clear_selection(),
+            _ => return false,
+        }
+        true
+    }
+    fn selection_range(&self) -> Option<((usize, usize), (usize, usize))> {
+        if self.mode == EditorMode::VisualBlock {
+            return None;
+        }
+        let anchor = self.content.clamp(self.selection_anchor?);
+        let cursor = self.content.clamp(self.cursor_position);
+        match (anchor.1, anchor.0).cmp(&(cursor.1, cursor.0)) {
+            std::cmp::Ordering::Less => Some((anchor, cursor)),
+            std::cmp::Ordering::Greater => Some((cursor, anchor)),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+    fn selected_text(&self) -> String {
+        self.selection_range()
+            .map(|(start, end)| self.content.text_range(start, end))
+            .unwrap_or_default()
+    }
+    fn clear_selection(&mut self) {
+        self.selection_anchor = None;
+        if matches!(self.mode, EditorMode::Visual | EditorMode::VisualBlock) {
+            self.mode = EditorMode::Normal;
         }
+    }
+    fn delete_selection(&mut self) -> bool {
+        let Some((start, end)) = self.selection_range() else {
+            self.clear_selection();
+            return false;
+        };
+        self.content.remove(start, end);
+        self.cursor_position = start;
+        self.clear_selection();
         self.modified = true;
-        self.set_status_message("Replacement completed.");
+        true
+    }
+    fn update_selection_for_key(&mut self, key: &KeyEvent) {
+        let is_movement = matches!(
+            key.code,
+            KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down
+                | KeyCode::Home | KeyCode::End | KeyCode::PageUp | KeyCode::PageDown
+        ) && !key.modifiers.contains(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if !is_movement {
+            return;
+        }
+        if key.modifiers.contains(KeyModifiers::SHIFT) || matches!(self.mode, EditorMode::Visual | EditorMode::VisualBlock) {
+            if self.selection_anchor.is_none() {
+                self.selection_anchor = Some(self.cursor_position);
+            }
+        } else {
+            self.selection_anchor = None;
+        }
+    }
+    fn select_line(&mut self) {
+        let line = self.cursor_position.1;
+        self.selection_anchor = Some((0, line));
+        self.cursor_position = if line + 1 < self.content.len_lines() {
+            (0, line + 1)
+        } else {
+            (self.content.line_len(line), line)
+        };
+    }
+    fn select_word(&mut self) {
+        let line: Vec<char> = self.content.line(self.cursor_position.1).chars().collect();
+        let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+        let col = self.cursor_position.0.min(line.len());
+        let start = col - line[..col].iter().rev().take_while(|c| is_word(c)).count();
+        let end = col + line[col..].iter().take_while(|c| is_word(c)).count();
+        if start < end {
+            self.selection_anchor = Some((start, self.cursor_position.1));
+            self.cursor_position.0 = end;
+        }
+    }
+    fn node_byte_range(&self) -> std::ops::Range<usize> {
+        if let Some((start, end)) = self.selection_range() {
+            return self.content.byte_index(start)..self.content.byte_index(end);
+        }
+        let (col, line_idx) = self.content.clamp(self.cursor_position);
+        let indent = self.content.line(line_idx).chars().take_while(|c| c.is_whitespace()).count();
+        let byte = self.content.byte_index((col.max(indent), line_idx));
+        byte..byte
+    }
+    fn expand_selection(&mut self) {
+        let Some(highlighter) = self.highlighter.as_ref() else {
+            self.set_status_message("No syntax tree for this file");
+            return;
+        };
+        let current = (self.selection_anchor.unwrap_or(self.cursor_position), self.cursor_position);
+        let range = match self.selection_range() {
+            Some((start, end)) => self.content.byte_index(start)..self.content.byte_index(end),
+            None => {
+                let byte = self.content.byte_index(self.cursor_position);
+                byte..byte
+            }
+        };
+        let Some(expanded) = highlighter.expand_range(range) else {
+            return;
+        };
+        let expanded = (self.content.byte_position(expanded.start), self.content.byte_position(expanded.end));
+        if self.syntax_selections.last().is_some_and(|&(_, last)| last != current) {
+            self.syntax_selections.clear();
+        }
+        self.syntax_selections.push((current, expanded));
+        self.selection_anchor = Some(expanded.0);
+        self.cursor_position = expanded.1;
+    }
+    fn shrink_selection(&mut self) {
+        let current = (self.selection_anchor.unwrap_or(self.cursor_position), self.cursor_position);
+        match self.syntax_selections.pop() {
+            Some((previous, expanded)) if expanded == current => {
+                self.selection_anchor = (previous.0 != previous.1).then_some(previous.0);
+                self.cursor_position = previous.1;
+            }
+            _ => {
+                self.syntax_selections.clear();
+                self.set_status_message("Nothing to shrink");
+            }
+        }
+    }
+    fn jump_to_node(&mut self, target: impl Fn(&Highlighter, std::ops::Range<usize>) -> Option<usize>) {
+        let Some(highlighter) = self.highlighter.as_ref() else {
+            self.set_status_message("No syntax tree for this file");
+            return;
+        };
+        match target(highlighter, self.node_byte_range()) {
+            Some(byte) => {
+                self.clear_selection();
+                self.cursor_position = self.content.byte_position(byte);
+            }
+            None => self.set_status_message("No node in that direction"),
+        }
     }
-    fn replace_current(&mut self) {
-        if let Some((line_index, col_index)) = self.highlighted_matches.get(self.current_match_index) {
-            let line = &mut self.content[*line_index];
-            line.replace_range(*col_index..*col_index + self.search_query.len(), &self.replace_text);
-            self.set_status_message(format!("Replaced occurrence at line {}.", line_index + 1));
+    fn indent_selection(&mut self, outdent: bool) {
+        let (start, end) = self.selection_range()
+            .unwrap_or((self.cursor_position, self.cursor_position));
+        let last_line = if end.1 > start.1 && end.0 == 0 { end.1 - 1 } else { end.1 };
+        self.save_state();
+        let indent = self.indent_unit();
+        let tab_width = self.tab_width();
+        let mut shifts = Vec::new();
+        for line_idx in start.1..=last_line {
+            if outdent {
+                let line = self.content.line(line_idx);
+                let width = if line.starts_with('\t') {
+                    1
+                } else {
+                    line.chars().take(tab_width).take_while(|&c| c == ' ').count()
+                };
+                self.content.remove((0, line_idx), (width, line_idx));
+                shifts.push((line_idx, -(width as isize)));
+            } else {
+                self.content.insert((0, line_idx), &indent);
+                shifts.push((line_idx, indent.chars().count() as isize));
+            }
+        }
+        for pos in std::iter::once(&mut self.cursor_position).chain(self.selection_anchor.as_mut()) {
+            if let Some(&(_, delta)) = shifts.iter().find(|(line, _)| *line == pos.1) {
+                pos.0 = (pos.0 as isize + delta).max(0) as usize;
+            }
         }
+        self.modified = true;
     }
     fn save_state(&mut self) {
-        let now = Instant::now();
-        let current_file = self.filename.clone();
-        let current_line = self.cursor_position.1;
-        let old_line = self.last_save_state.as_ref()
-            .and_then(|state| state.get(current_line))
-            .cloned()
-            .unwrap_or_default();
-        let new_line = self.content.get(current_line)
-            .cloned()
-            .unwrap_or_default();
-        if old_line != new_line {
-            let delta = MultiLineDelta {
-                start_line: current_line,
-                old_lines: vec![old_line],
-                new_lines: vec![new_line],
-                cursor_before: self.cursor_position,
+        self.typing_group = None;
+        let Some(base) = &self.last_save_state else {
+            self.reset_undo_base();
+            return;
+        };
+        let line_endings = (base.line_endings() != self.content.line_endings())
+            .then(|| (base.line_endings(), self.content.line_endings()));
+        let change = self.content.changes_since(base)
+            .or_else(|| line_endings.map(|_| (0, Vec::new(), Vec::new())));
+        if let Some((start_line, old_lines, new_lines)) = change {
+            self.undo_tree.push(MultiLineDelta {
+                start_line,
+                old_lines,
+                new_lines,
+                cursor_before: self.last_save_cursor,
                 cursor_after: self.cursor_position,
-                timestamp: now,
-                file_id: current_file.clone(),
-            };
-            self.undo_stack.push((self.content.clone(), self.cursor_position));
-            while self.undo_stack.len() > 10000 {
-                self.undo_stack.remove(0);
-            }
-            self.redo_stack.retain(|(state, _)| state != &self.content);
-            self.last_save_state = Some(self.content.clone());
-            self.last_edit_time = now;
+                line_endings,
+            });
+        }
+        self.reset_undo_base();
+    }
+    fn save_typing_state(&mut self, c: char) {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let continues_group = match self.typing_group {
+            Some((previous, position)) => position == self.cursor_position
+                && self.selection_anchor.is_none()
+                && self.last_edit_time.elapsed() < Duration::from_secs(1)
+                && (is_word(previous) || !is_word(c)),
+            None => false,
+        };
+        if !continues_group {
+            self.save_state();
         }
     }
+    fn reset_undo_base(&mut self) {
+        self.last_save_state = Some(self.content.clone());
+        self.last_save_cursor = self.cursor_position;
+        self.typing_group = None;
+    }
     fn create_new_file(&mut self) -> std::io::Result<()> {
         if !self.temp_filename.is_empty() {
             let path = self.current_dir.join(&self.temp_filename);
@@ -3654,15 +6574,101 @@ impl Editor {
                 fs::remove_dir_all(&logs_dir)?;
                 fs::create_dir(&logs_dir)?;
             }
+            let undo_dir = config_dir.join("undo");
+            if undo_dir.exists() {
+                fs::remove_dir_all(&undo_dir)?;
+            }
+            let symbols_dir = config_dir.join("symbols");
+            if symbols_dir.exists() {
+                fs::remove_dir_all(&symbols_dir)?;
+            }
+        }
+        self.symbol_index = None;
+        self.symbol_index_receiver = None;
+        self.undo_tree = UndoTree::default();
+        if self.modified {
+            self.undo_tree.forget_saved();
         }
-        self.undo_stack.clear();
-        self.redo_stack.clear();
         self.last_save_state = None;
         self.set_status_message("Cache cleared");
         Ok(())
     }
+    fn open_encoding_select(&mut self, action: EncodingAction) {
+        self.encoding_selection = ENCODINGS.iter()
+            .position(|label| encoding_for_label(label) == self.content.encoding)
+            .unwrap_or(0);
+        self.popup_state = PopupType::EncodingSelect(action);
+    }
+    fn handle_encoding_selection(&mut self, action: EncodingAction) -> std::io::Result<()> {
+        let encoding = encoding_for_label(ENCODINGS[self.encoding_selection]);
+        match action {
+            EncodingAction::Reopen => {
+                let Some(path) = self.filename.clone() else {
+                    self.set_status_message("No file to reopen");
+                    return Ok(());
+                };
+                if self.modified {
+                    self.set_status_message("Save or discard changes before reopening");
+                    return Ok(());
+                }
+                match fs::read(&path) {
+                    Ok(bytes) => {
+                        self.content = TextBuffer::from_bytes_with_encoding(&bytes, encoding);
+                        self.cursor_position = self.content.clamp(self.cursor_position);
+                        self.undo_tree = UndoTree::default();
+                        self.reset_undo_base();
+                        match Self::load_warning(&self.content) {
+                            Some(warning) => self.set_status_message(warning),
+                            None => self.set_status_message(format!("Reopened as {}", encoding.name())),
+                        }
+                    }
+                    Err(e) => self.set_status_message(format!("Error opening file: {}", e)),
+                }
+            }
+            EncodingAction::Save => {
+                if !self.content.can_encode(encoding) {
+                    self.set_status_message(format!("Text cannot be represented in {}", encoding.name()));
+                    return Ok(());
+                }
+                self.content.set_encoding(encoding);
+                self.modified = true;
+                self.save()?;
+            }
+        }
+        Ok(())
+    }
+    fn convert_line_endings(&mut self) {
+        self.save_state();
+        if self.content.mixed_line_endings {
+            self.content.unify_line_endings();
+            self.cursor_position = self.content.clamp(self.cursor_position);
+        } else {
+            self.content.line_ending = self.content.line_ending.toggled();
+        }
+        self.save_state();
+        self.modified = !self.undo_tree.is_saved();
+        self.set_status_message(format!("Line endings set to {}", self.content.line_ending.label()));
+    }
+    fn transliterate_to_ascii(&mut self) {
+        let text = self.content.text();
+        if text.is_ascii() {
+            self.set_status_message("File is already ASCII");
+            return;
+        }
+        self.save_state();
+        self.content.set_text(&deunicode(&text));
+        self.cursor_position = self.content.clamp(self.cursor_position);
+        self.modified = true;
+        self.set_status_message("Transliterated to ASCII");
+    }
     fn remove_empty_lines(&mut self) {
-        self.content.retain(|line| !line.trim().is_empty());
+        self.save_state();
+        let lines: Vec<String> = self.content.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.into_owned())
+            .collect();
+        self.content.set_text(&lines.join("\n"));
+        self.cursor_position = self.content.clamp(self.cursor_position);
         self.modified = true;
         self.set_status_message("Empty lines removed");
     }
@@ -0,0 +1,215 @@
+use crate::buffer::TextBuffer;
+use ignore::WalkBuilder;
+use regex::Regex;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+const MAX_HITS: usize = 10000;
+const MAX_PREVIEW_CHARS: usize = 200;
+const SKIPPED_DIRS: [&str; 3] = [".git", "target", "node_modules"];
+
+#[derive(Clone)]
+pub struct SearchHit {
+    pub path: PathBuf,
+    pub line: usize,
+    pub col: usize,
+    pub len: usize,
+    pub preview: String,
+    pub preview_col: usize,
+}
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(8000).any(|&byte| byte == 0)
+}
+pub fn project_files(root: &Path) -> impl Iterator<Item = PathBuf> {
+    WalkBuilder::new(root)
+        .hidden(false)
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(|entry| {
+            !(entry.file_type().is_some_and(|file_type| file_type.is_dir())
+                && SKIPPED_DIRS.iter().any(|name| entry.file_name() == *name))
+        })
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|file_type| file_type.is_file()))
+        .map(|entry| entry.into_path())
+}
+pub fn search_file(path: &Path, regex: &Regex) -> Vec<SearchHit> {
+    let Ok(bytes) = fs::read(path) else {
+        return Vec::new();
+    };
+    if is_binary(&bytes) {
+        return Vec::new();
+    }
+    let content = TextBuffer::from_bytes(&bytes);
+    let mut hits = Vec::new();
+    for (line_idx, line) in content.lines().enumerate() {
+        for found in regex.find_iter(&line).filter(|found| !found.is_empty()) {
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+            let col = line[..found.start()].chars().count();
+            hits.push(SearchHit {
+                path: path.to_path_buf(),
+                line: line_idx,
+                col,
+                len: found.as_str().chars().count(),
+                preview: trimmed.chars().take(MAX_PREVIEW_CHARS).collect(),
+                preview_col: col.saturating_sub(line[..indent].chars().count()),
+            });
+        }
+    }
+    hits
+}
+pub fn spawn_search(root: PathBuf, regex: Regex) -> Receiver<SearchHit> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut sent = 0;
+        for path in project_files(&root) {
+            for hit in search_file(&path, &regex) {
+                if sent >= MAX_HITS || sender.send(hit).is_err() {
+                    return;
+                }
+                sent += 1;
+            }
+        }
+    });
+    receiver
+}
+pub fn spawn_file_index(root: PathBuf) -> Receiver<(PathBuf, Vec<PathBuf>)> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let files = project_files(&root).collect();
+        let _ = sender.send((root, files));
+    });
+    receiver
+}
+pub fn expand_replacement(regex: &Regex, line: &str, byte: usize, template: &str, expand: bool) -> Option<(usize, String)> {
+    let captures = regex.captures_at(line, byte)?;
+    let found = captures.get(0)?;
+    if found.start() != byte {
+        return None;
+    }
+    let mut replacement = String::new();
+    if expand {
+        captures.expand(template, &mut replacement);
+    } else {
+        replacement.push_str(template);
+    }
+    Some((found.end(), replacement))
+}
+pub fn replace_hits(content: &mut TextBuffer, hits: &[&SearchHit], regex: &Regex, template: &str, expand: bool) -> usize {
+    let mut hits = hits.to_vec();
+    hits.sort_by_key(|hit| (hit.line, hit.col));
+    let mut applied = 0;
+    for hit in hits.into_iter().rev() {
+        if hit.line >= content.len_lines() {
+            continue;
+        }
+        let line = content.line(hit.line).into_owned();
+        let Some((byte, _)) = line.char_indices().nth(hit.col) else {
+            continue;
+        };
+        let Some((end, replacement)) = expand_replacement(regex, &line, byte, template, expand) else {
+            continue;
+        };
+        if line[byte..end].chars().count() != hit.len {
+            continue;
+        }
+        content.remove((hit.col, hit.line), (hit.col + hit.len, hit.line));
+        content.insert((hit.col, hit.line), &replacement);
+        applied += 1;
+    }
+    applied
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(line: usize, col: usize, len: usize) -> SearchHit {
+        SearchHit {
+            path: PathBuf::from("test.txt"),
+            line,
+            col,
+            len,
+            preview: String::new(),
+            preview_col: 0,
+        }
+    }
+
+    #[test]
+    fn replace_hits_applies_all_hits_on_a_line() {
+        let mut content = TextBuffer::from_text("foo foo\nbar foo");
+        let regex = Regex::new("foo").unwrap();
+        let hits = [hit(0, 0, 3), hit(0, 4, 3), hit(1, 4, 3)];
+        let applied = replace_hits(&mut content, &hits.iter().collect::<Vec<_>>(), &regex, "quux", false);
+        assert_eq!(applied, 3);
+        assert_eq!(content.text(), "quux quux\nbar quux");
+    }
+    #[test]
+    fn replace_hits_skips_stale_hits() {
+        let mut content = TextBuffer::from_text("xfoo\nfooo\nfoo");
+        let regex = Regex::new("fo+").unwrap();
+        let hits = [hit(0, 0, 3), hit(1, 0, 3), hit(2, 0, 3), hit(5, 0, 3), hit(2, 9, 3)];
+        let applied = replace_hits(&mut content, &hits.iter().collect::<Vec<_>>(), &regex, "bar", false);
+        assert_eq!(applied, 1);
+        assert_eq!(content.text(), "xfoo\nfooo\nbar");
+    }
+    #[test]
+    fn replace_hits_expands_groups() {
+        let mut content = TextBuffer::from_text("let a = 1;\nlet b = 2;");
+        let regex = Regex::new(r"let (?<name>\w+)").unwrap();
+        let hits = [hit(0, 0, 5), hit(1, 0, 5)];
+        let hits: Vec<_> = hits.iter().collect();
+        replace_hits(&mut content, &hits, &regex, "const ${name}", true);
+        assert_eq!(content.text(), "const a = 1;\nconst b = 2;");
+        let mut literal = TextBuffer::from_text("let a");
+        replace_hits(&mut literal, &hits[..1], &regex, "$1", false);
+        assert_eq!(literal.text(), "$1");
+    }
+    #[test]
+    fn replace_hits_counts_chars_not_bytes() {
+        let mut content = TextBuffer::from_text("h\u{e9} caf\u{e9}");
+        let regex = Regex::new("caf\u{e9}").unwrap();
+        let applied = replace_hits(&mut content, &[&hit(0, 3, 4)], &regex, "tea", false);
+        assert_eq!(applied, 1);
+        assert_eq!(content.text(), "h\u{e9} tea");
+    }
+    #[test]
+    fn project_files_include_hidden_and_skip_ignored() {
+        let root = std::env::temp_dir().join(format!("red-search-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in [".git", ".github", "target", "build"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [".gitignore", ".env", ".git/config", ".github/ci.yml", "target/out", "build/out", "main.rs"] {
+            fs::write(root.join(file), "build/\n").unwrap();
+        }
+        let files: Vec<_> = project_files(&root)
+            .map(|path| path.strip_prefix(&root).unwrap().to_string_lossy().into_owned())
+            .collect();
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(files, vec![".env", ".github/ci.yml", ".gitignore", "main.rs"]);
+    }
+    #[test]
+    fn file_index_is_built_in_the_background() {
+        let root = std::env::temp_dir().join(format!("red-index-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        for file in ["src/main.rs", "Cargo.toml"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let (indexed_root, files) = spawn_file_index(root.clone()).recv().unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(indexed_root, root);
+        assert_eq!(files, vec![root.join("Cargo.toml"), root.join("src/main.rs")]);
+    }
+    #[test]
+    fn binary_files_are_detected() {
+        assert!(is_binary(b"ab\0cd"));
+        assert!(!is_binary("caf\u{e9}".as_bytes()));
+    }
+}
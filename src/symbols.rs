@@ -0,0 +1,99 @@
+use crate::{buffer::TextBuffer, search, syntax::{Highlighter, Symbol}};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::SystemTime,
+};
+
+const MAX_INDEXED_FILE_SIZE: u64 = 2 * 1024 * 1024;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct IndexedFile {
+    modified: SystemTime,
+    symbols: Vec<Symbol>,
+}
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SymbolIndex {
+    root: PathBuf,
+    files: BTreeMap<PathBuf, IndexedFile>,
+}
+impl SymbolIndex {
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.to_path_buf(), files: BTreeMap::new() }
+    }
+    pub fn load(cache: &Path, root: &Path) -> Self {
+        fs::read(cache).ok()
+            .and_then(|bytes| bincode::deserialize::<Self>(&bytes).ok())
+            .filter(|index| index.root == root)
+            .unwrap_or_else(|| Self::new(root))
+    }
+    pub fn save(&self, cache: &Path) -> std::io::Result<()> {
+        if let Some(parent) = cache.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let bytes = bincode::serialize(self).map_err(std::io::Error::other)?;
+        fs::write(cache, bytes)
+    }
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    pub fn refresh(&mut self) -> usize {
+        let mut files = BTreeMap::new();
+        let mut changed = 0;
+        for path in search::project_files(&self.root) {
+            if !Highlighter::indexes(&path) {
+                continue;
+            }
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let Ok(modified) = metadata.modified() else {
+                continue;
+            };
+            if metadata.len() > MAX_INDEXED_FILE_SIZE {
+                continue;
+            }
+            match self.files.remove(&path) {
+                Some(file) if file.modified == modified => {
+                    files.insert(path, file);
+                }
+                _ => {
+                    changed += 1;
+                    let symbols = parse_file(&path).unwrap_or_default();
+                    files.insert(path, IndexedFile { modified, symbols });
+                }
+            }
+        }
+        changed += self.files.len();
+        self.files = files;
+        changed
+    }
+    pub fn symbols(&self) -> impl Iterator<Item = (&Path, &Symbol)> {
+        self.files.iter().flat_map(|(path, file)| file.symbols.iter().map(move |symbol| (path.as_path(), symbol)))
+    }
+}
+fn parse_file(path: &Path) -> Option<Vec<Symbol>> {
+    let mut highlighter = Highlighter::for_path(Some(path))?;
+    let bytes = fs::read(path).ok()?;
+    if search::is_binary(&bytes) {
+        return None;
+    }
+    let content = TextBuffer::from_bytes(&bytes);
+    highlighter.update(&content);
+    highlighter.symbols(&content)
+}
+pub fn spawn_refresh(mut index: SymbolIndex, cache: Option<PathBuf>) -> Receiver<(SymbolIndex, usize)> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let changed = index.refresh();
+        if let Some(cache) = cache.filter(|_| changed > 0) {
+            let _ = index.save(&cache);
+        }
+        let _ = sender.send((index, changed));
+    });
+    receiver
+}
@@ -0,0 +1,378 @@
+use crate::buffer::TextBuffer;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::BTreeMap, ops::Range, path::Path, sync::OnceLock};
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Query, QueryCursor, Tree};
+
+type GrammarSource = fn() -> (Language, String, String);
+
+const RUST_EXTRA_TAGS: &str = "
+(impl_item type: (_) @name) @definition.impl
+(function_signature_item name: (identifier) @name) @definition.function
+";
+const LANGUAGES: [(&[&str], GrammarSource); 8] = [
+    (&["rs"], || (
+        tree_sitter_rust::LANGUAGE.into(),
+        tree_sitter_rust::HIGHLIGHTS_QUERY.to_string(),
+        [tree_sitter_rust::TAGS_QUERY, RUST_EXTRA_TAGS].join("\n"),
+    )),
+    (&["py", "pyw"], || (
+        tree_sitter_python::LANGUAGE.into(),
+        tree_sitter_python::HIGHLIGHTS_QUERY.to_string(),
+        tree_sitter_python::TAGS_QUERY.to_string(),
+    )),
+    (&["js", "mjs", "cjs", "jsx"], || (
+        tree_sitter_javascript::LANGUAGE.into(),
+        [tree_sitter_javascript::HIGHLIGHT_QUERY, tree_sitter_javascript::JSX_HIGHLIGHT_QUERY].concat(),
+        tree_sitter_javascript::TAGS_QUERY.to_string(),
+    )),
+    (&["c", "h"], || (
+        tree_sitter_c::LANGUAGE.into(),
+        tree_sitter_c::HIGHLIGHT_QUERY.to_string(),
+        tree_sitter_c::TAGS_QUERY.to_string(),
+    )),
+    (&["cpp", "cc", "cxx", "hpp", "hh", "hxx"], || (
+        tree_sitter_cpp::LANGUAGE.into(),
+        [tree_sitter_c::HIGHLIGHT_QUERY, tree_sitter_cpp::HIGHLIGHT_QUERY].concat(),
+        tree_sitter_cpp::TAGS_QUERY.to_string(),
+    )),
+    (&["go"], || (
+        tree_sitter_go::LANGUAGE.into(),
+        tree_sitter_go::HIGHLIGHTS_QUERY.to_string(),
+        tree_sitter_go::TAGS_QUERY.to_string(),
+    )),
+    (&["html", "htm"], || (tree_sitter_html::LANGUAGE.into(), tree_sitter_html::HIGHLIGHTS_QUERY.to_string(), String::new())),
+    (&["css"], || (tree_sitter_css::LANGUAGE.into(), tree_sitter_css::HIGHLIGHTS_QUERY.to_string(), String::new())),
+];
+const CONTAINER_KINDS: [&str; 6] = ["class", "struct", "enum", "union", "interface", "impl"];
+const IMPORT_KINDS: [&str; 6] = [
+    "use_declaration",
+    "import_statement",
+    "import_from_statement",
+    "future_import_statement",
+    "import_declaration",
+    "preproc_include",
+];
+static GRAMMARS: [OnceLock<Option<Grammar>>; LANGUAGES.len()] = [const { OnceLock::new() }; LANGUAGES.len()];
+
+pub type ScopeRun = (usize, usize, &'static str);
+
+struct Grammar {
+    language: Language,
+    query: Query,
+    scopes: Vec<Option<&'static str>>,
+    tags: Option<Query>,
+}
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: String,
+    pub line: usize,
+    pub col: usize,
+    pub depth: usize,
+}
+pub struct Highlighter {
+    grammar: &'static Grammar,
+    parser: Parser,
+    tree: Option<Tree>,
+    revision: u64,
+}
+impl Clone for Highlighter {
+    fn clone(&self) -> Self {
+        let mut highlighter = Self::with_grammar(self.grammar).expect("grammar was loaded before");
+        highlighter.tree = self.tree.clone();
+        highlighter.revision = self.revision;
+        highlighter
+    }
+}
+impl Highlighter {
+    pub fn for_path(path: Option<&Path>) -> Option<Self> {
+        Self::with_grammar(grammar_for(path?)?)
+    }
+    fn with_grammar(grammar: &'static Grammar) -> Option<Self> {
+        let mut parser = Parser::new();
+        parser.set_language(&grammar.language).ok()?;
+        Some(Self {
+            grammar,
+            parser,
+            tree: None,
+            revision: 0,
+        })
+    }
+    pub fn handles(&self, path: Option<&Path>) -> bool {
+        path.and_then(grammar_for).is_some_and(|grammar| std::ptr::eq(grammar, self.grammar))
+    }
+    pub fn indexes(path: &Path) -> bool {
+        grammar_for(path).is_some_and(|grammar| grammar.tags.is_some())
+    }
+    pub fn update(&mut self, content: &TextBuffer) {
+        if self.tree.is_some() && self.revision == content.revision() {
+            return;
+        }
+        if let Some(tree) = self.tree.as_mut() {
+            match content.edits_since(self.revision) {
+                Some(edits) => {
+                    for edit in edits {
+                        tree.edit(&InputEdit {
+                            start_byte: edit.start_byte,
+                            old_end_byte: edit.old_end_byte,
+                            new_end_byte: edit.new_end_byte,
+                            start_position: point(edit.start),
+                            old_end_position: point(edit.old_end),
+                            new_end_position: point(edit.new_end),
+                        });
+                    }
+                }
+                None => self.tree = None,
+            }
+        }
+        self.tree = self.parser.parse_with(&mut |byte, _| content.chunk_at_byte(byte), self.tree.as_ref());
+        self.revision = content.revision();
+    }
+    pub fn highlight_lines(&self, content: &TextBuffer, lines: Range<usize>) -> Vec<Vec<ScopeRun>> {
+        let Some(tree) = self.tree.as_ref().filter(|_| self.revision == content.revision()) else {
+            return Vec::new();
+        };
+        let lines = lines.start.min(content.len_lines())..lines.end.min(content.len_lines());
+        let start_byte = content.line_to_byte(lines.start);
+        let end_byte = content.line_to_byte(lines.end);
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(start_byte..end_byte);
+        let text = |node: Node| content.byte_chunks(node.byte_range()).map(str::as_bytes);
+        let mut captures = cursor.captures(&self.grammar.query, tree.root_node(), text);
+        let mut spans = Vec::new();
+        while let Some((found, index)) = captures.next() {
+            let capture = found.captures[*index];
+            if let Some(scope) = self.grammar.scopes[capture.index as usize] {
+                let range = capture.node.byte_range();
+                let (start, end) = (range.start.max(start_byte), range.end.min(end_byte));
+                if start < end {
+                    spans.push((start, end, found.pattern_index, scope));
+                }
+            }
+        }
+        spans.sort_by_key(|&(start, end, pattern, _)| (start, Reverse(end), Reverse(pattern)));
+        let mut painted: Vec<Option<&'static str>> = vec![None; end_byte - start_byte];
+        for (start, end, _, scope) in spans {
+            painted[start - start_byte..end - start_byte].fill(Some(scope));
+        }
+        lines
+            .map(|line_idx| {
+                let offset = content.line_to_byte(line_idx) - start_byte;
+                let mut runs: Vec<ScopeRun> = Vec::new();
+                for (col, (byte, _)) in content.line(line_idx).char_indices().enumerate() {
+                    let Some(scope) = painted[offset + byte] else {
+                        continue;
+                    };
+                    match runs.last_mut() {
+                        Some(run) if run.1 == col && run.2 == scope => run.1 += 1,
+                        _ => runs.push((col, col + 1, scope)),
+                    }
+                }
+                runs
+            })
+            .collect()
+    }
+    pub fn fold_ranges(&self) -> Option<Vec<(usize, usize)>> {
+        let tree = self.tree.as_ref()?;
+        let mut ends = BTreeMap::new();
+        let mut cursor = tree.walk();
+        'walk: loop {
+            let node = cursor.node();
+            if node.is_named() && node.parent().is_some() {
+                add_fold(&mut ends, node.start_position().row, last_row(node));
+            }
+            add_sibling_runs(&mut ends, node);
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    break 'walk;
+                }
+            }
+        }
+        Some(ends.into_iter().collect())
+    }
+    pub fn expand_range(&self, range: Range<usize>) -> Option<Range<usize>> {
+        let mut node = self.tree.as_ref()?.root_node().descendant_for_byte_range(range.start, range.end)?;
+        loop {
+            let node_range = node.byte_range();
+            if node.is_named() && node_range != range && node_range.start <= range.start && node_range.end >= range.end {
+                return Some(node_range);
+            }
+            node = node.parent()?;
+        }
+    }
+    pub fn sibling_start(&self, range: Range<usize>, forward: bool) -> Option<usize> {
+        let node = self.node_at(range)?;
+        let sibling = if forward { node.next_named_sibling() } else { node.prev_named_sibling() };
+        Some(sibling?.start_byte())
+    }
+    pub fn parent_start(&self, range: Range<usize>) -> Option<usize> {
+        let parent = self.node_at(range)?.parent().filter(|parent| parent.parent().is_some())?;
+        Some(parent.start_byte())
+    }
+    pub fn symbols(&self, content: &TextBuffer) -> Option<Vec<Symbol>> {
+        let tree = self.tree.as_ref().filter(|_| self.revision == content.revision())?;
+        let query: &'static Query = self.grammar.tags.as_ref()?;
+        let mut cursor = QueryCursor::new();
+        let text = |node: Node| content.byte_chunks(node.byte_range()).map(str::as_bytes);
+        let mut matches = cursor.matches(query, tree.root_node(), text);
+        let mut definitions: Vec<(Node, Node, &'static str)> = Vec::new();
+        while let Some(found) = matches.next() {
+            let mut name = None;
+            let mut definition = None;
+            for capture in found.captures {
+                let capture_name = query.capture_names()[capture.index as usize];
+                if capture_name == "name" {
+                    name = Some(capture.node);
+                } else if let Some(kind) = capture_name.strip_prefix("definition.") {
+                    definition = Some((capture.node, kind));
+                }
+            }
+            if let (Some(name), Some((node, kind))) = (name, definition) {
+                definitions.push((name, node, kind));
+            }
+        }
+        definitions.sort_by_key(|(name, node, _)| (name.start_byte(), node.end_byte() - node.start_byte()));
+        definitions.dedup_by_key(|(name, _, _)| name.start_byte());
+        definitions.sort_by_key(|(_, node, _)| (node.start_byte(), Reverse(node.end_byte())));
+        let mut parents: Vec<(usize, &'static str)> = Vec::new();
+        let mut symbols = Vec::new();
+        for (name, node, kind) in definitions {
+            while parents.last().is_some_and(|&(end, _)| end <= node.start_byte()) {
+                parents.pop();
+            }
+            let kind = match (kind, node.kind()) {
+                ("class", node_kind) if node_kind.starts_with("struct") => "struct",
+                ("class", node_kind) if node_kind.starts_with("enum") => "enum",
+                ("class", node_kind) if node_kind.starts_with("union") => "union",
+                ("class", "type_item") => "type",
+                ("function", _) if parents.last().is_some_and(|(_, parent)| CONTAINER_KINDS.contains(parent)) => "method",
+                (kind, _) => kind,
+            };
+            let name_text = if kind == "impl" {
+                let header_end = node.child_by_field_name("body").map_or(node.end_byte(), |body| body.start_byte());
+                content.byte_chunks(node.start_byte()..header_end).collect::<String>()
+            } else {
+                content.byte_chunks(name.byte_range()).collect::<String>()
+            };
+            let (col, line) = content.byte_position(name.start_byte());
+            symbols.push(Symbol {
+                name: name_text.split_whitespace().collect::<Vec<_>>().join(" "),
+                kind: kind.to_string(),
+                line,
+                col,
+                depth: parents.len(),
+            });
+            parents.push((node.end_byte(), kind));
+        }
+        Some(symbols)
+    }
+    fn node_at(&self, range: Range<usize>) -> Option<Node<'_>> {
+        let mut node = self.tree.as_ref()?.root_node().named_descendant_for_byte_range(range.start, range.end)?;
+        if range.is_empty() {
+            while let Some(parent) = node.parent().filter(|parent| parent.parent().is_some()) {
+                if parent.start_byte() != node.start_byte() {
+                    break;
+                }
+                node = parent;
+            }
+        }
+        node.parent().is_some().then_some(node)
+    }
+}
+fn last_row(node: Node) -> usize {
+    let end = node.end_position();
+    if end.column == 0 && end.row > node.start_position().row {
+        end.row - 1
+    } else {
+        end.row
+    }
+}
+fn add_fold(ends: &mut BTreeMap<usize, usize>, start: usize, end: usize) {
+    if end > start {
+        let fold_end = ends.entry(start).or_insert(end);
+        *fold_end = (*fold_end).max(end);
+    }
+}
+fn add_sibling_runs(ends: &mut BTreeMap<usize, usize>, node: Node) {
+    let mut cursor = node.walk();
+    let mut run: Option<(&str, usize, usize)> = None;
+    for child in node.named_children(&mut cursor) {
+        let kind = if child.kind().contains("comment") {
+            Some("comment")
+        } else {
+            IMPORT_KINDS.contains(&child.kind()).then_some("import")
+        };
+        let (start, end) = (child.start_position().row, last_row(child));
+        match (&mut run, kind) {
+            (Some((run_kind, _, run_end)), Some(kind)) if *run_kind == kind && start == *run_end + 1 => *run_end = end,
+            _ => {
+                if let Some((_, run_start, run_end)) = run.take() {
+                    add_fold(ends, run_start, run_end);
+                }
+                run = kind.map(|kind| (kind, start, end));
+            }
+        }
+    }
+    if let Some((_, run_start, run_end)) = run {
+        add_fold(ends, run_start, run_end);
+    }
+}
+fn grammar_for(path: &Path) -> Option<&'static Grammar> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let index = LANGUAGES.iter().position(|(extensions, _)| extensions.contains(&extension.as_str()))?;
+    GRAMMARS[index]
+        .get_or_init(|| {
+            let (language, source, tags) = (LANGUAGES[index].1)();
+            let query = Query::new(&language, &source).ok()?;
+            let scopes = query.capture_names().iter()
+                .map(|name| if name.starts_with('_') { None } else { scope_for(name) })
+                .collect();
+            let tags = (!tags.is_empty()).then(|| Query::new(&language, &tags).ok()).flatten();
+            Some(Grammar { language, query, scopes, tags })
+        })
+        .as_ref()
+}
+fn point((column, row): (usize, usize)) -> Point {
+    Point::new(row, column)
+}
+fn scope_for(capture: &str) -> Option<&'static str> {
+    let mut name = capture;
+    loop {
+        let scope = match name {
+            "keyword" | "import" | "media" | "keyframes" | "supports" | "charset" => "keyword",
+            "operator" => "keyword.operator",
+            "string" => "string",
+            "string.special" => "string.regexp",
+            "escape" | "string.escape" => "constant.character.escape",
+            "comment" => "comment",
+            "function" | "function.method" => "entity.name.function",
+            "function.builtin" | "function.macro" => "support.function",
+            "type" | "constructor" => "entity.name.type",
+            "type.builtin" => "storage.type",
+            "number" => "constant.numeric",
+            "constant" => "constant.other",
+            "constant.builtin" => "constant.language",
+            "variable.builtin" => "variable.language",
+            "variable.parameter" => "variable.parameter",
+            "property" => "variable.other.member",
+            "attribute" => "entity.other.attribute-name",
+            "tag" => "entity.name.tag",
+            "tag.error" => "invalid",
+            "label" => "entity.name.label",
+            "namespace" | "module" => "entity.name.namespace",
+            "punctuation" | "delimiter" => "punctuation",
+            _ => match name.rsplit_once('.') {
+                Some((parent, _)) => {
+                    name = parent;
+                    continue;
+                }
+                None => return None,
+            },
+        };
+        return Some(scope);
+    }
+}
@@ -0,0 +1,327 @@
+use crate::buffer::{LineEnding, TextBuffer};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+const MAX_UNDO_NODES: usize = 10000;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MultiLineDelta {
+    pub start_line: usize,
+    pub old_lines: Vec<String>,
+    pub new_lines: Vec<String>,
+    pub cursor_before: (usize, usize),
+    pub cursor_after: (usize, usize),
+    pub line_endings: Option<((LineEnding, bool), (LineEnding, bool))>,
+}
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UndoNode {
+    pub parent: usize,
+    pub children: Vec<usize>,
+    pub redo_child: Option<usize>,
+    pub delta: Option<MultiLineDelta>,
+    pub timestamp: SystemTime,
+}
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UndoTree {
+    pub nodes: Vec<UndoNode>,
+    pub current: usize,
+    #[serde(skip)]
+    saved: Option<usize>,
+}
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self {
+            nodes: vec![UndoNode {
+                parent: 0,
+                children: Vec::new(),
+                redo_child: None,
+                delta: None,
+                timestamp: SystemTime::now(),
+            }],
+            current: 0,
+            saved: Some(0),
+        }
+    }
+}
+impl UndoTree {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.current);
+    }
+    pub fn forget_saved(&mut self) {
+        self.saved = None;
+    }
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.current)
+    }
+    pub fn push(&mut self, delta: MultiLineDelta) {
+        let index = self.nodes.len();
+        self.nodes.push(UndoNode {
+            parent: self.current,
+            children: Vec::new(),
+            redo_child: None,
+            delta: Some(delta),
+            timestamp: SystemTime::now(),
+        });
+        self.nodes[self.current].children.push(index);
+        self.nodes[self.current].redo_child = Some(index);
+        self.current = index;
+        if self.nodes.len() > MAX_UNDO_NODES {
+            self.trim(MAX_UNDO_NODES as u64 * 3 / 4, |_| 1);
+        }
+    }
+    pub fn undo(&mut self, content: &mut TextBuffer) -> Option<(usize, usize)> {
+        let node = &self.nodes[self.current];
+        let delta = node.delta.as_ref()?;
+        content.splice_lines(delta.start_line, &delta.new_lines, &delta.old_lines);
+        if let Some((before, _)) = delta.line_endings {
+            content.set_line_endings(before);
+        }
+        let cursor = delta.cursor_before;
+        let parent = node.parent;
+        self.nodes[parent].redo_child = Some(self.current);
+        self.current = parent;
+        Some(cursor)
+    }
+    pub fn redo(&mut self, content: &mut TextBuffer) -> Option<(usize, usize)> {
+        let child = self.nodes[self.current].redo_child?;
+        let delta = self.nodes[child].delta.as_ref()?;
+        content.splice_lines(delta.start_line, &delta.old_lines, &delta.new_lines);
+        if let Some((_, after)) = delta.line_endings {
+            content.set_line_endings(after);
+        }
+        self.current = child;
+        Some(delta.cursor_after)
+    }
+    pub fn jump_to(&mut self, target: usize, content: &mut TextBuffer) -> Option<(usize, usize)> {
+        if target >= self.nodes.len() || target == self.current {
+            return None;
+        }
+        let path = self.ancestors(target);
+        let mut on_path = vec![false; self.nodes.len()];
+        for &node in &path {
+            on_path[node] = true;
+        }
+        let mut cursor = None;
+        while !on_path[self.current] {
+            cursor = self.undo(content).or(cursor);
+        }
+        let depth = path.iter().position(|&node| node == self.current).unwrap_or(0);
+        for &node in path[..depth].iter().rev() {
+            self.nodes[self.current].redo_child = Some(node);
+            cursor = self.redo(content).or(cursor);
+        }
+        cursor
+    }
+    pub fn node_at_time(&self, time: SystemTime, older: bool) -> usize {
+        if older {
+            self.nodes.iter().rposition(|node| node.timestamp <= time).unwrap_or(0)
+        } else {
+            self.nodes.iter().position(|node| node.timestamp >= time).unwrap_or(self.nodes.len() - 1)
+        }
+    }
+    pub fn rows(&self) -> Vec<(usize, usize)> {
+        let mut rows = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![(0, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            rows.push((node, depth));
+            for (i, &child) in self.nodes[node].children.iter().enumerate().rev() {
+                stack.push((child, depth + usize::from(i > 0)));
+            }
+        }
+        rows
+    }
+    pub fn trim(&mut self, limit: u64, cost: impl Fn(&UndoNode) -> u64) {
+        let mut totals: Vec<u64> = self.nodes.iter().map(&cost).collect();
+        for index in (1..self.nodes.len()).rev() {
+            totals[self.nodes[index].parent] += totals[index];
+        }
+        let mut path = self.ancestors(self.current);
+        path.reverse();
+        let root = path.iter().copied().find(|&node| totals[node] <= limit).unwrap_or(self.current);
+        if root == 0 {
+            return;
+        }
+        let mut mapping = vec![None; self.nodes.len()];
+        let mut nodes = Vec::new();
+        for index in root..self.nodes.len() {
+            let parent = self.nodes[index].parent;
+            if index != root && mapping[parent].is_none() {
+                continue;
+            }
+            mapping[index] = Some(nodes.len());
+            nodes.push(self.nodes[index].clone());
+        }
+        for node in &mut nodes {
+            node.parent = mapping[node.parent].unwrap_or(0);
+            node.children = node.children.iter().filter_map(|&child| mapping[child]).collect();
+            node.redo_child = node.redo_child.and_then(|child| mapping[child]);
+        }
+        nodes[0].parent = 0;
+        nodes[0].delta = None;
+        self.nodes = nodes;
+        self.current = mapping[self.current].unwrap_or(0);
+        self.saved = self.saved.and_then(|saved| mapping[saved]);
+    }
+    fn ancestors(&self, mut node: usize) -> Vec<usize> {
+        let mut path = vec![node];
+        while node != 0 {
+            node = self.nodes[node].parent;
+            path.push(node);
+        }
+        path
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(tree: &mut UndoTree, content: &mut TextBuffer, change: impl FnOnce(&mut TextBuffer)) {
+        let base = content.clone();
+        change(content);
+        let (start_line, old_lines, new_lines) = content.changes_since(&base).unwrap();
+        tree.push(MultiLineDelta {
+            start_line,
+            old_lines,
+            new_lines,
+            cursor_before: (0, start_line),
+            cursor_after: (0, start_line),
+            line_endings: None,
+        });
+    }
+    fn append(tree: &mut UndoTree, content: &mut TextBuffer, text: &str) {
+        edit(tree, content, |content| {
+            let end = content.len_lines() - 1;
+            content.insert((content.line_len(end), end), text);
+        });
+    }
+
+    #[test]
+    fn undo_and_redo_restore_text() {
+        let mut tree = UndoTree::default();
+        let mut content = TextBuffer::from_text("a");
+        append(&mut tree, &mut content, "\nb");
+        append(&mut tree, &mut content, "c");
+        assert!(tree.undo(&mut content).is_some());
+        assert_eq!(content.text(), "a\nb");
+        assert!(tree.undo(&mut content).is_some());
+        assert_eq!(content.text(), "a");
+        assert!(tree.undo(&mut content).is_none());
+        assert!(tree.redo(&mut content).is_some());
+        assert!(tree.redo(&mut content).is_some());
+        assert_eq!(content.text(), "a\nbc");
+        assert!(tree.redo(&mut content).is_none());
+    }
+    #[test]
+    fn redo_follows_latest_branch() {
+        let mut tree = UndoTree::default();
+        let mut content = TextBuffer::from_text("a");
+        append(&mut tree, &mut content, "b");
+        tree.undo(&mut content);
+        append(&mut tree, &mut content, "c");
+        tree.undo(&mut content);
+        tree.redo(&mut content);
+        assert_eq!(content.text(), "ac");
+        assert_eq!(tree.nodes[0].children, vec![1, 2]);
+    }
+    #[test]
+    fn jump_to_crosses_branches() {
+        let mut tree = UndoTree::default();
+        let mut content = TextBuffer::from_text("x");
+        append(&mut tree, &mut content, "\none");
+        append(&mut tree, &mut content, "\ntwo");
+        tree.undo(&mut content);
+        tree.undo(&mut content);
+        append(&mut tree, &mut content, "\nother");
+        assert_eq!(tree.jump_to(2, &mut content), Some((0, 1)));
+        assert_eq!(content.text(), "x\none\ntwo");
+        assert_eq!(tree.current, 2);
+        assert!(tree.jump_to(3, &mut content).is_some());
+        assert_eq!(content.text(), "x\nother");
+        assert!(tree.jump_to(3, &mut content).is_none());
+        assert!(tree.jump_to(tree.nodes.len(), &mut content).is_none());
+    }
+    #[test]
+    fn saved_state_follows_current_node() {
+        let mut tree = UndoTree::default();
+        let mut content = TextBuffer::from_text("a");
+        assert!(tree.is_saved());
+        append(&mut tree, &mut content, "b");
+        assert!(!tree.is_saved());
+        tree.undo(&mut content);
+        assert!(tree.is_saved());
+        tree.redo(&mut content);
+        tree.mark_saved();
+        tree.undo(&mut content);
+        assert!(!tree.is_saved());
+        tree.forget_saved();
+        tree.redo(&mut content);
+        assert!(!tree.is_saved());
+    }
+    #[test]
+    fn line_ending_changes_are_undone() {
+        let mut tree = UndoTree::default();
+        let mut content = TextBuffer::from_bytes(b"one\r\ntwo\nthree\r\n");
+        let base = content.clone();
+        content.unify_line_endings();
+        let (start_line, old_lines, new_lines) = content.changes_since(&base).unwrap();
+        tree.push(MultiLineDelta {
+            start_line,
+            old_lines,
+            new_lines,
+            cursor_before: (0, 0),
+            cursor_after: (0, 0),
+            line_endings: Some((base.line_endings(), content.line_endings())),
+        });
+        assert!(!tree.is_saved());
+        tree.undo(&mut content);
+        assert!(tree.is_saved());
+        assert!(content == base);
+        assert_eq!(content.text(), base.text());
+        tree.redo(&mut content);
+        assert!(!content.mixed_line_endings);
+        assert!(content.line_ending == LineEnding::CrLf);
+        assert_eq!(content.text(), "one\ntwo\nthree");
+    }
+    #[test]
+    fn trim_keeps_recent_history() {
+        let mut tree = UndoTree::default();
+        let mut content = TextBuffer::from_text("0");
+        for digit in 1..=5 {
+            append(&mut tree, &mut content, &digit.to_string());
+            if digit == 4 {
+                tree.mark_saved();
+            }
+        }
+        tree.trim(2, |_| 1);
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(tree.current, 1);
+        assert!(tree.nodes[0].delta.is_none());
+        assert!(tree.undo(&mut content).is_some());
+        assert!(tree.is_saved());
+        assert_eq!(content.text(), "01234");
+        assert!(tree.undo(&mut content).is_none());
+        assert!(tree.redo(&mut content).is_some());
+        assert_eq!(content.text(), "012345");
+    }
+    #[test]
+    fn trim_drops_other_branches() {
+        let mut tree = UndoTree::default();
+        let mut content = TextBuffer::from_text("");
+        append(&mut tree, &mut content, "a");
+        append(&mut tree, &mut content, "b");
+        tree.undo(&mut content);
+        tree.undo(&mut content);
+        tree.mark_saved();
+        append(&mut tree, &mut content, "c");
+        append(&mut tree, &mut content, "d");
+        tree.trim(2, |_| 1);
+        assert_eq!(tree.nodes.len(), 2);
+        assert!(!tree.is_saved());
+        assert!(tree.nodes.iter().all(|node| node.children.iter().all(|&child| child < tree.nodes.len())));
+        tree.undo(&mut content);
+        assert_eq!(content.text(), "c");
+    }
+}